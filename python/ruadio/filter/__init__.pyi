@@ -56,7 +56,8 @@ class SincInterpDelay:
             `sinc_half_width` must not be greater than `delay + 0.5`.
         window_type : Optional[str]
             The window function to use for the sinc filter. The available window functions include
-            'hamming' and 'hann', and the default is Hamming window.
+            'rectangular', 'hamming', 'hann', 'blackman', 'blackmanharris' and 'flattop', and the
+            default is Hamming window. A ValueError is raised for an unknown name.
         '''
         ...
 
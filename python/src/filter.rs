@@ -6,11 +6,13 @@ use numpy::{
     ToPyArray,
 };
 
+use pyo3::exceptions::PyValueError;
 use ruadio::filter::{
     Filter,
     LinearInterpDelay,
     SincInterpDelay,
 };
+use ruadio::filter::design::window::Window;
 
 use crate::utilities::convert_to_f32_array;
 
@@ -59,8 +61,12 @@ pub mod py_filter {
     impl PySincInterpDelay {
         #[new]
         #[pyo3(signature = (delay, sinc_half_width=None, window_type=None))]
-        fn new(delay: f32, sinc_half_width: Option<usize>, window_type: Option<&str>) -> Self {
-            Self { filter: SincInterpDelay::new(delay, sinc_half_width, window_type) }
+        fn new(delay: f32, sinc_half_width: Option<usize>, window_type: Option<&str>) -> PyResult<Self> {
+            let window = window_type
+                .map(|name| name.parse::<Window>())
+                .transpose()
+                .map_err(|err| PyValueError::new_err(err.to_string()))?;
+            Ok(Self { filter: SincInterpDelay::new(delay, sinc_half_width, window) })
         }
 
         fn process<'py>(&mut self, py: Python<'py>, input: Bound<'py, PyAny>) -> PyResult<Bound<'py, PyArray1<f32>>> {
@@ -1,3 +1,6 @@
+// The `#[pymethods]` expansion of PyO3 0.23 triggers this lint on every `PyResult` return type
+#![allow(clippy::useless_conversion)]
+
 use pyo3::prelude::*;
 
 mod filter;
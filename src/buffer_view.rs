@@ -26,7 +26,7 @@ impl<'outer, 'inner> BufferView<'outer, 'inner> {
     pub fn new(channels: &'outer [&'inner [f32]]) -> Self {
         Self {
             channels,
-            num_samples: channels.get(0).map_or(0, |ch| ch.len()),
+            num_samples: channels.first().map_or(0, |ch| ch.len()),
         }
     }
 
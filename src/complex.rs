@@ -0,0 +1,234 @@
+//! A minimal complex number type for filter design, frequency response evaluation and FFT.
+//!
+//! Only the operations needed by this crate are implemented. [`Complex<f64>`] is used for filter
+//! design and analysis where the precision matters, and [`Complex<f32>`] is used for real-time
+//! processing.
+
+use std::ops::{Add, AddAssign, Div, Mul, MulAssign, Neg, Sub, SubAssign};
+
+/// A complex number in Cartesian form.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Complex<T> {
+    pub re: T,
+    pub im: T,
+}
+
+impl<T> Complex<T> {
+    pub const fn new(re: T, im: T) -> Self {
+        Self { re, im }
+    }
+}
+
+macro_rules! impl_complex {
+    ($t:ty) => {
+        impl Complex<$t> {
+            pub const ZERO: Self = Self::new(0.0, 0.0);
+            pub const ONE: Self = Self::new(1.0, 0.0);
+            pub const I: Self = Self::new(0.0, 1.0);
+
+            /// Create a complex number from its magnitude and phase.
+            pub fn from_polar(r: $t, theta: $t) -> Self {
+                Self::new(r * theta.cos(), r * theta.sin())
+            }
+
+            /// `exp(j * theta)`, i.e. the unit phasor with the phase `theta`.
+            pub fn cis(theta: $t) -> Self {
+                Self::new(theta.cos(), theta.sin())
+            }
+
+            pub fn conj(self) -> Self {
+                Self::new(self.re, -self.im)
+            }
+
+            /// The squared magnitude.
+            pub fn norm_sqr(self) -> $t {
+                self.re * self.re + self.im * self.im
+            }
+
+            /// The magnitude.
+            pub fn norm(self) -> $t {
+                self.re.hypot(self.im)
+            }
+
+            /// The phase in radians, in the range `[-pi, pi]`.
+            pub fn arg(self) -> $t {
+                self.im.atan2(self.re)
+            }
+
+            /// The reciprocal `1 / self`.
+            pub fn inv(self) -> Self {
+                let d = self.norm_sqr();
+                Self::new(self.re / d, -self.im / d)
+            }
+
+            /// The principal square root.
+            pub fn sqrt(self) -> Self {
+                Self::from_polar(self.norm().sqrt(), self.arg() / 2.0)
+            }
+
+            pub fn exp(self) -> Self {
+                Self::from_polar(self.re.exp(), self.im)
+            }
+
+            /// The principal natural logarithm.
+            pub fn ln(self) -> Self {
+                Self::new(self.norm().ln(), self.arg())
+            }
+
+            pub fn sin(self) -> Self {
+                Self::new(self.re.sin() * self.im.cosh(), self.re.cos() * self.im.sinh())
+            }
+
+            pub fn cos(self) -> Self {
+                Self::new(self.re.cos() * self.im.cosh(), -self.re.sin() * self.im.sinh())
+            }
+
+            pub fn sinh(self) -> Self {
+                Self::new(self.re.sinh() * self.im.cos(), self.re.cosh() * self.im.sin())
+            }
+
+            /// The principal inverse cosine, `-j * ln(z + j * sqrt(1 - z^2))`.
+            pub fn acos(self) -> Self {
+                let root = (Self::ONE - self * self).sqrt();
+                -Self::I * (self + Self::I * root).ln()
+            }
+
+            /// The principal inverse hyperbolic sine, `ln(z + sqrt(z^2 + 1))`.
+            pub fn asinh(self) -> Self {
+                (self + (self * self + Self::ONE).sqrt()).ln()
+            }
+        }
+
+        impl From<$t> for Complex<$t> {
+            fn from(re: $t) -> Self {
+                Self::new(re, 0.0)
+            }
+        }
+
+        impl Add for Complex<$t> {
+            type Output = Self;
+            fn add(self, rhs: Self) -> Self {
+                Self::new(self.re + rhs.re, self.im + rhs.im)
+            }
+        }
+
+        impl Add<$t> for Complex<$t> {
+            type Output = Self;
+            fn add(self, rhs: $t) -> Self {
+                Self::new(self.re + rhs, self.im)
+            }
+        }
+
+        impl Sub for Complex<$t> {
+            type Output = Self;
+            fn sub(self, rhs: Self) -> Self {
+                Self::new(self.re - rhs.re, self.im - rhs.im)
+            }
+        }
+
+        impl Sub<$t> for Complex<$t> {
+            type Output = Self;
+            fn sub(self, rhs: $t) -> Self {
+                Self::new(self.re - rhs, self.im)
+            }
+        }
+
+        impl Mul for Complex<$t> {
+            type Output = Self;
+            fn mul(self, rhs: Self) -> Self {
+                Self::new(
+                    self.re * rhs.re - self.im * rhs.im,
+                    self.re * rhs.im + self.im * rhs.re,
+                )
+            }
+        }
+
+        impl Mul<$t> for Complex<$t> {
+            type Output = Self;
+            fn mul(self, rhs: $t) -> Self {
+                Self::new(self.re * rhs, self.im * rhs)
+            }
+        }
+
+        impl Div for Complex<$t> {
+            type Output = Self;
+            #[allow(clippy::suspicious_arithmetic_impl)]
+            fn div(self, rhs: Self) -> Self {
+                self * rhs.inv()
+            }
+        }
+
+        impl Div<$t> for Complex<$t> {
+            type Output = Self;
+            fn div(self, rhs: $t) -> Self {
+                Self::new(self.re / rhs, self.im / rhs)
+            }
+        }
+
+        impl Neg for Complex<$t> {
+            type Output = Self;
+            fn neg(self) -> Self {
+                Self::new(-self.re, -self.im)
+            }
+        }
+
+        impl AddAssign for Complex<$t> {
+            fn add_assign(&mut self, rhs: Self) {
+                *self = *self + rhs;
+            }
+        }
+
+        impl SubAssign for Complex<$t> {
+            fn sub_assign(&mut self, rhs: Self) {
+                *self = *self - rhs;
+            }
+        }
+
+        impl MulAssign for Complex<$t> {
+            fn mul_assign(&mut self, rhs: Self) {
+                *self = *self * rhs;
+            }
+        }
+
+        impl MulAssign<$t> for Complex<$t> {
+            fn mul_assign(&mut self, rhs: $t) {
+                *self = *self * rhs;
+            }
+        }
+    };
+}
+
+impl_complex!(f32);
+impl_complex!(f64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    fn assert_complex_eq(a: Complex<f64>, b: Complex<f64>) {
+        assert_abs_diff_eq!(a.re, b.re, epsilon = 1e-12);
+        assert_abs_diff_eq!(a.im, b.im, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn arithmetic() {
+        let a: Complex<f64> = Complex::new(1.0, 2.0);
+        let b: Complex<f64> = Complex::new(-3.0, 0.5);
+        assert_complex_eq(a + b, Complex::new(-2.0, 2.5));
+        assert_complex_eq(a - b, Complex::new(4.0, 1.5));
+        assert_complex_eq(a * b, Complex::new(-4.0, -5.5));
+        assert_complex_eq(a / b * b, a);
+        assert_complex_eq(a * a.inv(), Complex::<f64>::ONE);
+    }
+
+    #[test]
+    fn elementary_functions() {
+        let z: Complex<f64> = Complex::new(0.3, -0.7);
+        assert_complex_eq(z.sqrt() * z.sqrt(), z);
+        assert_complex_eq(z.ln().exp(), z);
+        assert_complex_eq(z.acos().cos(), z);
+        assert_complex_eq(z.asinh().sinh(), z);
+        assert_complex_eq(Complex::<f64>::cis(std::f64::consts::FRAC_PI_2), Complex::<f64>::I);
+    }
+}
@@ -50,7 +50,7 @@ impl Effect for DigitalDelay {
         // Update the dependent parameters
         self.sample_rate_per_ms = sample_rate / 1000.0;
         self.delay_samples = self.delay_time * self.sample_rate_per_ms;
-        self.smoothing_factor = (-1.0 * DELAY_TIME_SMOOTHING * self.sample_rate_per_ms)
+        self.smoothing_factor = (-DELAY_TIME_SMOOTHING * self.sample_rate_per_ms)
             .recip()
             .exp();
         self.smoothed_delay_samples = self.delay_samples;
@@ -161,12 +161,74 @@ impl DigitalDelay {
     }
 }
 
+/// A delay line with fractional (linearly interpolated) reads, for building effects like
+/// reverbs and modulated delays.
+///
+/// Unlike [`DigitalDelay`], which interpolates when it writes into its delay lines, this line
+/// stores the samples as they are and interpolates when it reads, so several taps with
+/// different, modulated delays can read the same line.
+///
+/// The length of the buffer is a power of 2 that can hold at least the maximum delay.
+pub(crate) struct DelayLine {
+    buffer: Vec<f32>,
+    /// Index of the next sample to be written to the buffer.
+    write_index: usize,
+}
+
+impl DelayLine {
+    /// Create a delay line that supports delays up to `max_delay` samples.
+    pub(crate) fn new(max_delay: usize) -> Self {
+        Self {
+            buffer: vec![0.0; (max_delay + 2).next_power_of_two()],
+            write_index: 0,
+        }
+    }
+
+    /// Push a new sample into the delay line.
+    pub(crate) fn push(&mut self, x: f32) {
+        let mask = self.buffer.len() - 1;
+        self.buffer[self.write_index] = x;
+        self.write_index = (self.write_index + 1) & mask;
+    }
+
+    /// Read the sample `delay` samples before the next sample to be pushed, i.e. `read(1.0)` is
+    /// the most recently pushed sample. Non-integer delays are linearly interpolated.
+    pub(crate) fn read(&self, delay: f32) -> f32 {
+        debug_assert!(delay >= 1.0 && delay < self.buffer.len() as f32);
+        let mask = self.buffer.len() - 1;
+        let delay_int = delay.floor() as usize;
+        let delay_frac = delay - delay_int as f32;
+        let y1 = self.buffer[self.write_index.wrapping_sub(delay_int) & mask];
+        let y2 = self.buffer[self.write_index.wrapping_sub(delay_int + 1) & mask];
+        y1 + (y2 - y1) * delay_frac
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.buffer.fill(0.0);
+        self.write_index = 0;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use approx::assert_relative_eq;
     use crate::assert_all_close;
 
+    #[test]
+    fn test_delay_line() {
+        let mut line = DelayLine::new(4);
+        for x in [1.0, 2.0, 3.0, 4.0] {
+            line.push(x);
+        }
+        assert_eq!(line.read(1.0), 4.0);
+        assert_eq!(line.read(4.0), 1.0);
+        assert_relative_eq!(line.read(2.25), 2.75);
+
+        line.reset();
+        assert_eq!(line.read(1.0), 0.0);
+    }
+
     #[test]
     fn test_new_delay() {
         let delay = DigitalDelay::new(2);
@@ -244,8 +306,8 @@ mod tests {
 
         // First samples should be zero (dry is 0)
         let expected_delay: usize = 48 * 11;
-        for i in 0..expected_delay {
-            assert!(buffer[i].abs() < 1e-6, "Expected buffer[{}]: {} to be zero", i, buffer[i]);
+        for (i, &y) in buffer.iter().enumerate().take(expected_delay) {
+            assert!(y.abs() < 1e-6, "Expected buffer[{}]: {} to be zero", i, y);
         }
 
         // After delay_time, we should see the signal
@@ -274,13 +336,13 @@ mod tests {
         // Check the impulse and its echoes
         let expected_delay: usize = 48 * delay_time as usize;
         let mut echo_count = 0;
-        for i in 0..buffer.len() {
+        for (i, &y) in buffer.iter().enumerate() {
             if i % expected_delay > 0 || i == 0 {
-                assert!(buffer[i].abs() < 1e-6, "Expected buffer[{}]: {} to be zero", i, buffer[i]);
+                assert!(y.abs() < 1e-6, "Expected buffer[{}]: {} to be zero", i, y);
             }
             else {
                 let amplitude: f32 = feedback.powi(echo_count);
-                assert!((buffer[i] - amplitude).abs() < 1e-6, "Expected buffer[{}]: {} to be {}", i, buffer[i], amplitude);
+                assert!((y - amplitude).abs() < 1e-6, "Expected buffer[{}]: {} to be {}", i, y, amplitude);
                 echo_count += 1;
             }
         }
@@ -0,0 +1,606 @@
+//! Downward compressor.
+
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use crate::buffer_view::{BufferView, BufferViewMut};
+use crate::effects::Effect;
+use crate::filter::{Filter, SosFilter};
+use crate::filter::design::{SecondOrderSection, SosCoeffs};
+use crate::filter::design::biquad::{self, Width};
+use super::detector::{Detector, LevelDetector, MAX_RMS_WINDOW};
+use super::meter::GainReductionMeter;
+use super::{level_db, smooth_gain, time_constant_coeff};
+
+/// Where the level detector of a [`Compressor`] takes its signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Topology {
+    /// The detector listens to the input. The static curve is exactly the one set by the
+    /// threshold and the ratio.
+    #[default]
+    FeedForward,
+    /// The detector listens to the compressed output of the previous sample, like many vintage
+    /// compressors. The compression is softer: the effective ratio is larger than the set one.
+    Feedback,
+}
+
+/// The domain where a [`Compressor`] smooths its gain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GainSmoothing {
+    /// The gain is smoothed in dB, so the attack and release curves are linear in dB.
+    #[default]
+    Log,
+    /// The gain is smoothed as a linear amplitude, which releases faster at the beginning and
+    /// slower at the end.
+    Linear,
+}
+
+/// The filter of the sidechain of a [`Compressor`], which shapes the signal seen by the detector.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SidechainFilter {
+    #[default]
+    Off,
+    /// Highpass filter with the cutoff frequency in Hz, e.g. to keep the bass from pumping the
+    /// compressor.
+    HighPass(f32),
+    /// Bandpass filter with the center frequency in Hz and the bandwidth in octaves, e.g. to key
+    /// the compressor on a frequency band.
+    BandPass(f32, f32),
+}
+
+/// How the channels in a linking group of a [`Compressor`] combine their gains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LinkMode {
+    /// The group follows the most attenuated channel, so no channel is compressed less than it
+    /// would be alone.
+    #[default]
+    Max,
+    /// The group follows the average of the gains in dB.
+    Average,
+}
+
+/// Downward compressor with soft knee, selectable level detector, gain smoothing domain and
+/// topology.
+///
+/// The static curve with the knee width `W` is the one of Giannoulis et al., "Digital Dynamic
+/// Range Compressor Design - A Tutorial and Analysis": the gain is quadratic in the level within
+/// `W / 2` dB around the threshold.
+///
+/// The attack is the time to reduce the gain and the release is the time to recover it.
+///
+/// Any number of channels is supported. The channels in a linking group share their gains
+/// according to the [`LinkMode`] and the linking amount, e.g. L/R/C of a 5.1 stem can be linked
+/// while the LFE is compressed on its own. By default, all channels are in one group.
+///
+/// The detector can be keyed by an external signal with
+/// [`process_sidechain_inplace`](Self::process_sidechain_inplace), e.g. for ducking music under a
+/// voice. The sidechain signal is filtered by the [`SidechainFilter`] before the detector, and it
+/// can be monitored in the listen mode.
+///
+/// The gain reduction, excluding the makeup gain, can be polled from another thread through
+/// [`gain_reduction_meter`](Self::gain_reduction_meter).
+pub struct Compressor {
+    num_channels: usize,
+    sample_rate: f32,
+    block_size: usize,
+
+    threshold: f32,
+    ratio: f32,
+    knee: f32,
+    attack_ms: f32,
+    release_ms: f32,
+    linking: f32,
+    link_groups: Vec<Vec<usize>>,
+    link_mode: LinkMode,
+    makeup_gain: f32,
+    detector: Detector,
+    rms_window_ms: f32,
+    smoothing: GainSmoothing,
+    topology: Topology,
+    sidechain_filter: SidechainFilter,
+    sidechain_listen: bool,
+
+    attack_coeff: f32,
+    release_coeff: f32,
+
+    // Internal states for each channel
+    sidechain_filters: Vec<SosFilter>,
+    detectors: Vec<LevelDetector>,
+    /// The smoothed gains in dB.
+    gains: Vec<f32>,
+    /// The outputs of the previous sample before the makeup gain, for the feedback topology.
+    last_outputs: Vec<f32>,
+    meter: GainReductionMeter,
+
+    // Scratch buffers for each channel
+    target_gains: Vec<f32>,
+    sidechain: Vec<f32>,
+    peak_reductions: Vec<f32>,
+}
+
+impl Effect for Compressor {
+    fn prepare(&mut self, sample_rate: f32, block_size: usize) {
+        self.sample_rate = sample_rate;
+        self.block_size = block_size;
+
+        let max_rms_window = (MAX_RMS_WINDOW * sample_rate * 0.001).ceil() as usize;
+        self.detectors = (0..self.num_channels)
+            .map(|_| LevelDetector::new(self.detector, max_rms_window))
+            .collect();
+        self.sidechain_filters = (0..self.num_channels)
+            .map(|_| SosFilter::new(self.sidechain_filter_coeffs()))
+            .collect();
+        self.update_coeffs();
+        self.update_rms_window();
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        self.sidechain_filters.iter_mut().for_each(|filter| filter.reset());
+        self.detectors.iter_mut().for_each(|detector| detector.reset());
+        self.gains.fill(0.0);
+        self.last_outputs.fill(0.0);
+        self.meter.reset();
+    }
+
+    fn process_inplace<'outer, 'inner>(
+        &mut self,
+        buffer: &'outer mut BufferViewMut<'outer, 'inner>,
+    ) {
+        // Check if the effect is prepared
+        if self.sample_rate == 0.0 {
+            return;
+        }
+
+        debug_assert!(buffer.num_channels() == self.num_channels);
+        let num_samples = buffer.num_samples();
+        self.process_channels(buffer.channels_mut(), num_samples, None);
+    }
+}
+
+impl Default for Compressor {
+    fn default() -> Self {
+        Self {
+            num_channels: 1,
+            sample_rate: 0.0,
+            block_size: 0,
+            threshold: -12.0,
+            ratio: 2.0,
+            knee: 0.0,
+            attack_ms: 5.0,
+            release_ms: 50.0,
+            linking: 1.0,
+            link_groups: vec![vec![0]],
+            link_mode: LinkMode::Max,
+            makeup_gain: 0.0,
+            detector: Detector::Peak,
+            rms_window_ms: 10.0,
+            smoothing: GainSmoothing::Log,
+            topology: Topology::FeedForward,
+            sidechain_filter: SidechainFilter::Off,
+            sidechain_listen: false,
+            attack_coeff: 0.0,
+            release_coeff: 0.0,
+            sidechain_filters: vec![],
+            detectors: vec![],
+            gains: vec![0.0],
+            last_outputs: vec![0.0],
+            meter: GainReductionMeter::new(1),
+            target_gains: vec![0.0],
+            sidechain: vec![0.0],
+            peak_reductions: vec![0.0],
+        }
+    }
+}
+
+impl Compressor {
+    pub fn new(num_channels: usize) -> Self {
+        assert!(num_channels > 0, "num_channels must be greater than 0");
+        Self {
+            num_channels,
+            link_groups: vec![(0..num_channels).collect()],
+            gains: vec![0.0; num_channels],
+            last_outputs: vec![0.0; num_channels],
+            meter: GainReductionMeter::new(num_channels),
+            target_gains: vec![0.0; num_channels],
+            sidechain: vec![0.0; num_channels],
+            peak_reductions: vec![0.0; num_channels],
+            ..Default::default()
+        }
+    }
+
+    /// A handle to the gain reduction of each channel, which can be polled from another thread.
+    pub fn gain_reduction_meter(&self) -> GainReductionMeter {
+        self.meter.clone()
+    }
+
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold;
+    }
+
+    pub fn set_ratio(&mut self, ratio: f32) {
+        self.ratio = ratio;
+    }
+
+    /// Set the knee width in dB. 0 dB is a hard knee.
+    pub fn set_knee(&mut self, knee: f32) {
+        assert!(knee >= 0.0);
+        self.knee = knee;
+    }
+
+    pub fn set_attack(&mut self, attack_ms: f32) {
+        self.attack_ms = attack_ms;
+        self.update_coeffs();
+    }
+
+    pub fn set_release(&mut self, release_ms: f32) {
+        self.release_ms = release_ms;
+        self.update_coeffs();
+    }
+
+    /// Set the linking amount in `[0, 1]`. 0.0 makes the channels independent even in a group.
+    pub fn set_linking(&mut self, linking: f32) {
+        self.linking = linking;
+    }
+
+    /// Set the linking groups, e.g. `vec![vec![0, 1, 2], vec![4, 5]]` for a 5.1 stem links L/R/C
+    /// and the surrounds separately, while the LFE (channel 3) is not linked. The channels not in
+    /// any group are compressed on their own.
+    ///
+    /// # Panics
+    ///
+    /// * If a channel index is out of range or appears more than once.
+    pub fn set_link_groups(&mut self, link_groups: Vec<Vec<usize>>) {
+        let mut grouped = vec![false; self.num_channels];
+        for &ch in link_groups.iter().flatten() {
+            assert!(ch < self.num_channels, "Channel {ch} is out of range");
+            assert!(!grouped[ch], "Channel {ch} is in more than one group");
+            grouped[ch] = true;
+        }
+        self.link_groups = link_groups;
+    }
+
+    pub fn set_link_mode(&mut self, link_mode: LinkMode) {
+        self.link_mode = link_mode;
+    }
+
+    pub fn set_makeup_gain(&mut self, makeup_gain: f32) {
+        self.makeup_gain = makeup_gain;
+    }
+
+    /// Set the level detector. [`Detector::TruePeak`] lags the signal by 6 samples, since the
+    /// compressor has no lookahead.
+    pub fn set_detector(&mut self, detector: Detector) {
+        self.detector = detector;
+        self.detectors.iter_mut().for_each(|d| d.set_detector(detector));
+    }
+
+    /// Set the window of the RMS detector in ms, up to 300 ms.
+    pub fn set_rms_window(&mut self, rms_window_ms: f32) {
+        assert!(rms_window_ms > 0.0 && rms_window_ms <= MAX_RMS_WINDOW);
+        self.rms_window_ms = rms_window_ms;
+        self.update_rms_window();
+    }
+
+    pub fn set_gain_smoothing(&mut self, smoothing: GainSmoothing) {
+        self.smoothing = smoothing;
+    }
+
+    pub fn set_topology(&mut self, topology: Topology) {
+        self.topology = topology;
+    }
+
+    /// Set the filter of the sidechain signal.
+    ///
+    /// # Panics
+    ///
+    /// * If a frequency is not in the range `(0, sample_rate / 2)` once the compressor is prepared.
+    pub fn set_sidechain_filter(&mut self, filter: SidechainFilter) {
+        self.sidechain_filter = filter;
+        if self.sample_rate > 0.0 {
+            let coeffs = self.sidechain_filter_coeffs();
+            self.sidechain_filters.iter_mut().for_each(|filter| filter.set_coeffs(coeffs.clone()));
+        }
+    }
+
+    /// Output the filtered sidechain signal instead of the compressed signal, to hear what the
+    /// detector listens to.
+    pub fn set_sidechain_listen(&mut self, listen: bool) {
+        self.sidechain_listen = listen;
+    }
+
+    /// Process the buffer in place with the detector keyed by an external sidechain signal.
+    ///
+    /// The key must have the same number of samples as the buffer, and either one channel, which
+    /// keys all channels, or as many channels as the compressor. The detector always listens to
+    /// the key, regardless of the [`Topology`].
+    pub fn process_sidechain_inplace<'outer, 'inner>(
+        &mut self,
+        buffer: &'outer mut BufferViewMut<'outer, 'inner>,
+        key: BufferView,
+    ) {
+        // Check if the effect is prepared
+        if self.sample_rate == 0.0 {
+            return;
+        }
+
+        debug_assert!(buffer.num_channels() == self.num_channels);
+        assert!(
+            key.num_channels() == 1 || key.num_channels() == self.num_channels,
+            "The key must have 1 channel or as many channels as the compressor"
+        );
+        assert_eq!(key.num_samples(), buffer.num_samples(), "The key must have as many samples as the buffer");
+        let num_samples = buffer.num_samples();
+        self.process_channels(buffer.channels_mut(), num_samples, Some(key.channels()));
+    }
+
+    /// Process the first `num_samples` samples of the channels in place, with the detector keyed
+    /// by `key` if given. The channels can be slices or the buffers of a multiband compressor.
+    pub(super) fn process_channels<C: AsRef<[f32]> + AsMut<[f32]>>(
+        &mut self,
+        channels: &mut [C],
+        num_samples: usize,
+        key: Option<&[&[f32]]>,
+    ) {
+        // Iterate over samples
+        self.peak_reductions.fill(0.0);
+        for n in 0..num_samples {
+            for (ch, channel) in channels.iter().enumerate() {
+                let detector_input = match (key, self.topology) {
+                    (Some(key), _) => key[ch.min(key.len() - 1)][n],
+                    (None, Topology::FeedForward) => channel.as_ref()[n],
+                    (None, Topology::Feedback) => self.last_outputs[ch],
+                };
+                self.sidechain[ch] = self.sidechain_filters[ch].process_sample(detector_input);
+                let level = level_db(self.detectors[ch].process(self.sidechain[ch]));
+                self.target_gains[ch] = self.compute_target_gain(level);
+            }
+
+            for group in self.link_groups.iter() {
+                let group_gain = match self.link_mode {
+                    LinkMode::Max => group.iter().map(|&ch| self.target_gains[ch]).fold(0.0, f32::min),
+                    LinkMode::Average => {
+                        group.iter().map(|&ch| self.target_gains[ch]).sum::<f32>() / group.len() as f32
+                    }
+                };
+                for &ch in group {
+                    self.target_gains[ch] += self.linking * (group_gain - self.target_gains[ch]);
+                }
+            }
+
+            for (ch, channel) in channels.iter_mut().enumerate() {
+                let channel = channel.as_mut();
+                self.gains[ch] = self.smooth_gain(self.target_gains[ch], self.gains[ch]);
+                self.peak_reductions[ch] = self.peak_reductions[ch].max(-self.gains[ch]);
+                self.last_outputs[ch] = channel[n] * 10.0f32.powf(self.gains[ch] / 20.0);
+                channel[n] = if self.sidechain_listen {
+                    self.sidechain[ch]
+                } else {
+                    self.last_outputs[ch] * 10.0f32.powf(self.makeup_gain / 20.0)
+                };
+            }
+        }
+
+        for (ch, &peak_reduction) in self.peak_reductions.iter().enumerate() {
+            self.meter.update(ch, -self.gains[ch], peak_reduction);
+        }
+    }
+
+    fn sidechain_filter_coeffs(&self) -> SosCoeffs {
+        let section = match self.sidechain_filter {
+            SidechainFilter::Off => SecondOrderSection::new(1.0, 0.0, 0.0, 1.0, 0.0, 0.0),
+            SidechainFilter::HighPass(freq) => biquad::highpass(self.sample_rate, freq, Width::Q(FRAC_1_SQRT_2)),
+            SidechainFilter::BandPass(freq, bandwidth) => {
+                biquad::bandpass(self.sample_rate, freq, Width::Bandwidth(bandwidth))
+            }
+        };
+        section.into()
+    }
+
+    fn update_coeffs(&mut self) {
+        self.attack_coeff = time_constant_coeff(self.attack_ms, self.sample_rate);
+        self.release_coeff = time_constant_coeff(self.release_ms, self.sample_rate);
+    }
+
+    /// Resize the RMS windows, which clears them. Not a part of `update_coeffs`, so that the
+    /// attack and release can be changed while running.
+    fn update_rms_window(&mut self) {
+        let rms_window = (self.rms_window_ms * self.sample_rate * 0.001).round() as usize;
+        self.detectors.iter_mut().for_each(|detector| detector.set_rms_window(rms_window));
+    }
+
+    /// The static curve: the target gain in dB for a level in dB.
+    fn compute_target_gain(&self, level: f32) -> f32 {
+        debug_assert!(self.ratio > 1.0);
+
+        let overshoot = level - self.threshold;
+        let slope = 1.0 / self.ratio - 1.0;
+        let target_gain = {
+            if 2.0 * overshoot <= -self.knee {
+                0.0
+            } else if 2.0 * overshoot < self.knee {
+                slope * (overshoot + self.knee / 2.0).powi(2) / (2.0 * self.knee)
+            } else {
+                slope * overshoot
+            }
+        };
+        debug_assert!(target_gain <= 0.0);
+        target_gain
+    }
+
+    fn smooth_gain(&self, target_gain: f32, current_gain: f32) -> f32 {
+        // The gain falls in the attack phase
+        match self.smoothing {
+            GainSmoothing::Log => smooth_gain(target_gain, current_gain, self.attack_coeff, self.release_coeff),
+            GainSmoothing::Linear => {
+                let target = 10.0f32.powf(target_gain / 20.0);
+                let current = 10.0f32.powf(current_gain / 20.0);
+                let smoothed = smooth_gain(target, current, self.attack_coeff, self.release_coeff);
+                20.0 * smoothed.log10()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 1000.0;
+
+    fn instant_compressor() -> Compressor {
+        let mut compressor = Compressor::new(1);
+        compressor.set_attack(0.0);
+        compressor.set_release(0.0);
+        compressor.set_threshold(-20.0);
+        compressor.set_ratio(4.0);
+        compressor.prepare(SAMPLE_RATE, 64);
+        compressor
+    }
+
+    fn gain_db(output: f32, input: f32) -> f32 {
+        20.0 * (output / input).log10()
+    }
+
+    #[test]
+    fn soft_knee() {
+        let mut compressor = instant_compressor();
+        compressor.set_knee(10.0);
+
+        // Below the knee, at the threshold, and above the knee
+        let input = [0.01, 0.1, 1.0];
+        let output = compressor.process(BufferView::new(&[&input]));
+        let gains: Vec<f32> = output[0].iter().zip(input).map(|(&y, x)| gain_db(y, x)).collect();
+        crate::assert_all_close!(gains, [0.0, -0.75 * 25.0 / 20.0, -15.0], 1e-4);
+    }
+
+    #[test]
+    fn rms_detector() {
+        let mut compressor = instant_compressor();
+        compressor.set_detector(Detector::Rms);
+        compressor.set_rms_window(4.0);
+
+        // A square wave of amplitude 1 has an RMS level of 0 dB, while the first samples are
+        // still averaged with the silence before
+        let output = compressor.process(BufferView::new(&[&[1.0, -1.0, 1.0, -1.0, 1.0]]));
+        assert!(gain_db(output[0][0].abs(), 1.0) > -15.0 + 1e-3);
+        assert!((gain_db(output[0][4].abs(), 1.0) + 15.0).abs() < 1e-4);
+
+        // Changing the attack while running keeps the RMS window, so the gain is continuous
+        compressor.set_attack(1.0);
+        let output = compressor.process(BufferView::new(&[&[-1.0, 1.0, -1.0, 1.0]]));
+        let gains: Vec<f32> = output[0].iter().map(|y| gain_db(y.abs(), 1.0)).collect();
+        crate::assert_all_close!(gains, [-15.0; 4], 1e-4);
+    }
+
+    #[test]
+    fn linear_smoothing() {
+        let mut log = instant_compressor();
+        log.set_release(10.0);
+        let mut linear = instant_compressor();
+        linear.set_release(10.0);
+        linear.set_gain_smoothing(GainSmoothing::Linear);
+
+        // The linear gain recovers faster at the beginning of the release
+        let mut input = vec![1.0; 10];
+        input.extend([0.01; 5]);
+        let log = log.process(BufferView::new(&[&input]));
+        let linear = linear.process(BufferView::new(&[&input]));
+        assert!((log[0][9] - linear[0][9]).abs() < 1e-6);
+        assert!(linear[0][10] > log[0][10]);
+    }
+
+    #[test]
+    fn ducking() {
+        let mut compressor = Compressor::new(2);
+        compressor.set_attack(0.0);
+        compressor.set_release(0.0);
+        compressor.set_threshold(-20.0);
+        compressor.set_ratio(4.0);
+        compressor.prepare(SAMPLE_RATE, 64);
+
+        // A mono key at 0 dB then silence ducks both channels by 15 dB then releases them
+        let key = [1.0, 1.0, 0.0, 0.0];
+        let key_slices: Vec<&[f32]> = vec![&key];
+        let mut buffer = [vec![0.01; 4], vec![0.02; 4]];
+        let mut slices: Vec<&mut [f32]> = buffer.iter_mut().map(|ch| ch.as_mut_slice()).collect();
+        compressor.process_sidechain_inplace(&mut BufferViewMut::new(&mut slices), BufferView::new(&key_slices));
+        let ducked = 10.0f32.powf(-15.0 / 20.0);
+        crate::assert_all_close!(buffer[0], [0.01 * ducked, 0.01 * ducked, 0.01, 0.01]);
+        crate::assert_all_close!(buffer[1], [0.02 * ducked, 0.02 * ducked, 0.02, 0.02]);
+    }
+
+    #[test]
+    fn sidechain_filter() {
+        let mut compressor = instant_compressor();
+        compressor.set_sidechain_filter(SidechainFilter::HighPass(100.0));
+
+        // The highpass filter removes the DC from the detector, so it is not compressed
+        let output = compressor.process(BufferView::new(&[&[1.0; 1000]]));
+        assert!((output[0][999] - 1.0).abs() < 1e-3);
+
+        // In the listen mode, the output is the filtered sidechain signal
+        compressor.set_sidechain_listen(true);
+        compressor.reset();
+        let output = compressor.process(BufferView::new(&[&[1.0; 1000]]));
+        assert_eq!(output[0][0], biquad::highpass(SAMPLE_RATE, 100.0, Width::Q(FRAC_1_SQRT_2)).b()[0]);
+        assert!(output[0][999].abs() < 1e-3);
+    }
+
+    #[test]
+    fn gain_reduction_meter() {
+        let mut compressor = instant_compressor();
+        compressor.set_makeup_gain(6.0);
+        let meter = compressor.gain_reduction_meter();
+
+        // The makeup gain is not a part of the gain reduction
+        let _ = compressor.process(BufferView::new(&[&[1.0, 0.1, 0.01]]));
+        assert_eq!(meter.current(0), 0.0);
+        assert!((meter.block_peak(0) - 15.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn link_groups() {
+        let mut compressor = Compressor::new(4);
+        compressor.set_attack(0.0);
+        compressor.set_release(0.0);
+        compressor.set_threshold(-20.0);
+        compressor.set_ratio(4.0);
+        compressor.set_link_groups(vec![vec![0, 1, 2]]);
+        compressor.prepare(SAMPLE_RATE, 64);
+
+        // Channel 0 at 0 dB pulls the linked channels down by 15 dB, but not channel 3
+        let output = compressor.process(BufferView::new(&[&[1.0], &[0.01], &[0.1], &[1.0]]));
+        let gains: Vec<f32> = output.iter().zip([1.0, 0.01, 0.1, 1.0]).map(|(y, x)| gain_db(y[0], x)).collect();
+        crate::assert_all_close!(gains, [-15.0, -15.0, -15.0, -15.0], 1e-4);
+        let output = compressor.process(BufferView::new(&[&[0.01], &[0.01], &[0.01], &[1.0]]));
+        let gains: Vec<f32> = output.iter().zip([0.01, 0.01, 0.01, 1.0]).map(|(y, x)| gain_db(y[0], x)).collect();
+        crate::assert_all_close!(gains, [0.0, 0.0, 0.0, -15.0], 1e-4);
+
+        // The average of -15, 0 and -7.5 dB
+        compressor.set_link_mode(LinkMode::Average);
+        let input = [1.0, 0.01, 10.0f32.powf(-0.5), 0.01];
+        let channels: Vec<&[f32]> = input.iter().map(std::slice::from_ref).collect();
+        let output = compressor.process(BufferView::new(&channels));
+        let gains: Vec<f32> = output.iter().zip(input).map(|(y, x)| gain_db(y[0], x)).collect();
+        crate::assert_all_close!(gains, [-7.5, -7.5, -7.5, 0.0], 1e-4);
+    }
+
+    #[test]
+    #[should_panic]
+    fn link_groups_overlap() {
+        let mut compressor = Compressor::new(3);
+        compressor.set_link_groups(vec![vec![0, 1], vec![1, 2]]);
+    }
+
+    #[test]
+    fn feedback_topology() {
+        let mut compressor = instant_compressor();
+        compressor.set_topology(Topology::Feedback);
+
+        // The detector sees the previous output, so the gain converges to the fixed point of
+        // G = (1 / R - 1) (G - T) for a 0 dB input, which is an effective ratio of 1.75
+        let output = compressor.process(BufferView::new(&[&[1.0; 200]]));
+        let expected = -60.0 / 7.0;
+        assert!((gain_db(output[0][199], 1.0) - expected).abs() < 1e-3);
+    }
+}
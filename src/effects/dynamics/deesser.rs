@@ -0,0 +1,268 @@
+//! De-esser.
+
+use crate::buffer_view::BufferViewMut;
+use crate::effects::Effect;
+use crate::filter::{Filter, SosFilter};
+use crate::filter::design::SecondOrderSection;
+use crate::filter::design::biquad::{self, Width};
+use super::meter::GainReductionMeter;
+use super::{level_db, smooth_gain, time_constant_coeff};
+
+const RATIO: f32 = 5.0;
+const ATTACK: f32 = 0.5; // ms
+const RELEASE: f32 = 40.0; // ms
+
+const DEFAULT_FREQUENCY: f32 = 6500.0; // Hz
+const DEFAULT_BANDWIDTH: f32 = 1.5; // octaves
+const DEFAULT_THRESHOLD: f32 = -30.0; // dB
+const DEFAULT_RANGE: f32 = 12.0; // dB
+
+/// What a [`DeEsser`] attenuates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeEsserMode {
+    /// Only the sibilance band is attenuated, which keeps the rest of the signal untouched.
+    #[default]
+    SplitBand,
+    /// The whole signal is attenuated, which sounds more natural on sibilance that is not
+    /// confined to the band.
+    Wideband,
+}
+
+/// De-esser: the sibilance is detected in a band around the frequency, and the band or the whole
+/// signal is compressed when its level exceeds the threshold, by at most the range.
+///
+/// The band is isolated by a bandpass biquad with 0 dB peak gain. In the split-band mode, the band
+/// is subtracted from the signal and added back after the attenuation, so the signal is unchanged
+/// when there is no sibilance. All channels share the same gain, so the stereo image does not
+/// shift. In the monitor mode, the output is the band signal, to tune the frequency by ear.
+///
+/// The gain reduction can be polled from another thread through
+/// [`gain_reduction_meter`](Self::gain_reduction_meter).
+pub struct DeEsser {
+    num_channels: usize,
+    sample_rate: f32,
+
+    frequency: f32,
+    bandwidth: f32,
+    threshold: f32,
+    range: f32,
+    mode: DeEsserMode,
+    monitor: bool,
+
+    attack_coeff: f32,
+    release_coeff: f32,
+
+    // Internal states
+    /// The bandpass filter of each channel.
+    filters: Vec<SosFilter>,
+    /// The smoothed gain in dB.
+    gain: f32,
+    meter: GainReductionMeter,
+    /// Scratch buffer for the band signals of a sample.
+    bands: Vec<f32>,
+}
+
+impl Effect for DeEsser {
+    fn prepare(&mut self, sample_rate: f32, _block_size: usize) {
+        assert!(sample_rate > 0.0);
+        self.sample_rate = sample_rate;
+        self.attack_coeff = time_constant_coeff(ATTACK, sample_rate);
+        self.release_coeff = time_constant_coeff(RELEASE, sample_rate);
+        let coeffs = self.bandpass();
+        self.filters = (0..self.num_channels).map(|_| SosFilter::new(coeffs.clone().into())).collect();
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        self.filters.iter_mut().for_each(|filter| filter.reset());
+        self.gain = 0.0;
+        self.meter.reset();
+    }
+
+    fn process_inplace<'outer, 'inner>(
+        &mut self,
+        buffer: &'outer mut BufferViewMut<'outer, 'inner>,
+    ) {
+        // Check if the effect is prepared
+        if self.sample_rate == 0.0 {
+            return;
+        }
+
+        debug_assert_eq!(buffer.num_channels(), self.num_channels);
+        let num_samples = buffer.num_samples();
+        let channels: &mut [&mut [f32]] = buffer.channels_mut();
+
+        let mut peak_reduction = 0.0f32;
+        for n in 0..num_samples {
+            // Detect on the loudest band
+            let mut level = f32::NEG_INFINITY;
+            for ((channel, filter), band) in channels.iter().zip(self.filters.iter_mut()).zip(self.bands.iter_mut()) {
+                *band = filter.process_sample(channel[n]);
+                level = level.max(level_db(*band));
+            }
+            let target_gain = ((self.threshold - level) * (1.0 - 1.0 / RATIO)).clamp(-self.range, 0.0);
+            self.gain = smooth_gain(target_gain, self.gain, self.attack_coeff, self.release_coeff);
+            peak_reduction = peak_reduction.max(-self.gain);
+
+            let gain = 10.0f32.powf(self.gain / 20.0);
+            for (channel, &band) in channels.iter_mut().zip(self.bands.iter()) {
+                channel[n] = match (self.monitor, self.mode) {
+                    (true, _) => band,
+                    (false, DeEsserMode::SplitBand) => channel[n] + (gain - 1.0) * band,
+                    (false, DeEsserMode::Wideband) => channel[n] * gain,
+                };
+            }
+        }
+
+        for ch in 0..self.num_channels {
+            self.meter.update(ch, -self.gain, peak_reduction);
+        }
+    }
+}
+
+impl DeEsser {
+    pub fn new(num_channels: usize) -> Self {
+        assert!(num_channels > 0, "num_channels must be greater than 0");
+        Self {
+            num_channels,
+            sample_rate: 0.0,
+            frequency: DEFAULT_FREQUENCY,
+            bandwidth: DEFAULT_BANDWIDTH,
+            threshold: DEFAULT_THRESHOLD,
+            range: DEFAULT_RANGE,
+            mode: DeEsserMode::SplitBand,
+            monitor: false,
+            attack_coeff: 0.0,
+            release_coeff: 0.0,
+            filters: vec![],
+            gain: 0.0,
+            meter: GainReductionMeter::new(num_channels),
+            bands: vec![0.0; num_channels],
+        }
+    }
+
+    /// A handle to the gain reduction, which can be polled from another thread. All channels have
+    /// the same reading.
+    pub fn gain_reduction_meter(&self) -> GainReductionMeter {
+        self.meter.clone()
+    }
+
+    /// Set the center frequency of the sibilance band in Hz.
+    ///
+    /// # Panics
+    ///
+    /// * If the frequency is not in the range `(0, sample_rate / 2)` once the de-esser is
+    ///   prepared.
+    pub fn set_frequency(&mut self, frequency: f32) {
+        assert!(frequency > 0.0);
+        self.frequency = frequency;
+        self.update_filters();
+    }
+
+    /// Set the bandwidth of the sibilance band in octaves.
+    pub fn set_bandwidth(&mut self, bandwidth: f32) {
+        assert!(bandwidth > 0.0);
+        self.bandwidth = bandwidth;
+        self.update_filters();
+    }
+
+    /// Set the threshold in dBFS of the band level.
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold;
+    }
+
+    /// Set the range, i.e. the maximum attenuation in dB.
+    pub fn set_range(&mut self, range: f32) {
+        assert!(range >= 0.0);
+        self.range = range;
+    }
+
+    pub fn set_mode(&mut self, mode: DeEsserMode) {
+        self.mode = mode;
+    }
+
+    /// Output the sibilance band instead of the processed signal.
+    pub fn set_monitor(&mut self, monitor: bool) {
+        self.monitor = monitor;
+    }
+
+    fn bandpass(&self) -> SecondOrderSection {
+        biquad::bandpass(self.sample_rate, self.frequency, Width::Bandwidth(self.bandwidth))
+    }
+
+    fn update_filters(&mut self) {
+        if self.sample_rate > 0.0 {
+            let coeffs = self.bandpass();
+            self.filters
+                .iter_mut()
+                .for_each(|filter| filter.set_coeffs(coeffs.clone().into()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer_view::BufferView;
+    use crate::utilities::testing::{peak, sine};
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    #[test]
+    fn wideband() {
+        let mut deesser = DeEsser::new(1);
+        deesser.set_mode(DeEsserMode::Wideband);
+        deesser.prepare(SAMPLE_RATE, 512);
+        let meter = deesser.gain_reduction_meter();
+
+        // A loud sibilance is attenuated by the range
+        let output = deesser.process(BufferView::new(&[&sine(6500.0, 0.5, SAMPLE_RATE, 9600)]));
+        assert!((peak(&output[0][4800..]) - 0.5 * 10.0f32.powf(-12.0 / 20.0)).abs() < 0.01);
+        assert!((meter.current(0) - 12.0).abs() < 0.1);
+
+        // A loud low frequency is not
+        deesser.reset();
+        let output = deesser.process(BufferView::new(&[&sine(200.0, 0.5, SAMPLE_RATE, 9600)]));
+        assert!((peak(&output[0][4800..]) - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn split_band() {
+        let mut deesser = DeEsser::new(2);
+        deesser.set_threshold(-50.0);
+        deesser.set_range(40.0);
+        deesser.prepare(SAMPLE_RATE, 512);
+
+        // Only the sibilance band is attenuated
+        let low = sine(150.0, 0.5, SAMPLE_RATE, 9600);
+        let high = sine(6500.0, 0.5, SAMPLE_RATE, 9600);
+        let mix: Vec<f32> = low.iter().zip(high.iter()).map(|(a, b)| a + b).collect();
+        let output = deesser.process(BufferView::new(&[&mix, &mix]));
+        let residual: Vec<f32> = output[0].iter().zip(low.iter()).map(|(y, x)| y - x).collect();
+        assert!(peak(&residual[4800..]) < 0.05);
+        crate::assert_all_close!(output[0], output[1]);
+    }
+
+    #[test]
+    fn transparent() {
+        let mut deesser = DeEsser::new(1);
+        deesser.set_threshold(0.0);
+        deesser.prepare(SAMPLE_RATE, 512);
+        let input = sine(6500.0, 0.5, SAMPLE_RATE, 9600);
+        let output = deesser.process(BufferView::new(&[&input]));
+        crate::assert_all_close!(output[0], input);
+    }
+
+    #[test]
+    fn monitor() {
+        let mut deesser = DeEsser::new(1);
+        deesser.set_monitor(true);
+        deesser.set_threshold(0.0);
+        deesser.prepare(SAMPLE_RATE, 512);
+        let output = deesser.process(BufferView::new(&[&sine(200.0, 0.5, SAMPLE_RATE, 9600)]));
+        assert!(peak(&output[0][4800..]) < 0.025);
+        deesser.reset();
+        let output = deesser.process(BufferView::new(&[&sine(6500.0, 0.5, SAMPLE_RATE, 9600)]));
+        assert!((peak(&output[0][4800..]) - 0.5).abs() < 0.01);
+    }
+}
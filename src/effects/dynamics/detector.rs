@@ -0,0 +1,187 @@
+//! Level detectors of the dynamics processors.
+
+use std::f32::consts::PI;
+
+use crate::utilities::sinc;
+
+/// The oversampling factor of the true-peak detector.
+const OVERSAMPLING: usize = 4;
+/// The number of taps of each phase of the true-peak interpolator.
+const INTERP_TAPS: usize = 12;
+/// The longest window of the RMS detectors in ms.
+pub(super) const MAX_RMS_WINDOW: f32 = 300.0;
+
+/// The level detector of a dynamics processor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Detector {
+    /// The absolute value of each sample.
+    #[default]
+    Peak,
+    /// The root mean square over a sliding window.
+    Rms,
+    /// The peak of the 4x oversampled signal, which includes the inter-sample peaks. It lags the
+    /// signal by 6 samples.
+    TruePeak,
+}
+
+/// True-peak detector: the peaks between the samples are estimated by 4x oversampling with a
+/// Hann-windowed sinc interpolator, as recommended by ITU-R BS.1770.
+pub(super) struct TruePeakDetector {
+    /// The fractional phases of the interpolator.
+    kernels: [[f32; INTERP_TAPS]; OVERSAMPLING - 1],
+    /// The recent input samples.
+    history: [f32; INTERP_TAPS],
+}
+
+/// Root mean square over a sliding window.
+pub(super) struct RmsDetector {
+    /// Ring buffer of the squared samples. The length is the maximum window.
+    buffer: Vec<f32>,
+    window: usize,
+    index: usize,
+    sum: f64,
+}
+
+/// A [`Detector`] of any kind for a single channel.
+pub(super) struct LevelDetector {
+    detector: Detector,
+    rms: RmsDetector,
+    true_peak: TruePeakDetector,
+}
+
+impl TruePeakDetector {
+    /// The delay of the detector in samples.
+    pub(super) const DELAY: usize = INTERP_TAPS / 2;
+
+    pub(super) fn new() -> Self {
+        // The distances from the interpolated positions to the taps
+        let half_width = Self::DELAY as f32 + 0.5;
+        let mut kernels = [[0.0; INTERP_TAPS]; OVERSAMPLING - 1];
+        for (p, kernel) in kernels.iter_mut().enumerate() {
+            let position = (INTERP_TAPS - 1 - Self::DELAY) as f32 + (p + 1) as f32 / OVERSAMPLING as f32;
+            for (k, h) in kernel.iter_mut().enumerate() {
+                let t = position - k as f32;
+                *h = sinc(t) * (0.5 + 0.5 * (PI * t / half_width).cos());
+            }
+            // Unity gain at DC
+            let sum: f32 = kernel.iter().sum();
+            kernel.iter_mut().for_each(|h| *h /= sum);
+        }
+        Self {
+            kernels,
+            history: [0.0; INTERP_TAPS],
+        }
+    }
+
+    /// Push a sample and return the peak from the sample [`Self::DELAY`] samples ago up to the
+    /// next one.
+    pub(super) fn process(&mut self, x: f32) -> f32 {
+        self.history.copy_within(1.., 0);
+        self.history[INTERP_TAPS - 1] = x;
+
+        let sample = self.history[INTERP_TAPS - 1 - Self::DELAY].abs();
+        self.kernels.iter().fold(sample, |peak, kernel| {
+            let y: f32 = kernel.iter().zip(self.history.iter()).map(|(h, x)| h * x).sum();
+            peak.max(y.abs())
+        })
+    }
+
+    pub(super) fn reset(&mut self) {
+        self.history.fill(0.0);
+    }
+}
+
+impl RmsDetector {
+    /// Create a detector with windows up to `max_window` samples.
+    pub(super) fn new(max_window: usize) -> Self {
+        let max_window = max_window.max(1);
+        Self {
+            buffer: vec![0.0; max_window],
+            window: max_window,
+            index: 0,
+            sum: 0.0,
+        }
+    }
+
+    /// Set the window in samples, limited to the maximum window. The detector is reset.
+    pub(super) fn set_window(&mut self, window: usize) {
+        self.window = window.clamp(1, self.buffer.len());
+        self.reset();
+    }
+
+    pub(super) fn process(&mut self, x: f32) -> f32 {
+        let square = x * x;
+        self.sum += (square - self.buffer[self.index]) as f64;
+        self.buffer[self.index] = square;
+        self.index = (self.index + 1) % self.window;
+        (self.sum.max(0.0) / self.window as f64).sqrt() as f32
+    }
+
+    pub(super) fn reset(&mut self) {
+        self.buffer.fill(0.0);
+        self.index = 0;
+        self.sum = 0.0;
+    }
+}
+
+impl LevelDetector {
+    pub(super) fn new(detector: Detector, max_rms_window: usize) -> Self {
+        Self {
+            detector,
+            rms: RmsDetector::new(max_rms_window),
+            true_peak: TruePeakDetector::new(),
+        }
+    }
+
+    pub(super) fn set_detector(&mut self, detector: Detector) {
+        self.detector = detector;
+        self.reset();
+    }
+
+    pub(super) fn set_rms_window(&mut self, window: usize) {
+        self.rms.set_window(window);
+    }
+
+    /// Push a sample and return the detected amplitude.
+    pub(super) fn process(&mut self, x: f32) -> f32 {
+        match self.detector {
+            Detector::Peak => x.abs(),
+            Detector::Rms => self.rms.process(x),
+            Detector::TruePeak => self.true_peak.process(x),
+        }
+    }
+
+    pub(super) fn reset(&mut self) {
+        self.rms.reset();
+        self.true_peak.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rms() {
+        let mut detector = RmsDetector::new(8);
+        detector.set_window(4);
+        let levels: Vec<f32> = [2.0, -2.0, 2.0, -2.0, 0.0, 0.0].iter().map(|&x| detector.process(x)).collect();
+        crate::assert_all_close!(levels, [1.0, 2.0f32.sqrt(), 3.0f32.sqrt(), 2.0, 3.0f32.sqrt(), 2.0f32.sqrt()]);
+    }
+
+    #[test]
+    fn true_peak() {
+        // A sine at fs/4 sampled at 45 degrees: the sample peak is -3 dB and the true peak is 0 dB
+        let mut detector = TruePeakDetector::new();
+        let peak = (0..100)
+            .map(|n| detector.process((PI * (n as f32 / 2.0 + 0.25)).sin()))
+            .skip(20)
+            .fold(0.0f32, f32::max);
+        assert!((peak - 1.0).abs() < 0.02);
+
+        // The detector lags by the delay
+        detector.reset();
+        let levels: Vec<f32> = (0..10).map(|n| detector.process(if n == 0 { 1.0 } else { 0.0 })).collect();
+        assert_eq!(levels[TruePeakDetector::DELAY], 1.0);
+    }
+}
@@ -0,0 +1,564 @@
+//! Dynamic equalizer.
+
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use crate::buffer_view::{BufferView, BufferViewMut};
+use crate::effects::Effect;
+use crate::filter::{Filter, SosFilter};
+use crate::filter::design::SecondOrderSection;
+use crate::filter::design::biquad::{self, Width};
+use super::detector::{Detector, LevelDetector, MAX_RMS_WINDOW};
+use super::meter::GainReductionMeter;
+use super::{level_db, smooth_gain, time_constant_coeff};
+
+const MAX_BANDS: usize = 8;
+/// The number of samples between the updates of the equalizer filters.
+const CONTROL_INTERVAL: usize = 16;
+/// The smallest gain change in dB that redesigns an equalizer filter.
+const MIN_GAIN_CHANGE: f32 = 0.01;
+
+/// The shape of a [`DynamicEqBand`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EqBandShape {
+    /// Peaking filter around the frequency. The detector listens to a bandpass filter with the
+    /// same frequency and Q.
+    #[default]
+    Peak,
+    /// Low-shelf filter below the frequency. The detector listens to a lowpass filter.
+    LowShelf,
+    /// High-shelf filter above the frequency. The detector listens to a highpass filter.
+    HighShelf,
+}
+
+/// A band of a [`DynamicEq`]: a peaking or shelving filter whose gain is the static gain plus a
+/// dynamic gain driven by the level of the band.
+///
+/// The dynamic gain follows the static curve of a compressor applied to the band level: above
+/// the threshold, the level is reduced according to the ratio. A ratio below 1 boosts the band
+/// instead, e.g. 0.5 boosts it by the overshoot. The dynamic gain is limited to the range in
+/// both directions. The attack is the time for the dynamic gain to move away from 0 dB and the
+/// release the time to come back.
+///
+/// The band level is measured by a [`Detector`] after the detector filter, by default the RMS over
+/// 10 ms, so the gain settles on the static curve for a steady tone. A peak detector reacts
+/// faster, but the gain then ripples with the waveform.
+pub struct DynamicEqBand {
+    num_channels: usize,
+    sample_rate: f32,
+
+    shape: EqBandShape,
+    frequency: f32,
+    q: f32,
+    gain: f32,
+    threshold: f32,
+    ratio: f32,
+    range: f32,
+    attack_ms: f32,
+    release_ms: f32,
+    detector: Detector,
+    rms_window_ms: f32,
+    external_sidechain: bool,
+    bypass: bool,
+
+    attack_coeff: f32,
+    release_coeff: f32,
+
+    // Internal states
+    /// The detector filters of each channel.
+    detector_filters: Vec<SosFilter>,
+    /// The level detectors of each channel.
+    detectors: Vec<LevelDetector>,
+    /// The equalizer filters of each channel.
+    filters: Vec<SosFilter>,
+    /// The smoothed dynamic gain in dB.
+    dynamic_gain: f32,
+    /// The dynamic gain in dB the equalizer filters are designed with.
+    applied_gain: f32,
+    /// The peak gain reduction in dB in the current block.
+    peak_reduction: f32,
+}
+
+/// Dynamic equalizer: up to 8 parametric bands (peak or shelf) in series, whose gains move with
+/// the level of their own frequency region, e.g. to tame a resonance only when it rings or to
+/// duck the low end of a bass only when the kick hits.
+///
+/// Bands are added with [`add_band`](Self::add_band) and configured through
+/// [`band_mut`](Self::band_mut). The detectors of all bands listen to the input of the equalizer,
+/// and all channels share the gain of a band, driven by the loudest channel. A band can instead
+/// be keyed by an external signal, given to
+/// [`process_sidechain_inplace`](Self::process_sidechain_inplace).
+///
+/// The filters are redesigned every 16 samples when their gains change.
+///
+/// Since all channels share the gains of the bands, the [`GainReductionMeter`] returned by
+/// [`gain_reduction_meter`](Self::gain_reduction_meter) has one reading per band instead of per
+/// channel: reading `i` is the cut of band `i`, and boosts read as 0 dB. It has 8 readings,
+/// whatever the number of bands.
+pub struct DynamicEq {
+    num_channels: usize,
+    sample_rate: f32,
+    bands: Vec<DynamicEqBand>,
+
+    // Internal states
+    /// The number of samples until the next filter update.
+    control_countdown: usize,
+    meter: GainReductionMeter,
+}
+
+impl Effect for DynamicEq {
+    fn prepare(&mut self, sample_rate: f32, _block_size: usize) {
+        assert!(sample_rate > 0.0);
+        self.sample_rate = sample_rate;
+        self.bands.iter_mut().for_each(|band| band.prepare(sample_rate));
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        self.bands.iter_mut().for_each(|band| band.reset());
+        self.control_countdown = 0;
+        self.meter.reset();
+    }
+
+    fn process_inplace<'outer, 'inner>(
+        &mut self,
+        buffer: &'outer mut BufferViewMut<'outer, 'inner>,
+    ) {
+        // Check if the effect is prepared
+        if self.sample_rate == 0.0 {
+            return;
+        }
+
+        debug_assert!(buffer.num_channels() == self.num_channels);
+        self.process_channels(buffer.channels_mut(), None);
+    }
+}
+
+impl DynamicEq {
+    pub fn new(num_channels: usize) -> Self {
+        assert!(num_channels > 0, "num_channels must be greater than 0");
+        Self {
+            num_channels,
+            sample_rate: 0.0,
+            bands: vec![],
+            control_countdown: 0,
+            meter: GainReductionMeter::new(MAX_BANDS),
+        }
+    }
+
+    /// Add a band with the given shape and frequency in Hz, and return its index. The band has no
+    /// static gain, a threshold of -20 dBFS, a ratio of 2, a range of 12 dB, 5 ms attack and
+    /// 50 ms release.
+    ///
+    /// # Panics
+    ///
+    /// * If there are already 8 bands.
+    /// * If the frequency is not in the range `(0, sample_rate / 2)` once the equalizer is
+    ///   prepared.
+    pub fn add_band(&mut self, shape: EqBandShape, frequency: f32) -> usize {
+        assert!(
+            self.bands.len() < MAX_BANDS,
+            "A dynamic EQ has at most {MAX_BANDS} bands"
+        );
+        let mut band = DynamicEqBand::new(self.num_channels, shape, frequency);
+        if self.sample_rate > 0.0 {
+            band.prepare(self.sample_rate);
+        }
+        self.bands.push(band);
+        self.bands.len() - 1
+    }
+
+    /// Remove a band. The indices of the following bands are shifted down.
+    pub fn remove_band(&mut self, band: usize) {
+        self.bands.remove(band);
+        self.meter.reset();
+    }
+
+    pub fn num_bands(&self) -> usize {
+        self.bands.len()
+    }
+
+    pub fn band(&self, band: usize) -> &DynamicEqBand {
+        &self.bands[band]
+    }
+
+    /// A band, for setting its parameters.
+    pub fn band_mut(&mut self, band: usize) -> &mut DynamicEqBand {
+        &mut self.bands[band]
+    }
+
+    /// A handle to the gain reduction of each band, which can be polled from another thread. The
+    /// readings are indexed by band.
+    pub fn gain_reduction_meter(&self) -> GainReductionMeter {
+        self.meter.clone()
+    }
+
+    /// Process the buffer in place, with the detectors of the bands set to an external sidechain
+    /// keyed by `key`. The other bands listen to the buffer.
+    ///
+    /// The key must have the same number of samples as the buffer, and either one channel, which
+    /// keys all channels, or as many channels as the equalizer.
+    pub fn process_sidechain_inplace<'outer, 'inner>(
+        &mut self,
+        buffer: &'outer mut BufferViewMut<'outer, 'inner>,
+        key: BufferView,
+    ) {
+        // Check if the effect is prepared
+        if self.sample_rate == 0.0 {
+            return;
+        }
+
+        debug_assert!(buffer.num_channels() == self.num_channels);
+        assert!(
+            key.num_channels() == 1 || key.num_channels() == self.num_channels,
+            "The key must have 1 channel or as many channels as the equalizer"
+        );
+        assert_eq!(
+            key.num_samples(),
+            buffer.num_samples(),
+            "The key must have as many samples as the buffer"
+        );
+        self.process_channels(buffer.channels_mut(), Some(key.channels()));
+    }
+
+    /// Process the channels in place, with the bands set to an external sidechain keyed by `key`
+    /// if given.
+    fn process_channels(&mut self, channels: &mut [&mut [f32]], key: Option<&[&[f32]]>) {
+        let num_samples = channels.first().map_or(0, |ch| ch.len());
+
+        self.bands.iter_mut().for_each(|band| band.peak_reduction = 0.0);
+        for n in 0..num_samples {
+            // All detectors listen to the input before any band is applied
+            for band in self.bands.iter_mut().filter(|band| !band.bypass) {
+                let level = match (key, band.external_sidechain) {
+                    (Some(key), true) => band.detect(|ch| key[ch.min(key.len() - 1)][n]),
+                    _ => band.detect(|ch| channels[ch][n]),
+                };
+                band.update_gain(level);
+            }
+
+            let update = self.control_countdown == 0;
+            self.control_countdown = if update {
+                CONTROL_INTERVAL - 1
+            } else {
+                self.control_countdown - 1
+            };
+            for band in self.bands.iter_mut().filter(|band| !band.bypass) {
+                if update && (band.dynamic_gain - band.applied_gain).abs() >= MIN_GAIN_CHANGE {
+                    band.applied_gain = band.dynamic_gain;
+                    band.update_filters();
+                }
+                for (channel, filter) in channels.iter_mut().zip(band.filters.iter_mut()) {
+                    channel[n] = filter.process_sample(channel[n]);
+                }
+            }
+        }
+
+        for (i, band) in self.bands.iter().enumerate() {
+            self.meter.update(i, -band.dynamic_gain, band.peak_reduction);
+        }
+    }
+}
+
+impl DynamicEqBand {
+    fn new(num_channels: usize, shape: EqBandShape, frequency: f32) -> Self {
+        assert!(frequency > 0.0);
+        Self {
+            num_channels,
+            sample_rate: 0.0,
+            shape,
+            frequency,
+            q: FRAC_1_SQRT_2,
+            gain: 0.0,
+            threshold: -20.0,
+            ratio: 2.0,
+            range: 12.0,
+            attack_ms: 5.0,
+            release_ms: 50.0,
+            detector: Detector::Rms,
+            rms_window_ms: 10.0,
+            external_sidechain: false,
+            bypass: false,
+            attack_coeff: 0.0,
+            release_coeff: 0.0,
+            detector_filters: vec![],
+            detectors: vec![],
+            filters: vec![],
+            dynamic_gain: 0.0,
+            applied_gain: 0.0,
+            peak_reduction: 0.0,
+        }
+    }
+
+    pub fn set_shape(&mut self, shape: EqBandShape) {
+        self.shape = shape;
+        self.update_detectors();
+        self.update_filters();
+    }
+
+    /// Set the frequency in Hz: the center of a peak, or the midpoint of a shelf.
+    ///
+    /// # Panics
+    ///
+    /// * If the frequency is not in the range `(0, sample_rate / 2)` once the equalizer is
+    ///   prepared.
+    pub fn set_frequency(&mut self, frequency: f32) {
+        assert!(frequency > 0.0);
+        self.frequency = frequency;
+        self.update_detectors();
+        self.update_filters();
+    }
+
+    /// Set the quality factor of the filter and of its detector.
+    pub fn set_q(&mut self, q: f32) {
+        assert!(q > 0.0);
+        self.q = q;
+        self.update_detectors();
+        self.update_filters();
+    }
+
+    /// Set the static gain in dB, which is applied regardless of the level.
+    pub fn set_gain(&mut self, gain: f32) {
+        self.gain = gain;
+        self.update_filters();
+    }
+
+    /// Set the threshold in dBFS of the band level.
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold;
+    }
+
+    /// Set the ratio. Above 1, the band is cut when it exceeds the threshold; below 1, it is
+    /// boosted.
+    pub fn set_ratio(&mut self, ratio: f32) {
+        assert!(ratio > 0.0);
+        self.ratio = ratio;
+    }
+
+    /// Set the range, i.e. the maximum change of the gain in dB.
+    pub fn set_range(&mut self, range: f32) {
+        assert!(range >= 0.0);
+        self.range = range;
+    }
+
+    pub fn set_attack(&mut self, attack_ms: f32) {
+        self.attack_ms = attack_ms;
+        self.update_time_constants();
+    }
+
+    pub fn set_release(&mut self, release_ms: f32) {
+        self.release_ms = release_ms;
+        self.update_time_constants();
+    }
+
+    pub fn set_detector(&mut self, detector: Detector) {
+        self.detector = detector;
+        self.detectors.iter_mut().for_each(|d| d.set_detector(detector));
+    }
+
+    /// Set the window of the RMS detector in ms, up to 300 ms.
+    pub fn set_rms_window(&mut self, rms_window_ms: f32) {
+        assert!(rms_window_ms > 0.0 && rms_window_ms <= MAX_RMS_WINDOW);
+        self.rms_window_ms = rms_window_ms;
+        self.update_rms_window();
+    }
+
+    /// Key the detector of the band by the external sidechain given to
+    /// [`DynamicEq::process_sidechain_inplace`]. Without a key, the detector listens to the input.
+    pub fn set_external_sidechain(&mut self, external_sidechain: bool) {
+        self.external_sidechain = external_sidechain;
+    }
+
+    /// Bypass the band: it is neither detected nor applied.
+    pub fn set_bypass(&mut self, bypass: bool) {
+        self.bypass = bypass;
+    }
+
+    /// The current dynamic gain in dB, on top of the static gain.
+    pub fn dynamic_gain(&self) -> f32 {
+        self.dynamic_gain
+    }
+
+    fn prepare(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.detector_filters = (0..self.num_channels)
+            .map(|_| SosFilter::new(self.detector_section().into()))
+            .collect();
+        let max_rms_window = (MAX_RMS_WINDOW * sample_rate * 0.001).ceil() as usize;
+        self.detectors = (0..self.num_channels)
+            .map(|_| LevelDetector::new(self.detector, max_rms_window))
+            .collect();
+        self.filters = (0..self.num_channels)
+            .map(|_| SosFilter::new(self.filter_section().into()))
+            .collect();
+        self.update_time_constants();
+        self.update_rms_window();
+    }
+
+    fn reset(&mut self) {
+        self.detector_filters.iter_mut().for_each(|filter| filter.reset());
+        self.detectors.iter_mut().for_each(|detector| detector.reset());
+        self.filters.iter_mut().for_each(|filter| filter.reset());
+        self.dynamic_gain = 0.0;
+        self.applied_gain = 0.0;
+        self.peak_reduction = 0.0;
+        self.update_filters();
+    }
+
+    /// Filter a sample of each channel of the detector input and return the loudest level in dB.
+    fn detect(&mut self, input: impl Fn(usize) -> f32) -> f32 {
+        self.detector_filters
+            .iter_mut()
+            .zip(self.detectors.iter_mut())
+            .enumerate()
+            .map(|(ch, (filter, detector))| level_db(detector.process(filter.process_sample(input(ch)))))
+            .fold(f32::NEG_INFINITY, f32::max)
+    }
+
+    /// Move the dynamic gain towards the static curve for the level in dB.
+    fn update_gain(&mut self, level: f32) {
+        let overshoot = (level - self.threshold).max(0.0);
+        let target_gain = (overshoot * (1.0 / self.ratio - 1.0)).clamp(-self.range, self.range);
+        // The attack moves the gain away from 0 dB, in either direction
+        self.dynamic_gain = if self.ratio >= 1.0 {
+            smooth_gain(target_gain, self.dynamic_gain, self.attack_coeff, self.release_coeff)
+        } else {
+            smooth_gain(target_gain, self.dynamic_gain, self.release_coeff, self.attack_coeff)
+        };
+        self.peak_reduction = self.peak_reduction.max(-self.dynamic_gain);
+    }
+
+    fn update_time_constants(&mut self) {
+        self.attack_coeff = time_constant_coeff(self.attack_ms, self.sample_rate);
+        self.release_coeff = time_constant_coeff(self.release_ms, self.sample_rate);
+    }
+
+    fn update_rms_window(&mut self) {
+        let rms_window = (self.rms_window_ms * self.sample_rate * 0.001).round() as usize;
+        self.detectors.iter_mut().for_each(|detector| detector.set_rms_window(rms_window));
+    }
+
+    fn detector_section(&self) -> SecondOrderSection {
+        let width = Width::Q(self.q);
+        match self.shape {
+            EqBandShape::Peak => biquad::bandpass(self.sample_rate, self.frequency, width),
+            EqBandShape::LowShelf => biquad::lowpass(self.sample_rate, self.frequency, width),
+            EqBandShape::HighShelf => biquad::highpass(self.sample_rate, self.frequency, width),
+        }
+    }
+
+    fn filter_section(&self) -> SecondOrderSection {
+        let width = Width::Q(self.q);
+        let gain = self.gain + self.applied_gain;
+        match self.shape {
+            EqBandShape::Peak => biquad::peaking(self.sample_rate, self.frequency, width, gain),
+            EqBandShape::LowShelf => biquad::low_shelf(self.sample_rate, self.frequency, width, gain),
+            EqBandShape::HighShelf => biquad::high_shelf(self.sample_rate, self.frequency, width, gain),
+        }
+    }
+
+    fn update_detectors(&mut self) {
+        if self.sample_rate > 0.0 {
+            let section = self.detector_section();
+            self.detector_filters
+                .iter_mut()
+                .for_each(|filter| filter.set_section(0, section.clone()));
+        }
+    }
+
+    /// Redesign the equalizer filters. The sections are overwritten in place, so this does not
+    /// allocate and runs on the audio thread.
+    fn update_filters(&mut self) {
+        if self.sample_rate > 0.0 {
+            let section = self.filter_section();
+            self.filters
+                .iter_mut()
+                .for_each(|filter| filter.set_section(0, section.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utilities::testing::{peak, sine};
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    fn db(amplitude: f32) -> f32 {
+        20.0 * amplitude.log10()
+    }
+
+    #[test]
+    fn static_gain() {
+        let mut eq = DynamicEq::new(1);
+        let band = eq.add_band(EqBandShape::Peak, 1000.0);
+        eq.band_mut(band).set_gain(6.0);
+        eq.band_mut(band).set_threshold(20.0);
+        eq.prepare(SAMPLE_RATE, 512);
+        let output = eq.process(BufferView::new(&[&sine(1000.0, 0.1, SAMPLE_RATE, 9600)]));
+        assert!((db(peak(&output[0][4800..]) / 0.1) - 6.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn downward() {
+        let mut eq = DynamicEq::new(2);
+        let band = eq.add_band(EqBandShape::Peak, 1000.0);
+        eq.band_mut(band).set_threshold(-30.0);
+        eq.band_mut(band).set_ratio(4.0);
+        eq.prepare(SAMPLE_RATE, 512);
+        let meter = eq.gain_reduction_meter();
+
+        // The RMS level of -9 dBFS is 21 dB above the threshold: the cut of 15.75 dB is limited by
+        // the range
+        let output = eq.process(BufferView::new(&[&sine(1000.0, 0.5, SAMPLE_RATE, 9600), &[0.0; 9600]]));
+        assert!((db(peak(&output[0][4800..]) / 0.5) + 12.0).abs() < 0.2);
+        assert!((eq.band(band).dynamic_gain() + 12.0).abs() < 0.1);
+        assert!((meter.current(band) - 12.0).abs() < 0.1);
+
+        // -49 dBFS is below the threshold
+        eq.reset();
+        let output = eq.process(BufferView::new(&[&sine(1000.0, 0.005, SAMPLE_RATE, 9600), &[0.0; 9600]]));
+        assert!(db(peak(&output[0][4800..]) / 0.005).abs() < 0.1);
+
+        // A loud signal away from the band is not detected
+        eq.reset();
+        let output = eq.process(BufferView::new(&[&sine(50.0, 0.5, SAMPLE_RATE, 9600), &[0.0; 9600]]));
+        assert!(db(peak(&output[0][4800..]) / 0.5).abs() < 0.1);
+    }
+
+    #[test]
+    fn upward() {
+        let mut eq = DynamicEq::new(1);
+        let band = eq.add_band(EqBandShape::HighShelf, 500.0);
+        eq.band_mut(band).set_threshold(-30.0);
+        eq.band_mut(band).set_ratio(0.5);
+        eq.prepare(SAMPLE_RATE, 512);
+
+        // The RMS level of -23 dBFS is 7 dB above the threshold and boosted by 7 dB
+        let output = eq.process(BufferView::new(&[&sine(3000.0, 0.1, SAMPLE_RATE, 9600)]));
+        assert!((db(peak(&output[0][4800..]) / 0.1) - 7.0).abs() < 0.3);
+        assert!((eq.band(band).dynamic_gain() - 7.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn external_sidechain() {
+        let mut eq = DynamicEq::new(1);
+        let band = eq.add_band(EqBandShape::LowShelf, 200.0);
+        eq.band_mut(band).set_threshold(-30.0);
+        eq.band_mut(band).set_ratio(4.0);
+        eq.band_mut(band).set_external_sidechain(true);
+        eq.prepare(SAMPLE_RATE, 512);
+
+        // A quiet bass is ducked by a loud key
+        let mut output = sine(50.0, 0.01, SAMPLE_RATE, 9600);
+        let key = sine(60.0, 0.5, SAMPLE_RATE, 9600);
+        let mut channels = [output.as_mut_slice()];
+        eq.process_sidechain_inplace(&mut BufferViewMut::new(&mut channels), BufferView::new(&[&key]));
+        assert!((db(peak(&output[4800..]) / 0.01) + 12.0).abs() < 0.3);
+
+        // Without a key, the band listens to the input
+        eq.reset();
+        let output = eq.process(BufferView::new(&[&sine(50.0, 0.01, SAMPLE_RATE, 9600)]));
+        assert!(db(peak(&output[0][4800..]) / 0.01).abs() < 0.1);
+    }
+}
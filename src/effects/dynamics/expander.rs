@@ -0,0 +1,321 @@
+//! Downward expander and noise gate.
+
+use crate::buffer_view::BufferViewMut;
+use crate::effects::Effect;
+use super::meter::GainReductionMeter;
+use super::{level_db, smooth_gain, time_constant_coeff};
+
+/// Downward expander: the signal below the threshold is attenuated by the ratio, i.e. every dB
+/// below the threshold becomes `ratio` dB. The attenuation is limited by the range. With an
+/// infinite ratio, the expander is a noise gate.
+///
+/// The gate opens when the level rises above the threshold and closes when the level falls below
+/// the threshold minus the hysteresis. After the level falls below, the gain is held for the hold
+/// time before the release starts. The attack is the time to open the gate (the gain rises) and the
+/// release is the time to close it (the gain falls).
+///
+/// With stereo linking, the more attenuated channel follows the other one, so that a signal in one
+/// channel keeps both channels open.
+///
+/// The gain reduction can be polled from another thread through
+/// [`gain_reduction_meter`](Self::gain_reduction_meter).
+pub struct Expander {
+    num_channels: usize,
+    sample_rate: f32,
+
+    threshold: f32,
+    ratio: f32,
+    range: f32,
+    hysteresis: f32,
+    hold_ms: f32,
+    attack_ms: f32,
+    release_ms: f32,
+    linking: f32,
+
+    attack_coeff: f32,
+    release_coeff: f32,
+    hold_samples: usize,
+
+    // Internal states for each channel
+    gains: [f32; 2],
+    is_open: [bool; 2],
+    hold_counters: [usize; 2],
+    meter: GainReductionMeter,
+}
+
+impl Effect for Expander {
+    fn prepare(&mut self, sample_rate: f32, _block_size: usize) {
+        self.sample_rate = sample_rate;
+        self.update_coeffs();
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        self.gains = [0.0; 2];
+        self.is_open = [true; 2];
+        self.hold_counters = [0; 2];
+        self.meter.reset();
+    }
+
+    fn process_inplace<'outer, 'inner>(
+        &mut self,
+        buffer: &'outer mut BufferViewMut<'outer, 'inner>,
+    ) {
+        // Check if the effect is prepared
+        if self.sample_rate == 0.0 {
+            return;
+        }
+
+        debug_assert!(buffer.num_channels() == self.num_channels);
+        let num_samples = buffer.num_samples();
+        let channels: &mut [&mut [f32]] = buffer.channels_mut();
+
+        let mut peak_reductions = [0.0f32; 2];
+        for n in 0..num_samples {
+            let mut target_gains = [0.0; 2];
+            for ch in 0..self.num_channels {
+                target_gains[ch] = self.compute_target_gain(ch, channels[ch][n]);
+            }
+
+            if self.num_channels == 2 {
+                let [left, right] = &mut target_gains;
+                if *left > *right {
+                    *right += self.linking * (*left - *right);
+                } else {
+                    *left += self.linking * (*right - *left);
+                }
+            }
+
+            for (ch, channel) in channels.iter_mut().enumerate() {
+                // The gain falls in the release phase
+                let (falling_coeff, rising_coeff) = (self.release_coeff, self.attack_coeff);
+                self.gains[ch] = smooth_gain(target_gains[ch], self.gains[ch], falling_coeff, rising_coeff);
+                peak_reductions[ch] = peak_reductions[ch].max(-self.gains[ch]);
+                channel[n] *= 10.0f32.powf(self.gains[ch] / 20.0);
+            }
+        }
+
+        for (ch, &peak_reduction) in peak_reductions.iter().enumerate().take(self.num_channels) {
+            self.meter.update(ch, -self.gains[ch], peak_reduction);
+        }
+    }
+}
+
+impl Default for Expander {
+    fn default() -> Self {
+        Self {
+            num_channels: 1,
+            sample_rate: 0.0,
+            threshold: -50.0,
+            ratio: 2.0,
+            range: 40.0,
+            hysteresis: 0.0,
+            hold_ms: 10.0,
+            attack_ms: 1.0,
+            release_ms: 100.0,
+            linking: 1.0,
+            attack_coeff: 0.0,
+            release_coeff: 0.0,
+            hold_samples: 0,
+            gains: [0.0; 2],
+            is_open: [true; 2],
+            hold_counters: [0; 2],
+            meter: GainReductionMeter::new(1),
+        }
+    }
+}
+
+impl Expander {
+    pub fn new(num_channels: usize) -> Self {
+        assert!((1..=2).contains(&num_channels), "num_channels must be 1 or 2");
+        Self {
+            num_channels,
+            meter: GainReductionMeter::new(num_channels),
+            ..Default::default()
+        }
+    }
+
+    /// Create a noise gate, i.e. an expander with an infinite ratio.
+    pub fn gate(num_channels: usize) -> Self {
+        let mut gate = Self::new(num_channels);
+        gate.set_ratio(f32::INFINITY);
+        gate
+    }
+
+    /// A handle to the gain reduction of each channel, which can be polled from another thread.
+    pub fn gain_reduction_meter(&self) -> GainReductionMeter {
+        self.meter.clone()
+    }
+
+    /// Set the threshold in dBFS.
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold;
+    }
+
+    /// Set the expansion ratio, which must be at least 1.0. `f32::INFINITY` makes a gate.
+    pub fn set_ratio(&mut self, ratio: f32) {
+        assert!(ratio >= 1.0);
+        self.ratio = ratio;
+    }
+
+    /// Set the range, i.e. the maximum attenuation in dB.
+    pub fn set_range(&mut self, range: f32) {
+        assert!(range >= 0.0);
+        self.range = range;
+    }
+
+    /// Set the hysteresis in dB. The gate closes only when the level falls below the threshold
+    /// minus the hysteresis.
+    pub fn set_hysteresis(&mut self, hysteresis: f32) {
+        assert!(hysteresis >= 0.0);
+        self.hysteresis = hysteresis;
+    }
+
+    /// Set the hold time in ms.
+    pub fn set_hold(&mut self, hold_ms: f32) {
+        assert!(hold_ms >= 0.0);
+        self.hold_ms = hold_ms;
+        self.update_coeffs();
+    }
+
+    /// Set the attack time in ms.
+    pub fn set_attack(&mut self, attack_ms: f32) {
+        assert!(attack_ms >= 0.0);
+        self.attack_ms = attack_ms;
+        self.update_coeffs();
+    }
+
+    /// Set the release time in ms.
+    pub fn set_release(&mut self, release_ms: f32) {
+        assert!(release_ms >= 0.0);
+        self.release_ms = release_ms;
+        self.update_coeffs();
+    }
+
+    /// Set the stereo linking in `[0, 1]`.
+    pub fn set_linking(&mut self, linking: f32) {
+        assert!((0.0..=1.0).contains(&linking));
+        self.linking = linking;
+    }
+
+    fn update_coeffs(&mut self) {
+        self.attack_coeff = time_constant_coeff(self.attack_ms, self.sample_rate);
+        self.release_coeff = time_constant_coeff(self.release_ms, self.sample_rate);
+        self.hold_samples = (self.hold_ms * self.sample_rate * 0.001).round() as usize;
+    }
+
+    fn compute_target_gain(&mut self, ch: usize, x: f32) -> f32 {
+        let level = level_db(x);
+
+        // Open above the threshold, close below the threshold minus the hysteresis
+        let gate_threshold = if self.is_open[ch] { self.threshold - self.hysteresis } else { self.threshold };
+        if level >= gate_threshold {
+            self.is_open[ch] = true;
+            self.hold_counters[ch] = self.hold_samples;
+            return 0.0;
+        }
+        if self.hold_counters[ch] > 0 {
+            self.hold_counters[ch] -= 1;
+            return 0.0;
+        }
+        self.is_open[ch] = false;
+
+        // The hysteresis only moves the decision, the expansion is always below the threshold
+        let target_gain = ((level - self.threshold) * (self.ratio - 1.0)).max(-self.range);
+        debug_assert!(target_gain <= 0.0);
+        target_gain
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer_view::BufferView;
+
+    const SAMPLE_RATE: f32 = 1000.0;
+
+    fn instant_expander(num_channels: usize) -> Expander {
+        let mut expander = Expander::new(num_channels);
+        expander.set_attack(0.0);
+        expander.set_release(0.0);
+        expander.set_hold(0.0);
+        expander.prepare(SAMPLE_RATE, 64);
+        expander
+    }
+
+    #[test]
+    fn static_curve() {
+        let mut expander = instant_expander(1);
+        expander.set_threshold(-20.0);
+        expander.set_ratio(3.0);
+        expander.set_range(50.0);
+
+        // 0.01 is -40 dB: 20 dB below the threshold becomes 60 dB, and -60 dB is limited by the
+        // range
+        let output = expander.process(BufferView::new(&[&[0.5, 0.1, 0.01, 0.001]]));
+        let expected = [0.5, 0.1, 0.01 * 0.01, 0.001 * 10.0f32.powf(-2.5)];
+        for (y, e) in output[0].iter().zip(expected) {
+            assert!((y - e).abs() < 1e-6 * e.max(1e-3), "{y} != {e}");
+        }
+    }
+
+    #[test]
+    fn gate_hysteresis_and_hold() {
+        let mut gate = Expander::gate(1);
+        gate.set_attack(0.0);
+        gate.set_release(0.0);
+        gate.set_hold(2.0);
+        gate.set_threshold(-20.0);
+        gate.set_hysteresis(6.0);
+        gate.set_range(60.0);
+        gate.prepare(SAMPLE_RATE, 64);
+
+        // -23 dB keeps the open gate open, -30 dB closes it after the hold time
+        let input = vec![0.2, 0.07, 0.07, 0.03, 0.03, 0.03, 0.03, 0.07, 0.2];
+        let output = gate.process(BufferView::new(&[&input]));
+        let closed = 10.0f32.powf(-3.0);
+        let gains: Vec<f32> = output[0].iter().zip(input.iter()).map(|(y, x)| y / x).collect();
+        crate::assert_all_close!(gains, [1.0, 1.0, 1.0, 1.0, 1.0, closed, closed, closed, 1.0]);
+
+        // With a finite ratio, the expansion does not step when the hold expires: -30.5 dB is
+        // 10.5 dB below the threshold, and -23 dB is 3 dB below it once the gate is closed
+        gate.set_ratio(2.0);
+        gate.reset();
+        let input = vec![0.2, 0.03, 0.03, 0.03, 0.03, 0.07];
+        let output = gate.process(BufferView::new(&[&input]));
+        let gains: Vec<f32> = output[0].iter().zip(input.iter()).map(|(y, x)| y / x).collect();
+        crate::assert_all_close!(gains, [1.0, 1.0, 1.0, 0.3, 0.3, 0.7]);
+    }
+
+    #[test]
+    fn attack_and_release() {
+        let mut expander = Expander::gate(1);
+        expander.set_hold(0.0);
+        expander.set_attack(1.0);
+        expander.set_release(10.0);
+        expander.set_range(20.0);
+        expander.prepare(SAMPLE_RATE, 64);
+
+        // Closing takes the release time
+        let output = expander.process(BufferView::new(&[&[1e-4; 10]]));
+        let gain_db = 20.0 * (output[0][9] / 1e-4).log10();
+        assert!((gain_db + 20.0 * (1.0 - (-1.0f32).exp())).abs() < 1e-3);
+
+        // Opening takes the attack time
+        let output = expander.process(BufferView::new(&[&[0.5]]));
+        let gain_db = 20.0 * (output[0][0] / 0.5).log10();
+        assert!(gain_db < 0.0 && gain_db > -20.0 * (-1.0f32).exp() * 0.9);
+    }
+
+    #[test]
+    fn stereo_linking() {
+        let mut expander = instant_expander(2);
+        expander.set_threshold(-20.0);
+        let output = expander.process(BufferView::new(&[&[0.5; 4], &[0.001; 4]]));
+        crate::assert_all_close!(output[1], [0.001; 4]);
+
+        expander.set_linking(0.0);
+        let output = expander.process(BufferView::new(&[&[0.5; 4], &[0.001; 4]]));
+        assert!(output[1].iter().all(|&y| y < 0.001 * 0.1));
+    }
+}
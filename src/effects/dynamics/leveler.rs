@@ -0,0 +1,393 @@
+//! Automatic gain control.
+
+use std::f64::consts::PI;
+
+use crate::buffer_view::BufferViewMut;
+use crate::effects::Effect;
+use crate::filter::{Filter, SosFilter};
+use crate::filter::design::{SecondOrderSection, SosCoeffs};
+use super::meter::GainReductionMeter;
+use super::{smooth_gain, time_constant_coeff};
+
+/// The length of the measurement blocks.
+const BLOCK_MS: f32 = 100.0;
+/// The number of blocks in the measurement window, i.e. the 3 s of the short-term loudness.
+const WINDOW_BLOCKS: usize = 30;
+/// The offset of the loudness in LUFS from the K-weighted mean square in dB.
+const LUFS_OFFSET: f32 = -0.691;
+
+/// How an [`AutoLeveler`] measures the loudness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LoudnessMeasure {
+    /// Short-term loudness in LUFS as defined by ITU-R BS.1770 and EBU R 128: the K-weighted mean
+    /// square over 3 s, summed over the channels.
+    #[default]
+    ShortTermLufs,
+    /// The RMS level in dBFS over 3 s, averaged over the channels.
+    Rms,
+}
+
+/// Automatic gain control: a slow gain that brings the loudness of the signal to a target, e.g.
+/// to level the voices of a call recording before compressing it.
+///
+/// The loudness is measured in 100 ms blocks over a sliding window of 3 s. The blocks quieter
+/// than the gate threshold are silence: they are left out of the measurement, so pauses do not
+/// pull the gain up and the noise floor is not boosted. When the window only holds silence, the
+/// gain returns to 0 dB, or it stays where it is if freeze on silence is on. The gain is
+/// limited by the maximum boost and cut, and all channels share it.
+///
+/// The attack is the time to reduce the gain and the release is the time to raise it. There is
+/// no lookahead, so the leveler should be followed by a compressor or a limiter to catch the
+/// peaks.
+///
+/// The gain reduction can be polled from another thread through
+/// [`gain_reduction_meter`](Self::gain_reduction_meter); boosts read as 0 dB.
+pub struct AutoLeveler {
+    num_channels: usize,
+    sample_rate: f32,
+
+    measure: LoudnessMeasure,
+    target: f32,
+    max_boost: f32,
+    max_cut: f32,
+    gate_threshold: f32,
+    freeze_on_silence: bool,
+    attack_ms: f32,
+    release_ms: f32,
+
+    attack_coeff: f32,
+    release_coeff: f32,
+    block_len: usize,
+
+    // Internal states
+    /// The K-weighting filters of each channel.
+    weighting_filters: Vec<SosFilter>,
+    /// The sum of the powers of the current block, summed over the channels.
+    block_sum: f64,
+    block_count: usize,
+    /// The mean powers of the last blocks, `None` for silence.
+    block_powers: [Option<f64>; WINDOW_BLOCKS],
+    block_index: usize,
+    /// Whether the last block was silence.
+    silent: bool,
+    /// The target gain in dB.
+    target_gain: f32,
+    /// The smoothed gain in dB.
+    gain: f32,
+    meter: GainReductionMeter,
+}
+
+impl Effect for AutoLeveler {
+    fn prepare(&mut self, sample_rate: f32, _block_size: usize) {
+        assert!(sample_rate > 0.0);
+        self.sample_rate = sample_rate;
+        self.block_len = (BLOCK_MS * sample_rate * 0.001).round() as usize;
+        self.weighting_filters = (0..self.num_channels)
+            .map(|_| SosFilter::new(k_weighting(sample_rate)))
+            .collect();
+        self.update_coeffs();
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        self.weighting_filters.iter_mut().for_each(|filter| filter.reset());
+        self.block_sum = 0.0;
+        self.block_count = 0;
+        self.block_powers = [None; WINDOW_BLOCKS];
+        self.block_index = 0;
+        self.silent = true;
+        self.target_gain = 0.0;
+        self.gain = 0.0;
+        self.meter.reset();
+    }
+
+    fn process_inplace<'outer, 'inner>(
+        &mut self,
+        buffer: &'outer mut BufferViewMut<'outer, 'inner>,
+    ) {
+        // Check if the effect is prepared
+        if self.sample_rate == 0.0 {
+            return;
+        }
+
+        debug_assert!(buffer.num_channels() == self.num_channels);
+        let num_samples = buffer.num_samples();
+        let channels: &mut [&mut [f32]] = buffer.channels_mut();
+
+        let mut peak_reduction = 0.0f32;
+        for n in 0..num_samples {
+            // Measure
+            for (channel, filter) in channels.iter().zip(self.weighting_filters.iter_mut()) {
+                let x = match self.measure {
+                    LoudnessMeasure::ShortTermLufs => filter.process_sample(channel[n]),
+                    LoudnessMeasure::Rms => channel[n],
+                };
+                self.block_sum += (x * x) as f64;
+            }
+            self.block_count += 1;
+            if self.block_count == self.block_len {
+                self.end_block();
+            }
+
+            // Level
+            if !(self.freeze_on_silence && self.silent) {
+                self.gain = smooth_gain(self.target_gain, self.gain, self.attack_coeff, self.release_coeff);
+            }
+            peak_reduction = peak_reduction.max(-self.gain);
+            let gain = 10.0f32.powf(self.gain / 20.0);
+            channels.iter_mut().for_each(|channel| channel[n] *= gain);
+        }
+
+        for ch in 0..self.num_channels {
+            self.meter.update(ch, -self.gain, peak_reduction);
+        }
+    }
+}
+
+impl Default for AutoLeveler {
+    fn default() -> Self {
+        Self {
+            num_channels: 1,
+            sample_rate: 0.0,
+            measure: LoudnessMeasure::ShortTermLufs,
+            target: -18.0,
+            max_boost: 12.0,
+            max_cut: 12.0,
+            gate_threshold: -50.0,
+            freeze_on_silence: true,
+            attack_ms: 1000.0,
+            release_ms: 3000.0,
+            attack_coeff: 0.0,
+            release_coeff: 0.0,
+            block_len: 0,
+            weighting_filters: vec![],
+            block_sum: 0.0,
+            block_count: 0,
+            block_powers: [None; WINDOW_BLOCKS],
+            block_index: 0,
+            silent: true,
+            target_gain: 0.0,
+            gain: 0.0,
+            meter: GainReductionMeter::new(1),
+        }
+    }
+}
+
+impl AutoLeveler {
+    pub fn new(num_channels: usize) -> Self {
+        assert!(num_channels > 0, "num_channels must be greater than 0");
+        Self {
+            num_channels,
+            meter: GainReductionMeter::new(num_channels),
+            ..Default::default()
+        }
+    }
+
+    /// A handle to the gain reduction, which can be polled from another thread. All channels have
+    /// the same reading.
+    pub fn gain_reduction_meter(&self) -> GainReductionMeter {
+        self.meter.clone()
+    }
+
+    /// Set how the loudness is measured. The target and the gate threshold are in the unit of
+    /// the measure. The measurement window is cleared.
+    pub fn set_measure(&mut self, measure: LoudnessMeasure) {
+        self.measure = measure;
+        self.block_powers = [None; WINDOW_BLOCKS];
+    }
+
+    /// Set the target loudness in LUFS or dBFS.
+    pub fn set_target(&mut self, target: f32) {
+        self.target = target;
+    }
+
+    /// Set the maximum boost in dB.
+    pub fn set_max_boost(&mut self, max_boost: f32) {
+        assert!(max_boost >= 0.0);
+        self.max_boost = max_boost;
+    }
+
+    /// Set the maximum cut in dB, as a positive value.
+    pub fn set_max_cut(&mut self, max_cut: f32) {
+        assert!(max_cut >= 0.0);
+        self.max_cut = max_cut;
+    }
+
+    /// Set the loudness in LUFS or dBFS below which a block is silence.
+    pub fn set_gate_threshold(&mut self, gate_threshold: f32) {
+        self.gate_threshold = gate_threshold;
+    }
+
+    /// Hold the gain during silence instead of moving it.
+    pub fn set_freeze_on_silence(&mut self, freeze_on_silence: bool) {
+        self.freeze_on_silence = freeze_on_silence;
+    }
+
+    pub fn set_attack(&mut self, attack_ms: f32) {
+        self.attack_ms = attack_ms;
+        self.update_coeffs();
+    }
+
+    pub fn set_release(&mut self, release_ms: f32) {
+        self.release_ms = release_ms;
+        self.update_coeffs();
+    }
+
+    /// The loudness measured over the last 3 s without the silence, or `None` if they are all
+    /// silence.
+    pub fn loudness(&self) -> Option<f32> {
+        let (sum, count) = self
+            .block_powers
+            .iter()
+            .flatten()
+            .fold((0.0, 0), |(sum, count), power| (sum + power, count + 1));
+        (count > 0).then(|| self.power_to_db(sum / count as f64))
+    }
+
+    /// The current gain in dB.
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    /// Store the power of the finished block and update the target gain.
+    fn end_block(&mut self) {
+        let power = self.block_sum / self.block_count as f64;
+        self.block_sum = 0.0;
+        self.block_count = 0;
+
+        self.silent = self.power_to_db(power) < self.gate_threshold;
+        self.block_powers[self.block_index] = (!self.silent).then_some(power);
+        self.block_index = (self.block_index + 1) % WINDOW_BLOCKS;
+
+        match self.loudness() {
+            Some(loudness) => self.target_gain = (self.target - loudness).clamp(-self.max_cut, self.max_boost),
+            None if !self.freeze_on_silence => self.target_gain = 0.0,
+            None => {}
+        }
+    }
+
+    /// The loudness in LUFS or dBFS of a power summed over the channels.
+    fn power_to_db(&self, power: f64) -> f32 {
+        match self.measure {
+            LoudnessMeasure::ShortTermLufs => LUFS_OFFSET + 10.0 * power.log10() as f32,
+            LoudnessMeasure::Rms => 10.0 * (power / self.num_channels as f64).log10() as f32,
+        }
+    }
+
+    fn update_coeffs(&mut self) {
+        self.attack_coeff = time_constant_coeff(self.attack_ms, self.sample_rate);
+        self.release_coeff = time_constant_coeff(self.release_ms, self.sample_rate);
+    }
+}
+
+/// The K-weighting filter of ITU-R BS.1770: a high shelf modelling the head, followed by a
+/// highpass (the RLB weighting). The analog prototypes are fitted to the coefficients given by the
+/// standard at 48 kHz, so the filter can be designed at any sample rate.
+///
+/// See Brecht De Man, "Evaluation of Implementations of the EBU R128 Loudness Measurement".
+fn k_weighting(sample_rate: f32) -> SosCoeffs {
+    let sample_rate = sample_rate as f64;
+
+    // High shelf
+    let k = (PI * 1681.974450955533 / sample_rate).tan();
+    let q = 0.7071752369554196;
+    let vh = 10.0f64.powf(3.999843853973347 / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let shelf = SecondOrderSection::new(
+        (vh + vb * k / q + k * k) as f32,
+        (2.0 * (k * k - vh)) as f32,
+        (vh - vb * k / q + k * k) as f32,
+        (1.0 + k / q + k * k) as f32,
+        (2.0 * (k * k - 1.0)) as f32,
+        (1.0 - k / q + k * k) as f32,
+    );
+
+    // Highpass, with the numerator of the standard
+    let k = (PI * 38.13547087602444 / sample_rate).tan();
+    let q = 0.5003270373238773;
+    let a0 = 1.0 + k / q + k * k;
+    let highpass = SecondOrderSection::new(
+        a0 as f32,
+        (-2.0 * a0) as f32,
+        a0 as f32,
+        a0 as f32,
+        (2.0 * (k * k - 1.0)) as f32,
+        (1.0 - k / q + k * k) as f32,
+    );
+
+    SosCoeffs::new(vec![shelf, highpass])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer_view::BufferView;
+    use crate::utilities::testing::{peak, sine};
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    #[test]
+    fn loudness() {
+        // A 1 kHz sine at 0 dBFS in one channel is -3.01 LUFS
+        let mut leveler = AutoLeveler::new(2);
+        leveler.set_max_boost(0.0);
+        leveler.prepare(SAMPLE_RATE, 512);
+        leveler.process(BufferView::new(&[&sine(1000.0, 0.1, SAMPLE_RATE, 144000), &[0.0; 144000]]));
+        assert!((leveler.loudness().unwrap() + 23.01).abs() < 0.05);
+
+        // The RMS of a sine is -3 dB, averaged over the channels
+        let mut leveler = AutoLeveler::new(2);
+        leveler.set_measure(LoudnessMeasure::Rms);
+        leveler.prepare(SAMPLE_RATE, 512);
+        let input = sine(100.0, 0.1, SAMPLE_RATE, 144000);
+        leveler.process(BufferView::new(&[&input, &input]));
+        assert!((leveler.loudness().unwrap() + 23.01).abs() < 0.05);
+
+        // The K-weighting attenuates the low frequencies
+        let mut leveler = AutoLeveler::new(1);
+        leveler.prepare(SAMPLE_RATE, 512);
+        leveler.process(BufferView::new(&[&sine(20.0, 0.1, SAMPLE_RATE, 144000)]));
+        assert!(leveler.loudness().unwrap() < -30.0);
+    }
+
+    #[test]
+    fn leveling() {
+        let mut leveler = AutoLeveler::new(1);
+        leveler.set_target(-23.0);
+        leveler.set_attack(200.0);
+        leveler.set_release(200.0);
+        leveler.prepare(SAMPLE_RATE, 512);
+
+        // -29 LUFS is boosted by 6 dB
+        let output = leveler.process(BufferView::new(&[&sine(1000.0, 0.05, SAMPLE_RATE, 240000)]));
+        assert!((leveler.gain() - 6.0).abs() < 0.05);
+        assert!((peak(&output[0][192000..]) - 0.1).abs() < 0.001);
+
+        // -3 LUFS is cut by the maximum cut
+        leveler.set_max_cut(6.0);
+        let meter = leveler.gain_reduction_meter();
+        leveler.process(BufferView::new(&[&sine(1000.0, 1.0, SAMPLE_RATE, 240000)]));
+        assert!((leveler.gain() + 6.0).abs() < 0.05);
+        assert!((meter.current(0) - 6.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn silence() {
+        let mut leveler = AutoLeveler::new(1);
+        leveler.set_target(-23.0);
+        leveler.set_attack(200.0);
+        leveler.set_release(200.0);
+        leveler.prepare(SAMPLE_RATE, 512);
+        leveler.process(BufferView::new(&[&sine(1000.0, 0.05, SAMPLE_RATE, 240000)]));
+
+        // The gain is frozen during a pause with a noise floor below the gate
+        leveler.process(BufferView::new(&[&sine(1000.0, 0.0001, SAMPLE_RATE, 240000)]));
+        assert!(leveler.loudness().is_none());
+        assert!((leveler.gain() - 6.0).abs() < 0.05);
+
+        // Without the freeze, it returns to 0 dB once the window only holds silence
+        leveler.set_freeze_on_silence(false);
+        leveler.process(BufferView::new(&[&sine(1000.0, 0.0001, SAMPLE_RATE, 240000)]));
+        assert!(leveler.gain().abs() < 0.05);
+    }
+}
@@ -0,0 +1,359 @@
+//! Lookahead brickwall limiter with true-peak detection.
+
+use std::collections::VecDeque;
+
+use crate::buffer_view::BufferViewMut;
+use crate::effects::Effect;
+use super::detector::TruePeakDetector;
+use super::meter::GainReductionMeter;
+use super::time_constant_coeff;
+
+const MAX_LOOKAHEAD: f32 = 20.0; // ms
+
+const DEFAULT_CEILING: f32 = -1.0; // dBFS
+const DEFAULT_RELEASE: f32 = 100.0; // ms
+const DEFAULT_LOOKAHEAD: f32 = 5.0; // ms
+
+/// Lookahead brickwall limiter for any number of channels.
+///
+/// The gain needed to keep each sample under the ceiling is held at its minimum over the
+/// lookahead window, released with a one-pole filter and then averaged over the lookahead window.
+/// The signal is delayed by the lookahead, so the gain has reached the required value when a peak
+/// comes out, and the output never exceeds the ceiling. The delay is reported by
+/// [`Effect::latency`].
+///
+/// With true-peak detection, the peaks between the samples are estimated by 4x oversampling with a
+/// windowed sinc interpolator, as recommended by ITU-R BS.1770, which adds 6 samples of latency.
+///
+/// The gain reduction can be polled from another thread through
+/// [`gain_reduction_meter`](Self::gain_reduction_meter).
+pub struct Limiter {
+    num_channels: usize,
+    sample_rate: f32,
+
+    ceiling: f32,
+    release_ms: f32,
+    lookahead_ms: f32,
+    true_peak: bool,
+    linking: f32,
+
+    ceiling_gain: f32,
+    release_coeff: f32,
+    lookahead_samples: usize,
+
+    // Internal states for each channel
+    true_peak_detectors: Vec<TruePeakDetector>,
+    delay_lines: Vec<Vec<f32>>,
+    delay_index: usize,
+    /// Monotonic queues of `(sample index, gain)` for the minimum over the lookahead window.
+    min_queues: Vec<VecDeque<(usize, f32)>>,
+    released_gains: Vec<f32>,
+    /// Ring buffers of the released gains for the moving average.
+    average_buffers: Vec<Vec<f32>>,
+    average_sums: Vec<f64>,
+    average_index: usize,
+    sample_index: usize,
+    /// Scratch buffer for the gains of each sample.
+    gains: Vec<f32>,
+    /// The lowest applied gain of each channel in the current block.
+    min_gains: Vec<f32>,
+    meter: GainReductionMeter,
+}
+
+impl Effect for Limiter {
+    fn prepare(&mut self, sample_rate: f32, _block_size: usize) {
+        assert!(sample_rate > 0.0);
+        self.sample_rate = sample_rate;
+
+        // Allocate for the maximum lookahead
+        let max_lookahead = (MAX_LOOKAHEAD * sample_rate * 0.001).ceil() as usize;
+        let delay_len = (max_lookahead + TruePeakDetector::DELAY + 1).next_power_of_two();
+        self.delay_lines = vec![vec![0.0; delay_len]; self.num_channels];
+        self.min_queues = vec![VecDeque::with_capacity(max_lookahead + 1); self.num_channels];
+        self.average_buffers = vec![vec![1.0; max_lookahead + 1]; self.num_channels];
+
+        self.update_coeffs();
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        self.true_peak_detectors.iter_mut().for_each(|detector| detector.reset());
+        self.delay_lines.iter_mut().for_each(|line| line.fill(0.0));
+        self.delay_index = 0;
+        self.min_queues.iter_mut().for_each(|queue| queue.clear());
+        self.released_gains.fill(1.0);
+        let window = self.lookahead_samples + 1;
+        self.average_buffers.iter_mut().for_each(|buffer| buffer.fill(1.0));
+        self.average_sums.fill(window as f64);
+        self.average_index = 0;
+        self.sample_index = 0;
+        self.meter.reset();
+    }
+
+    fn latency(&self) -> usize {
+        self.lookahead_samples + self.detector_delay()
+    }
+
+    fn process_inplace<'outer, 'inner>(
+        &mut self,
+        buffer: &'outer mut BufferViewMut<'outer, 'inner>,
+    ) {
+        // Check if the effect is prepared
+        if self.sample_rate == 0.0 {
+            return;
+        }
+
+        debug_assert!(buffer.num_channels() == self.num_channels);
+        let num_samples = buffer.num_samples();
+        let channels: &mut [&mut [f32]] = buffer.channels_mut();
+
+        let window = self.lookahead_samples + 1;
+        let delay = self.latency();
+        let delay_mask = self.delay_lines[0].len() - 1;
+        self.min_gains.fill(1.0);
+        for n in 0..num_samples {
+            // The gains to keep the peaks under the ceiling
+            for (ch, channel) in channels.iter().enumerate() {
+                let peak = self.detect_peak(ch, channel[n]);
+                self.gains[ch] = (self.ceiling_gain / peak).min(1.0);
+            }
+            let min_gain = self.gains.iter().copied().fold(1.0, f32::min);
+            for gain in self.gains.iter_mut() {
+                *gain += self.linking * (min_gain - *gain);
+            }
+
+            for (ch, channel) in channels.iter_mut().enumerate() {
+                // Minimum over the lookahead window
+                let queue = &mut self.min_queues[ch];
+                while queue.back().is_some_and(|&(_, g)| g >= self.gains[ch]) {
+                    queue.pop_back();
+                }
+                queue.push_back((self.sample_index, self.gains[ch]));
+                while queue.front().is_some_and(|&(i, _)| i + window <= self.sample_index) {
+                    queue.pop_front();
+                }
+                let held = queue.front().unwrap().1;
+
+                // Instant attack and smooth release
+                let released = &mut self.released_gains[ch];
+                *released = if held < *released {
+                    held
+                } else {
+                    held + self.release_coeff * (*released - held)
+                };
+
+                // Moving average over the lookahead window
+                let average_buffer = &mut self.average_buffers[ch];
+                self.average_sums[ch] += (*released - average_buffer[self.average_index]) as f64;
+                average_buffer[self.average_index] = *released;
+                let gain = (self.average_sums[ch] / window as f64) as f32;
+                self.min_gains[ch] = self.min_gains[ch].min(gain);
+
+                // Delay the signal by the lookahead
+                let line = &mut self.delay_lines[ch];
+                line[self.delay_index] = channel[n];
+                let delayed = line[self.delay_index.wrapping_sub(delay) & delay_mask];
+                channel[n] = delayed * gain;
+            }
+
+            self.delay_index = (self.delay_index + 1) & delay_mask;
+            self.average_index = (self.average_index + 1) % window;
+            self.sample_index += 1;
+        }
+
+        for ch in 0..self.num_channels {
+            let gain = (self.average_sums[ch] / window as f64) as f32;
+            self.meter.update(ch, -20.0 * gain.log10(), -20.0 * self.min_gains[ch].log10());
+        }
+    }
+}
+
+impl Limiter {
+    pub fn new(num_channels: usize) -> Self {
+        assert!(num_channels > 0, "num_channels must be greater than 0");
+        let mut limiter = Self {
+            num_channels,
+            sample_rate: 0.0,
+            ceiling: DEFAULT_CEILING,
+            release_ms: DEFAULT_RELEASE,
+            lookahead_ms: DEFAULT_LOOKAHEAD,
+            true_peak: true,
+            linking: 1.0,
+            ceiling_gain: 0.0,
+            release_coeff: 0.0,
+            lookahead_samples: 0,
+            true_peak_detectors: (0..num_channels).map(|_| TruePeakDetector::new()).collect(),
+            delay_lines: vec![],
+            delay_index: 0,
+            min_queues: vec![],
+            released_gains: vec![1.0; num_channels],
+            average_buffers: vec![],
+            average_sums: vec![1.0; num_channels],
+            average_index: 0,
+            sample_index: 0,
+            gains: vec![1.0; num_channels],
+            min_gains: vec![1.0; num_channels],
+            meter: GainReductionMeter::new(num_channels),
+        };
+        limiter.update_coeffs();
+        limiter
+    }
+
+    /// A handle to the gain reduction of each channel, which can be polled from another thread.
+    pub fn gain_reduction_meter(&self) -> GainReductionMeter {
+        self.meter.clone()
+    }
+
+    /// Set the ceiling in dBFS.
+    pub fn set_ceiling(&mut self, ceiling: f32) {
+        self.ceiling = ceiling;
+        self.update_coeffs();
+    }
+
+    /// Set the release time in ms.
+    pub fn set_release(&mut self, release_ms: f32) {
+        assert!(release_ms >= 0.0);
+        self.release_ms = release_ms;
+        self.update_coeffs();
+    }
+
+    /// Set the lookahead time in ms, which changes the latency and resets the limiter.
+    pub fn set_lookahead(&mut self, lookahead_ms: f32) {
+        assert!((0.0..=MAX_LOOKAHEAD).contains(&lookahead_ms));
+        self.lookahead_ms = lookahead_ms;
+        self.update_coeffs();
+        self.reset();
+    }
+
+    /// Enable or disable the true-peak detection, which changes the latency and resets the
+    /// limiter.
+    pub fn set_true_peak(&mut self, true_peak: bool) {
+        self.true_peak = true_peak;
+        self.reset();
+    }
+
+    /// Set the linking between the channels in `[0, 1]`. With full linking, all channels are
+    /// attenuated by the same gain, which preserves the stereo image.
+    pub fn set_linking(&mut self, linking: f32) {
+        assert!((0.0..=1.0).contains(&linking));
+        self.linking = linking;
+    }
+
+    fn update_coeffs(&mut self) {
+        self.ceiling_gain = 10.0f32.powf(self.ceiling / 20.0);
+        self.release_coeff = time_constant_coeff(self.release_ms, self.sample_rate);
+        self.lookahead_samples = (self.lookahead_ms * self.sample_rate * 0.001).round() as usize;
+    }
+
+    fn detector_delay(&self) -> usize {
+        if self.true_peak { TruePeakDetector::DELAY } else { 0 }
+    }
+
+    /// Push a sample to the detector and return the peak at the detector delay.
+    fn detect_peak(&mut self, ch: usize, x: f32) -> f32 {
+        if self.true_peak {
+            self.true_peak_detectors[ch].process(x)
+        } else {
+            x.abs()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer_view::BufferView;
+    use crate::utilities::testing::peak;
+    use rand::Rng;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    #[test]
+    fn latency() {
+        let mut limiter = Limiter::new(1);
+        limiter.prepare(SAMPLE_RATE, 512);
+        assert_eq!(limiter.latency(), 240 + TruePeakDetector::DELAY);
+        limiter.set_true_peak(false);
+        assert_eq!(limiter.latency(), 240);
+
+        // Quiet signals are only delayed
+        let mut input = vec![0.0; 300];
+        input[0] = 0.5;
+        let output = limiter.process(BufferView::new(&[&input]));
+        assert!((output[0][240] - 0.5).abs() < 1e-6);
+        assert!(output[0].iter().enumerate().all(|(i, &y)| i == 240 || y == 0.0));
+    }
+
+    #[test]
+    fn ceiling() {
+        let mut rng = rand::rng();
+        let input: Vec<f32> = (0..48000).map(|_| rng.random_range(-4.0..4.0)).collect();
+
+        let mut limiter = Limiter::new(1);
+        limiter.set_true_peak(false);
+        limiter.set_ceiling(-3.0);
+        limiter.prepare(SAMPLE_RATE, 512);
+        let output = limiter.process(BufferView::new(&[&input]));
+        let ceiling = 10.0f32.powf(-3.0 / 20.0);
+        assert!(output[0].iter().all(|&y| y.abs() <= ceiling * (1.0 + 1e-5)));
+    }
+
+    #[test]
+    fn true_peak() {
+        // A sine at fs/4 sampled at 45 degrees: the sample peak is -3 dB and the true peak is 0 dB
+        let input: Vec<f32> = (0..4800)
+            .map(|n| (std::f64::consts::PI * (n as f64 / 2.0 + 0.25)).sin() as f32)
+            .collect();
+        let gain = |true_peak: bool| {
+            let mut limiter = Limiter::new(1);
+            limiter.set_true_peak(true_peak);
+            limiter.set_ceiling(-1.0);
+            limiter.prepare(SAMPLE_RATE, 512);
+            let output = limiter.process(BufferView::new(&[&input]));
+            peak(&output[0][4000..]) / 0.5f32.sqrt()
+        };
+        assert!((gain(false) - 1.0).abs() < 1e-5);
+        let ceiling = 10.0f32.powf(-1.0 / 20.0);
+        assert!((gain(true) - ceiling).abs() < 0.02);
+    }
+
+    #[test]
+    fn gain_reduction_meter() {
+        let mut limiter = Limiter::new(2);
+        limiter.set_true_peak(false);
+        limiter.set_ceiling(0.0);
+        limiter.set_linking(0.0);
+        limiter.prepare(SAMPLE_RATE, 512);
+        let meter = limiter.gain_reduction_meter();
+
+        let _ = limiter.process(BufferView::new(&[&[4.0; 480], &[0.5; 480]]));
+        assert!((meter.current(0) - 12.041).abs() < 1e-2);
+        assert!((meter.block_peak(0) - 12.041).abs() < 1e-2);
+        assert_eq!(meter.current(1), 0.0);
+
+        // The peak is held until it is taken
+        let _ = limiter.process(BufferView::new(&[&[0.0; 48000], &[0.0; 48000]]));
+        assert!(meter.current(0) < 0.01);
+        assert!((meter.take_peak(0) - 12.041).abs() < 1e-2);
+        assert_eq!(meter.take_peak(0), 0.0);
+    }
+
+    #[test]
+    fn linking() {
+        let loud = vec![2.0; 4800];
+        let quiet = vec![0.1; 4800];
+        let gains = |linking: f32| {
+            let mut limiter = Limiter::new(2);
+            limiter.set_true_peak(false);
+            limiter.set_ceiling(0.0);
+            limiter.set_linking(linking);
+            limiter.prepare(SAMPLE_RATE, 512);
+            let output = limiter.process(BufferView::new(&[&loud, &quiet]));
+            (output[0][4799] / 2.0, output[1][4799] / 0.1)
+        };
+        let (left, right) = gains(1.0);
+        assert!((left - 0.5).abs() < 1e-4 && (right - 0.5).abs() < 1e-4);
+        let (left, right) = gains(0.0);
+        assert!((left - 0.5).abs() < 1e-4 && (right - 1.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,105 @@
+//! Gain-reduction metering of the dynamics processors.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// A handle to the gain reduction of a dynamics processor, which can be polled from another
+/// thread, e.g. by a UI, while the processor is running.
+///
+/// The processor owns a meter and updates it at the end of every processed block; the clones
+/// returned by its `gain_reduction_meter` method share the same readings. The gain reduction is
+/// positive in dB, e.g. 6.0 means the signal is attenuated by 6 dB.
+///
+/// There is one reading per channel, except for a [`DynamicEq`](super::DynamicEq), whose channels
+/// share the gains of its bands: its readings are indexed by band.
+///
+/// The readings are stored in atomics, so updating and polling them never blocks. The channels
+/// are updated one by one, so a reading may mix two consecutive blocks.
+#[derive(Debug, Clone)]
+pub struct GainReductionMeter {
+    channels: Arc<[ChannelReadings]>,
+}
+
+#[derive(Debug, Default)]
+struct ChannelReadings {
+    /// The gain reduction at the end of the last block.
+    current: AtomicU32,
+    /// The peak gain reduction in the last block.
+    block_peak: AtomicU32,
+    /// The peak gain reduction since the last call of `take_peak`.
+    held_peak: AtomicU32,
+}
+
+impl GainReductionMeter {
+    pub(super) fn new(num_channels: usize) -> Self {
+        Self {
+            channels: (0..num_channels).map(|_| ChannelReadings::default()).collect(),
+        }
+    }
+
+    pub fn num_channels(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// The gain reduction in dB of a channel at the end of the last block.
+    pub fn current(&self, channel: usize) -> f32 {
+        f32::from_bits(self.channels[channel].current.load(Ordering::Relaxed))
+    }
+
+    /// The peak gain reduction in dB of a channel in the last block.
+    pub fn block_peak(&self, channel: usize) -> f32 {
+        f32::from_bits(self.channels[channel].block_peak.load(Ordering::Relaxed))
+    }
+
+    /// The peak gain reduction in dB of a channel since the last call of this method, which resets
+    /// it. Polling this method at any rate never misses a peak.
+    pub fn take_peak(&self, channel: usize) -> f32 {
+        f32::from_bits(self.channels[channel].held_peak.swap(0, Ordering::Relaxed))
+    }
+
+    /// Update the readings of a channel at the end of a block.
+    pub(super) fn update(&self, channel: usize, current: f32, block_peak: f32) {
+        // The bits of non-negative floats are ordered as the floats, so the peak can be held with
+        // an integer maximum. Negative values, including -0.0, are stored as 0.0.
+        let non_negative = |x: f32| if x > 0.0 { x } else { 0.0 };
+        let readings = &self.channels[channel];
+        let block_peak = non_negative(block_peak);
+        readings.current.store(non_negative(current).to_bits(), Ordering::Relaxed);
+        readings.block_peak.store(block_peak.to_bits(), Ordering::Relaxed);
+        readings.held_peak.fetch_max(block_peak.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Clear the readings.
+    pub(super) fn reset(&self) {
+        for readings in self.channels.iter() {
+            readings.current.store(0, Ordering::Relaxed);
+            readings.block_peak.store(0, Ordering::Relaxed);
+            readings.held_peak.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readings() {
+        let meter = GainReductionMeter::new(2);
+        let ui = meter.clone();
+        meter.update(0, 3.0, 12.0);
+        meter.update(0, 1.0, 6.0);
+        meter.update(1, -0.0, 0.0);
+        assert_eq!(ui.current(0), 1.0);
+        assert_eq!(ui.block_peak(0), 6.0);
+        assert_eq!(ui.take_peak(0), 12.0);
+        assert_eq!(ui.take_peak(0), 0.0);
+        assert_eq!(ui.current(1), 0.0);
+
+        // The readings can be polled from another thread
+        let handle = std::thread::spawn(move || ui.block_peak(0));
+        assert_eq!(handle.join().unwrap(), 6.0);
+        meter.reset();
+        assert_eq!(meter.current(0), 0.0);
+    }
+}
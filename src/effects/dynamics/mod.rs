@@ -1,166 +1,77 @@
-
-use crate::buffer_view::{BufferView, BufferViewMut};
-use crate::effects::Effect;
+//! Dynamics processors: effects whose gain depends on the level of the input signal.
+//!
+//! The processors share the same building blocks: the level of each sample is measured in dB by
+//! [`level_db`], a static gain curve maps it to a target gain in dB, and the target gain is
+//! smoothed by [`smooth_gain`] with separate time constants for falling and rising gains.
+//!
+//! Each processor owns a [`GainReductionMeter`] and updates it after every block, and a handle to
+//! it is returned by its `gain_reduction_meter` method.
+
+mod compressor;
+mod deesser;
+mod detector;
+mod dynamic_eq;
+mod expander;
+mod leveler;
+mod limiter;
+mod meter;
+mod multiband;
+mod transient;
+
+pub use compressor::{Compressor, GainSmoothing, LinkMode, SidechainFilter, Topology};
+pub use deesser::{DeEsser, DeEsserMode};
+pub use detector::Detector;
+pub use dynamic_eq::{DynamicEq, DynamicEqBand, EqBandShape};
+pub use expander::Expander;
+pub use leveler::{AutoLeveler, LoudnessMeasure};
+pub use limiter::Limiter;
+pub use meter::GainReductionMeter;
+pub use multiband::{CrossoverMode, MultibandCompressor};
+pub use transient::TransientShaper;
 
 const MIN_AMPLITUDE: f32 = 1e-10;
 
-pub struct Compressor {
-    num_channels: usize,
-    sample_rate: f32,
-    block_size: usize,
-
-    threshold: f32,
-    ratio: f32,
-    attack_ms: f32,
-    release_ms: f32,
-    linking: f32,
-    makeup_gain: f32,
-
-    attack_coeff: f32,
-    release_coeff: f32,
-
-    left_gain: f32,
-    right_gain: f32,
+/// The level of a sample in dBFS, limited to -200 dB at the bottom. The level can be above 0 dBFS.
+fn level_db(x: f32) -> f32 {
+    x.abs().max(MIN_AMPLITUDE).log10() * 20.0
 }
 
-impl Effect for Compressor {
-    fn prepare(&mut self, sample_rate: f32, block_size: usize) {
-        self.sample_rate = sample_rate;
-        self.block_size = block_size;
-
-        let samples_per_ms = sample_rate * 0.001;
-        self.attack_coeff = (-1.0 / (self.attack_ms * samples_per_ms)).exp();
-        self.release_coeff = (-1.0 / (self.release_ms * samples_per_ms)).exp();
-    }
-
-    fn reset(&mut self) {
-        self.left_gain = 0.0;
-        self.right_gain = 0.0;
-    }
-
-    fn process_inplace<'outer, 'inner>(
-        &mut self,
-        buffer: &'outer mut BufferViewMut<'outer, 'inner>,
-    ) {
-        // Check if the effect is prepared
-        if self.sample_rate == 0.0 {
-            return;
-        }
-
-        debug_assert!(buffer.num_channels() == self.num_channels);
-        let num_channels = buffer.num_channels().clamp(1, 2);
-        let num_samples = buffer.num_samples();
-
-        // Iterate over samples
-        if num_channels == 1 {
-            let channel = buffer.channel_mut(0);
-            for sample in channel.iter_mut() {
-                let target_gain = self.compute_target_gain(*sample);
-                self.left_gain = self.smooth_gain(target_gain, self.left_gain);
-                *sample *= 10.0f32.powf(self.left_gain / 20.0);
-            }
-        } else {
-            let channels = buffer.channels_mut();
-            for n in 0..num_samples {
-                let mut left_target_gain = self.compute_target_gain(channels[0][n]);
-                let mut right_target_gain = self.compute_target_gain(channels[1][n]);
-
-                if left_target_gain < right_target_gain {
-                    right_target_gain = right_target_gain + self.linking * (left_target_gain - right_target_gain);
-                } else {
-                    left_target_gain = left_target_gain + self.linking * (right_target_gain - left_target_gain);
-                }
-
-                self.left_gain = self.smooth_gain(left_target_gain, self.left_gain);
-                channels[0][n] *= 10.0f32.powf((self.left_gain + self.makeup_gain) / 20.0);
-
-                self.right_gain = self.smooth_gain(right_target_gain, self.right_gain);
-                channels[1][n] *= 10.0f32.powf((self.right_gain + self.makeup_gain) / 20.0);
-            }
-        }
-    }
-
-    fn process(&mut self, input: BufferView) -> Vec<Vec<f32>> {
-        let mut output = vec![vec![0.0; input.num_samples()]; input.num_channels()];
-        let mut slices: Vec<&mut [f32]> = output.iter_mut().map(|ch| ch.as_mut_slice()).collect();
-        self.process_inplace(&mut BufferViewMut::new(&mut slices));
-        output
-    }
+/// The coefficient of a one-pole smoothing filter with the given time constant in ms. A time
+/// constant of 0 ms gives 0.0, i.e. no smoothing.
+pub(super) fn time_constant_coeff(time_ms: f32, sample_rate: f32) -> f32 {
+    (-1.0 / (time_ms * sample_rate * 0.001)).exp()
 }
 
-impl Default for Compressor {
-    fn default() -> Self {
-        Self {
-            num_channels: 1,
-            sample_rate: 0.0,
-            block_size: 0,
-            threshold: -12.0,
-            ratio: 2.0,
-            attack_ms: 5.0,
-            release_ms: 50.0,
-            linking: 1.0,
-            makeup_gain: 0.0,
-            attack_coeff: 0.0,
-            release_coeff: 0.0,
-            left_gain: 0.0,
-            right_gain: 0.0,
-        }
-    }
+/// Move the current gain towards the target gain (both in dB) with the one-pole coefficient
+/// `falling_coeff` if the gain is decreasing, and `rising_coeff` otherwise.
+fn smooth_gain(target_gain: f32, current_gain: f32, falling_coeff: f32, rising_coeff: f32) -> f32 {
+    let coeff = if target_gain < current_gain { falling_coeff } else { rising_coeff };
+    target_gain + coeff * (current_gain - target_gain)
 }
 
-impl Compressor {
-    pub fn new(num_channels: usize) -> Self {
-        assert!((1..=2).contains(&num_channels), "num_channels must be 1 or 2");
-        Self {
-            num_channels,
-            ..Default::default()
-        }
-    }
-
-    pub fn set_threshold(&mut self, threshold: f32) {
-        self.threshold = threshold;
-    }
-
-    pub fn set_ratio(&mut self, ratio: f32) {
-        self.ratio = ratio;
-    }
-
-    pub fn set_attack(&mut self, attack_ms: f32) {
-        self.attack_ms = attack_ms;
-    }
-
-    pub fn set_release(&mut self, release_ms: f32) {
-        self.release_ms = release_ms;
-    }
-
-    pub fn set_linking(&mut self, linking: f32) {
-        self.linking = linking;
-    }
-
-    pub fn set_makeup_gain(&mut self, makeup_gain: f32) {
-        self.makeup_gain = makeup_gain;
-    }
-
-    fn compute_target_gain(&self, x: f32) -> f32 {
-        debug_assert!(self.ratio > 1.0);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let level = x.abs().clamp(MIN_AMPLITUDE, 1.0).log10() * 20.0;
-        let target_gain = {
-            if level > self.threshold {
-                (self.threshold - level) * (1.0 - 1.0 / self.ratio)
-            } else {
-                0.0
-            }
-        };
-        debug_assert!(target_gain <= 0.0);
-        target_gain
+    #[test]
+    fn test_level_db() {
+        assert_eq!(level_db(1.0), 0.0);
+        assert!((level_db(-0.1) + 20.0).abs() < 1e-5);
+        assert_eq!(level_db(0.0), -200.0);
+        assert!((level_db(10.0) - 20.0).abs() < 1e-5);
     }
 
-    fn smooth_gain(&self, target_gain: f32, current_gain: f32) -> f32 {
-        if target_gain < current_gain {
-            target_gain + self.attack_coeff * (current_gain - target_gain)
-        } else {
-            target_gain + self.release_coeff * (current_gain - target_gain)
-        }
+    #[test]
+    fn test_smooth_gain() {
+        let falling = time_constant_coeff(1.0, 1000.0);
+        let rising = time_constant_coeff(10.0, 1000.0);
+        assert!((falling - (-1.0f32).exp()).abs() < 1e-6);
+        assert_eq!(time_constant_coeff(0.0, 1000.0), 0.0);
+
+        // One time constant reaches 1 - 1/e of the way
+        let gain = smooth_gain(-10.0, 0.0, falling, rising);
+        assert!((gain + 10.0 * (1.0 - (-1.0f32).exp())).abs() < 1e-4);
+        let gain = smooth_gain(0.0, -10.0, falling, rising);
+        assert!((gain + 10.0 * (-0.1f32).exp()).abs() < 1e-4);
     }
 }
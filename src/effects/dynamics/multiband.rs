@@ -0,0 +1,467 @@
+//! Multiband compressor.
+
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use crate::buffer_view::BufferViewMut;
+use crate::effects::Effect;
+use crate::filter::{Filter, PartitionedConvolver, SosFilter};
+use crate::filter::design::{Band, FirCoeffs, SecondOrderSection, SosCoeffs};
+use crate::filter::design::biquad::{self, Width};
+use crate::filter::design::fir::firwin;
+use super::compressor::Compressor;
+
+const MIN_BANDS: usize = 2;
+const MAX_BANDS: usize = 5;
+
+/// The length of the linear-phase crossover filters in periods of the lowest crossover frequency.
+const FIR_PERIODS: f32 = 8.0;
+const MAX_FIR_TAPS: usize = 16383;
+/// The partitioning of the linear-phase crossover convolution.
+const FIR_MIN_BLOCK_SIZE: usize = 64;
+const FIR_MAX_BLOCK_SIZE: usize = 2048;
+
+/// The crossover filters of a [`MultibandCompressor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CrossoverMode {
+    /// 4th-order Linkwitz-Riley crossovers. The bands are in phase with each other, and their sum
+    /// is an allpass filter, i.e. it has a flat magnitude but not a linear phase. No latency.
+    #[default]
+    LinkwitzRiley,
+    /// Linear-phase FIR crossovers, whose sum is a pure delay. The latency is half the length of
+    /// the filters, which is 4 periods of the lowest crossover frequency.
+    LinearPhase,
+}
+
+/// Multiband compressor: the signal is split into 2 to 5 bands by crossover filters, each band
+/// is processed by its own [`Compressor`], and the bands are summed back. When no band
+/// compresses, the output has the same magnitude response as the input.
+///
+/// The compressors of the bands are configured through [`band_mut`](Self::band_mut). A band can
+/// be bypassed (not compressed) or soloed (only the soloed bands are output).
+///
+/// With [`CrossoverMode::LinkwitzRiley`], the bands are split one after another: the lowest band
+/// is split off first and the higher bands are split from the rest. Each band is then passed
+/// through the allpass filters of the crossovers above it, so that all the bands have the same
+/// phase response and sum to an allpass filter.
+pub struct MultibandCompressor {
+    num_channels: usize,
+    sample_rate: f32,
+    block_size: usize,
+
+    crossovers: Vec<f32>,
+    mode: CrossoverMode,
+    bands: Vec<Compressor>,
+    solo: Vec<bool>,
+    bypass: Vec<bool>,
+
+    // Internal states for each channel
+    splitters: Vec<Splitter>,
+    /// The band signals, `[band][channel][sample]`.
+    band_buffers: Vec<Vec<Vec<f32>>>,
+}
+
+/// The crossover filters of a channel.
+enum Splitter {
+    LinkwitzRiley {
+        /// The lowpass filters of the crossovers.
+        lowpasses: Vec<SosFilter>,
+        /// The highpass filters of the crossovers.
+        highpasses: Vec<SosFilter>,
+        /// The allpass filters of the crossovers above each band, except the highest one.
+        allpasses: Vec<SosFilter>,
+    },
+    LinearPhase {
+        /// The band filters, which sum to a delayed impulse.
+        convolvers: Vec<PartitionedConvolver>,
+        latency: usize,
+    },
+}
+
+impl Effect for MultibandCompressor {
+    fn prepare(&mut self, sample_rate: f32, block_size: usize) {
+        assert!(sample_rate > 0.0);
+        assert!(block_size > 0);
+        self.sample_rate = sample_rate;
+        self.block_size = block_size;
+
+        let num_bands = self.num_bands();
+        self.band_buffers = vec![vec![vec![0.0; block_size]; self.num_channels]; num_bands];
+        self.bands.iter_mut().for_each(|band| band.prepare(sample_rate, block_size));
+        self.build_splitters();
+    }
+
+    fn reset(&mut self) {
+        self.splitters.iter_mut().for_each(|splitter| splitter.reset());
+        self.bands.iter_mut().for_each(|band| band.reset());
+    }
+
+    fn latency(&self) -> usize {
+        self.splitters.first().map_or(0, |splitter| splitter.latency())
+    }
+
+    fn process_inplace<'outer, 'inner>(
+        &mut self,
+        buffer: &'outer mut BufferViewMut<'outer, 'inner>,
+    ) {
+        // Check if the effect is prepared
+        if self.sample_rate == 0.0 {
+            return;
+        }
+
+        debug_assert_eq!(buffer.num_channels(), self.num_channels);
+        let num_samples = buffer.num_samples();
+        let channels: &mut [&mut [f32]] = buffer.channels_mut();
+
+        // Process in chunks of the prepared block size
+        let mut start = 0;
+        while start < num_samples {
+            let end = (start + self.block_size).min(num_samples);
+            self.process_chunk(channels, start, end);
+            start = end;
+        }
+    }
+}
+
+impl MultibandCompressor {
+    /// Create a multiband compressor.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_channels` - The number of channels.
+    /// * `crossovers` - The 1 to 4 crossover frequencies in Hz in ascending order, which split
+    ///   the signal into 2 to 5 bands.
+    ///
+    /// # Panics
+    ///
+    /// * If `num_channels` is zero.
+    /// * If the number of crossovers is not in `1..=4`, or they are not positive and ascending.
+    pub fn new(num_channels: usize, crossovers: Vec<f32>) -> Self {
+        assert!(num_channels > 0, "num_channels must be greater than 0");
+        check_crossovers(&crossovers);
+        let num_bands = crossovers.len() + 1;
+        Self {
+            num_channels,
+            sample_rate: 0.0,
+            block_size: 0,
+            crossovers,
+            mode: CrossoverMode::LinkwitzRiley,
+            bands: (0..num_bands).map(|_| Compressor::new(num_channels)).collect(),
+            solo: vec![false; num_bands],
+            bypass: vec![false; num_bands],
+            splitters: vec![],
+            band_buffers: vec![],
+        }
+    }
+
+    pub fn num_bands(&self) -> usize {
+        self.bands.len()
+    }
+
+    /// The compressor of a band. Band 0 is the lowest.
+    pub fn band(&self, band: usize) -> &Compressor {
+        &self.bands[band]
+    }
+
+    /// The compressor of a band, for setting its parameters. Band 0 is the lowest.
+    pub fn band_mut(&mut self, band: usize) -> &mut Compressor {
+        &mut self.bands[band]
+    }
+
+    /// Set the crossover frequencies in Hz. The number of bands cannot be changed.
+    ///
+    /// The Linkwitz-Riley filters keep their states, while the linear-phase filters are
+    /// redesigned and reset.
+    ///
+    /// # Panics
+    ///
+    /// * If the number of crossovers differs from the current one.
+    /// * If the crossovers are not positive and ascending, or not below the Nyquist frequency.
+    pub fn set_crossovers(&mut self, crossovers: Vec<f32>) {
+        assert_eq!(crossovers.len(), self.crossovers.len(), "The number of bands must not change");
+        check_crossovers(&crossovers);
+        self.crossovers = crossovers;
+        if self.sample_rate == 0.0 {
+            return;
+        }
+        match self.mode {
+            CrossoverMode::LinkwitzRiley => {
+                let (lowpasses, highpasses, allpasses) = self.linkwitz_riley_coeffs();
+                for splitter in self.splitters.iter_mut() {
+                    splitter.set_linkwitz_riley_coeffs(&lowpasses, &highpasses, &allpasses);
+                }
+            }
+            CrossoverMode::LinearPhase => self.build_splitters(),
+        }
+    }
+
+    /// Set the crossover mode, which rebuilds the crossover filters and changes the latency.
+    pub fn set_crossover_mode(&mut self, mode: CrossoverMode) {
+        self.mode = mode;
+        if self.sample_rate > 0.0 {
+            self.build_splitters();
+        }
+    }
+
+    /// Solo a band. When any band is soloed, only the soloed bands are output.
+    pub fn set_solo(&mut self, band: usize, solo: bool) {
+        self.solo[band] = solo;
+    }
+
+    /// Bypass the compressor of a band. The band is still split and summed.
+    pub fn set_bypass(&mut self, band: usize, bypass: bool) {
+        self.bypass[band] = bypass;
+    }
+
+    fn process_chunk(&mut self, channels: &mut [&mut [f32]], start: usize, end: usize) {
+        let len = end - start;
+
+        // Split
+        for (ch, (channel, splitter)) in channels.iter().zip(self.splitters.iter_mut()).enumerate() {
+            splitter.split(&channel[start..end], &mut self.band_buffers, ch);
+        }
+
+        // Compress
+        for (band, compressor) in self.bands.iter_mut().enumerate() {
+            if self.bypass[band] {
+                continue;
+            }
+            compressor.process_channels(&mut self.band_buffers[band], len, None);
+        }
+
+        // Sum
+        let any_solo = self.solo.iter().any(|&solo| solo);
+        for (ch, channel) in channels.iter_mut().enumerate() {
+            let output = &mut channel[start..end];
+            output.fill(0.0);
+            for (band, buffers) in self.band_buffers.iter().enumerate() {
+                if any_solo && !self.solo[band] {
+                    continue;
+                }
+                for (y, x) in output.iter_mut().zip(buffers[ch].iter()) {
+                    *y += x;
+                }
+            }
+        }
+    }
+
+    fn build_splitters(&mut self) {
+        self.splitters = match self.mode {
+            CrossoverMode::LinkwitzRiley => {
+                let (lowpasses, highpasses, allpasses) = self.linkwitz_riley_coeffs();
+                let filters = |coeffs: &[SosCoeffs]| -> Vec<SosFilter> {
+                    coeffs.iter().map(|c| SosFilter::new(c.clone())).collect()
+                };
+                (0..self.num_channels)
+                    .map(|_| Splitter::LinkwitzRiley {
+                        lowpasses: filters(&lowpasses),
+                        highpasses: filters(&highpasses),
+                        allpasses: filters(&allpasses),
+                    })
+                    .collect()
+            }
+            CrossoverMode::LinearPhase => {
+                let bands = self.linear_phase_coeffs();
+                let latency = (bands[0].b().len() - 1) / 2;
+                (0..self.num_channels)
+                    .map(|_| Splitter::LinearPhase {
+                        convolvers: bands
+                            .iter()
+                            .map(|b| PartitionedConvolver::non_uniform(b.clone(), FIR_MIN_BLOCK_SIZE, FIR_MAX_BLOCK_SIZE))
+                            .collect(),
+                        latency,
+                    })
+                    .collect()
+            }
+        };
+    }
+
+    /// The 4th-order Linkwitz-Riley lowpass and highpass filters of the crossovers, and the
+    /// allpass filters of the crossovers above each band except the highest one.
+    fn linkwitz_riley_coeffs(&self) -> (Vec<SosCoeffs>, Vec<SosCoeffs>, Vec<SosCoeffs>) {
+        let q = Width::Q(FRAC_1_SQRT_2);
+        let squared = |section: SecondOrderSection| SosCoeffs::new(vec![section.clone(), section]);
+        let lowpasses = self.crossovers
+            .iter()
+            .map(|&freq| squared(biquad::lowpass(self.sample_rate, freq, q)))
+            .collect();
+        let highpasses = self.crossovers
+            .iter()
+            .map(|&freq| squared(biquad::highpass(self.sample_rate, freq, q)))
+            .collect();
+
+        // The sum of the lowpass and highpass filters is a 2nd-order allpass filter
+        let allpasses = (0..self.crossovers.len())
+            .map(|band| {
+                let sections: Vec<SecondOrderSection> = self.crossovers[band + 1..]
+                    .iter()
+                    .map(|&freq| biquad::allpass(self.sample_rate, freq, q))
+                    .collect();
+                if sections.is_empty() {
+                    SecondOrderSection::new(1.0, 0.0, 0.0, 1.0, 0.0, 0.0).into()
+                } else {
+                    SosCoeffs::new(sections)
+                }
+            })
+            .collect();
+        (lowpasses, highpasses, allpasses)
+    }
+
+    /// The linear-phase band filters: the differences of the lowpass filters at the adjacent
+    /// crossovers, so that they sum to a delayed impulse.
+    fn linear_phase_coeffs(&self) -> Vec<FirCoeffs> {
+        let num_taps = ((FIR_PERIODS * self.sample_rate / self.crossovers[0]) as usize / 2 * 2 + 1).min(MAX_FIR_TAPS);
+        let mut lowpasses: Vec<Vec<f32>> = self.crossovers
+            .iter()
+            .map(|&freq| firwin(num_taps, Band::Lowpass(freq), None, self.sample_rate).b().to_vec())
+            .collect();
+        let mut impulse = vec![0.0; num_taps];
+        impulse[num_taps / 2] = 1.0;
+        lowpasses.push(impulse);
+
+        let mut previous = vec![0.0; num_taps];
+        lowpasses
+            .into_iter()
+            .map(|lowpass| {
+                let band: Vec<f32> = lowpass.iter().zip(previous.iter()).map(|(a, b)| a - b).collect();
+                previous = lowpass;
+                FirCoeffs::new(band)
+            })
+            .collect()
+    }
+}
+
+impl Splitter {
+    /// Split the input of channel `ch` into `bands[band][ch]`.
+    fn split(&mut self, input: &[f32], bands: &mut [Vec<Vec<f32>>], ch: usize) {
+        match self {
+            Splitter::LinkwitzRiley { lowpasses, highpasses, allpasses } => {
+                let num_crossovers = lowpasses.len();
+                for (n, &x) in input.iter().enumerate() {
+                    let mut rest = x;
+                    for i in 0..num_crossovers {
+                        let low = lowpasses[i].process_sample(rest);
+                        rest = highpasses[i].process_sample(rest);
+                        bands[i][ch][n] = allpasses[i].process_sample(low);
+                    }
+                    bands[num_crossovers][ch][n] = rest;
+                }
+            }
+            Splitter::LinearPhase { convolvers, .. } => {
+                for (convolver, band) in convolvers.iter_mut().zip(bands.iter_mut()) {
+                    let output = &mut band[ch][..input.len()];
+                    output.copy_from_slice(input);
+                    convolver.process_inplace(output);
+                }
+            }
+        }
+    }
+
+    fn set_linkwitz_riley_coeffs(&mut self, lows: &[SosCoeffs], highs: &[SosCoeffs], alls: &[SosCoeffs]) {
+        if let Splitter::LinkwitzRiley { lowpasses, highpasses, allpasses } = self {
+            for (filters, coeffs) in [(lowpasses, lows), (highpasses, highs), (allpasses, alls)] {
+                for (filter, coeffs) in filters.iter_mut().zip(coeffs.iter()) {
+                    filter.set_coeffs(coeffs.clone());
+                }
+            }
+        }
+    }
+
+    fn latency(&self) -> usize {
+        match self {
+            Splitter::LinkwitzRiley { .. } => 0,
+            Splitter::LinearPhase { latency, .. } => *latency,
+        }
+    }
+
+    fn reset(&mut self) {
+        match self {
+            Splitter::LinkwitzRiley { lowpasses, highpasses, allpasses } => {
+                lowpasses.iter_mut().chain(highpasses).chain(allpasses).for_each(|filter| filter.reset());
+            }
+            Splitter::LinearPhase { convolvers, .. } => {
+                convolvers.iter_mut().for_each(|convolver| convolver.reset());
+            }
+        }
+    }
+}
+
+fn check_crossovers(crossovers: &[f32]) {
+    assert!(
+        (MIN_BANDS..=MAX_BANDS).contains(&(crossovers.len() + 1)),
+        "The number of bands must be in 2..=5"
+    );
+    assert!(crossovers[0] > 0.0, "The crossover frequencies must be positive");
+    assert!(
+        crossovers.windows(2).all(|pair| pair[0] < pair[1]),
+        "The crossover frequencies must be in ascending order"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer_view::BufferView;
+    use crate::filter::design::response::FrequencyResponse;
+    use crate::utilities::testing::{impulse, peak, sine};
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    fn neutral(mode: CrossoverMode) -> MultibandCompressor {
+        let mut compressor = MultibandCompressor::new(1, vec![150.0, 1000.0, 4000.0, 10000.0]);
+        compressor.set_crossover_mode(mode);
+        for band in 0..compressor.num_bands() {
+            compressor.set_bypass(band, true);
+        }
+        compressor.prepare(SAMPLE_RATE, 256);
+        compressor
+    }
+
+    #[test]
+    fn linkwitz_riley_is_flat() {
+        let mut compressor = neutral(CrossoverMode::LinkwitzRiley);
+        assert_eq!(compressor.latency(), 0);
+        let output = compressor.process(BufferView::new(&[&impulse(8192)]));
+        let freqs: Vec<f32> = (1..100).map(|i| i as f32 * 230.0).collect();
+        for magnitude in FirCoeffs::new(output[0].clone()).magnitude_db(&freqs, SAMPLE_RATE) {
+            assert!(magnitude.abs() < 0.01, "{magnitude}");
+        }
+    }
+
+    #[test]
+    fn linear_phase_is_a_delay() {
+        let mut compressor = neutral(CrossoverMode::LinearPhase);
+        let latency = compressor.latency();
+        assert_eq!(latency, 2560 / 2);
+        let output = compressor.process(BufferView::new(&[&impulse(4096)]));
+        for (n, &y) in output[0].iter().enumerate() {
+            let expected = if n == latency { 1.0 } else { 0.0 };
+            assert!((y - expected).abs() < 1e-4, "{n}: {y}");
+        }
+    }
+
+    #[test]
+    fn solo() {
+        let mut compressor = neutral(CrossoverMode::LinkwitzRiley);
+        compressor.set_solo(4, true);
+        let output = compressor.process(BufferView::new(&[&impulse(8192)]));
+        let magnitudes = FirCoeffs::new(output[0].clone()).magnitude_db(&[100.0, 15000.0], SAMPLE_RATE);
+        assert!(magnitudes[0] < -60.0);
+        assert!(magnitudes[1] < 0.0 && magnitudes[1] > -2.0);
+    }
+
+    #[test]
+    fn band_compression() {
+        let mut compressor = MultibandCompressor::new(1, vec![1000.0]);
+        compressor.band_mut(0).set_threshold(0.0);
+        compressor.band_mut(1).set_threshold(-40.0);
+        compressor.band_mut(1).set_ratio(10.0);
+        compressor.prepare(SAMPLE_RATE, 256);
+
+        // Only the high band is compressed
+        let low = compressor.process(BufferView::new(&[&sine(100.0, 0.5, SAMPLE_RATE, 9600)]));
+        compressor.reset();
+        let high = compressor.process(BufferView::new(&[&sine(8000.0, 0.5, SAMPLE_RATE, 9600)]));
+        assert!((peak(&low[0][4800..]) - 0.5).abs() < 0.01);
+        assert!(peak(&high[0][4800..]) < 0.1);
+    }
+}
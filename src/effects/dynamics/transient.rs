@@ -0,0 +1,321 @@
+//! Transient shaper.
+
+use crate::buffer_view::BufferViewMut;
+use crate::effects::Effect;
+use super::meter::GainReductionMeter;
+use super::{MIN_AMPLITUDE, smooth_gain, time_constant_coeff};
+
+/// The time constants of the envelope followers in ms.
+const FAST_ATTACK: f32 = 1.0;
+const SLOW_ATTACK: f32 = 30.0;
+const FAST_RELEASE: f32 = 50.0;
+const SLOW_RELEASE: f32 = 500.0;
+/// The difference in dB between the envelopes at which the attack or sustain gain is fully
+/// applied.
+const FULL_DIFFERENCE: f32 = 12.0;
+/// The amplitude above which the output is soft clipped.
+const CLIP_KNEE: f32 = 0.7;
+
+/// Transient shaper: the attack and the sustain of the sounds are boosted or cut independently of
+/// their level, e.g. to add punch to drums or to tame the room in a recording.
+///
+/// The amplitude of each channel is followed by a fast envelope, which is in turn followed by an
+/// envelope with a slow attack and one with a slow release. While the fast envelope is above the
+/// slow-attack one, the signal is in an attack; while it is below the slow-release one, the signal
+/// is decaying. The differences in dB, up to 12 dB, scale the attack and sustain gains.
+///
+/// With linking, the channels follow the loudest one, so a transient in one channel shapes all of
+/// them. The boosted samples can be soft clipped above -3 dBFS so that the boosts never exceed
+/// 0 dBFS.
+///
+/// The attenuation can be polled from another thread through
+/// [`gain_reduction_meter`](Self::gain_reduction_meter).
+pub struct TransientShaper {
+    num_channels: usize,
+    sample_rate: f32,
+
+    attack_gain: f32,
+    sustain_gain: f32,
+    linking: f32,
+    clipping: bool,
+
+    fast_attack_coeff: f32,
+    slow_attack_coeff: f32,
+    fast_release_coeff: f32,
+    slow_release_coeff: f32,
+
+    // Internal states for each channel
+    /// The envelopes of the amplitude with fast attack and release.
+    fast_envelopes: Vec<f32>,
+    /// The envelopes of the fast envelopes with slow attack and instant release.
+    slow_attack_envelopes: Vec<f32>,
+    /// The envelopes of the fast envelopes with instant attack and slow release.
+    slow_release_envelopes: Vec<f32>,
+    meter: GainReductionMeter,
+
+    // Scratch buffers for each channel
+    amplitudes: Vec<f32>,
+    gains: Vec<f32>,
+    peak_reductions: Vec<f32>,
+}
+
+impl Effect for TransientShaper {
+    fn prepare(&mut self, sample_rate: f32, _block_size: usize) {
+        self.sample_rate = sample_rate;
+        self.fast_attack_coeff = time_constant_coeff(FAST_ATTACK, sample_rate);
+        self.slow_attack_coeff = time_constant_coeff(SLOW_ATTACK, sample_rate);
+        self.fast_release_coeff = time_constant_coeff(FAST_RELEASE, sample_rate);
+        self.slow_release_coeff = time_constant_coeff(SLOW_RELEASE, sample_rate);
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        self.fast_envelopes.fill(0.0);
+        self.slow_attack_envelopes.fill(0.0);
+        self.slow_release_envelopes.fill(0.0);
+        self.meter.reset();
+    }
+
+    fn process_inplace<'outer, 'inner>(
+        &mut self,
+        buffer: &'outer mut BufferViewMut<'outer, 'inner>,
+    ) {
+        // Check if the effect is prepared
+        if self.sample_rate == 0.0 {
+            return;
+        }
+
+        debug_assert!(buffer.num_channels() == self.num_channels);
+        let num_samples = buffer.num_samples();
+        let channels: &mut [&mut [f32]] = buffer.channels_mut();
+
+        self.peak_reductions.fill(0.0);
+        for n in 0..num_samples {
+            let mut max_amplitude = 0.0f32;
+            for (channel, amplitude) in channels.iter().zip(self.amplitudes.iter_mut()) {
+                *amplitude = channel[n].abs();
+                max_amplitude = max_amplitude.max(*amplitude);
+            }
+
+            for (ch, channel) in channels.iter_mut().enumerate() {
+                let amplitude = self.amplitudes[ch] + self.linking * (max_amplitude - self.amplitudes[ch]);
+                let gain = self.compute_gain(ch, amplitude);
+                self.gains[ch] = gain;
+                self.peak_reductions[ch] = self.peak_reductions[ch].max(-gain);
+
+                let x = channel[n];
+                let y = x * 10.0f32.powf(gain / 20.0);
+                // Only the boost is clipped, so the output never falls below the input
+                channel[n] = if self.clipping && gain > 0.0 && y.abs() > x.abs() {
+                    soft_clip(y).abs().max(x.abs()).copysign(y)
+                } else {
+                    y
+                };
+            }
+        }
+
+        for (ch, (&gain, &peak_reduction)) in self.gains.iter().zip(self.peak_reductions.iter()).enumerate() {
+            self.meter.update(ch, -gain, peak_reduction);
+        }
+    }
+}
+
+impl Default for TransientShaper {
+    fn default() -> Self {
+        Self {
+            num_channels: 1,
+            sample_rate: 0.0,
+            attack_gain: 0.0,
+            sustain_gain: 0.0,
+            linking: 1.0,
+            clipping: true,
+            fast_attack_coeff: 0.0,
+            slow_attack_coeff: 0.0,
+            fast_release_coeff: 0.0,
+            slow_release_coeff: 0.0,
+            fast_envelopes: vec![0.0],
+            slow_attack_envelopes: vec![0.0],
+            slow_release_envelopes: vec![0.0],
+            meter: GainReductionMeter::new(1),
+            amplitudes: vec![0.0],
+            gains: vec![0.0],
+            peak_reductions: vec![0.0],
+        }
+    }
+}
+
+impl TransientShaper {
+    pub fn new(num_channels: usize) -> Self {
+        assert!(num_channels > 0, "num_channels must be greater than 0");
+        Self {
+            num_channels,
+            fast_envelopes: vec![0.0; num_channels],
+            slow_attack_envelopes: vec![0.0; num_channels],
+            slow_release_envelopes: vec![0.0; num_channels],
+            meter: GainReductionMeter::new(num_channels),
+            amplitudes: vec![0.0; num_channels],
+            gains: vec![0.0; num_channels],
+            peak_reductions: vec![0.0; num_channels],
+            ..Default::default()
+        }
+    }
+
+    /// A handle to the attenuation of each channel, which can be polled from another thread.
+    pub fn gain_reduction_meter(&self) -> GainReductionMeter {
+        self.meter.clone()
+    }
+
+    /// Set the gain of the attacks in dB. Positive values add punch, negative values soften them.
+    pub fn set_attack_gain(&mut self, attack_gain: f32) {
+        self.attack_gain = attack_gain;
+    }
+
+    /// Set the gain of the sustains in dB. Positive values bring out the tails and the room,
+    /// negative values tighten the sounds.
+    pub fn set_sustain_gain(&mut self, sustain_gain: f32) {
+        self.sustain_gain = sustain_gain;
+    }
+
+    /// Set the linking in `[0, 1]`.
+    pub fn set_linking(&mut self, linking: f32) {
+        assert!((0.0..=1.0).contains(&linking));
+        self.linking = linking;
+    }
+
+    /// Soft clip the boosted samples above -3 dBFS so that the boosts never exceed 0 dBFS. The
+    /// samples that are not boosted are never clipped, even above 0 dBFS.
+    pub fn set_clipping(&mut self, clipping: bool) {
+        self.clipping = clipping;
+    }
+
+    /// Update the envelopes of a channel with the amplitude and return the gain in dB.
+    fn compute_gain(&mut self, ch: usize, amplitude: f32) -> f32 {
+        // The envelopes fall in the release phase. The slow envelopes follow the fast one, so
+        // they only differ from it while it rises or falls.
+        let fast = smooth_gain(
+            amplitude,
+            self.fast_envelopes[ch],
+            self.fast_release_coeff,
+            self.fast_attack_coeff,
+        );
+        let slow_attack = smooth_gain(fast, self.slow_attack_envelopes[ch], 0.0, self.slow_attack_coeff);
+        let slow_release = smooth_gain(fast, self.slow_release_envelopes[ch], self.slow_release_coeff, 0.0);
+        self.fast_envelopes[ch] = fast;
+        self.slow_attack_envelopes[ch] = slow_attack;
+        self.slow_release_envelopes[ch] = slow_release;
+
+        let difference_db = |a: f32, b: f32| 20.0 * (a.max(MIN_AMPLITUDE) / b.max(MIN_AMPLITUDE)).log10();
+        let attack = (difference_db(fast, slow_attack) / FULL_DIFFERENCE).clamp(0.0, 1.0);
+        let sustain = (difference_db(slow_release, fast) / FULL_DIFFERENCE).clamp(0.0, 1.0);
+        attack * self.attack_gain + sustain * self.sustain_gain
+    }
+}
+
+/// Leave the samples below [`CLIP_KNEE`] unchanged and bend the larger ones smoothly towards 1.0.
+fn soft_clip(x: f32) -> f32 {
+    let amplitude = x.abs();
+    if amplitude <= CLIP_KNEE {
+        x
+    } else {
+        let headroom = 1.0 - CLIP_KNEE;
+        (CLIP_KNEE + headroom * ((amplitude - CLIP_KNEE) / headroom).tanh()).copysign(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer_view::BufferView;
+    use crate::utilities::testing::{peak, sine};
+    use std::f32::consts::PI;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    /// A 200 Hz tone starting after 100 ms and decaying by 20 dB per 100 ms, as a drum hit.
+    fn hit(amplitude: f32) -> Vec<f32> {
+        (0..24000)
+            .map(|n| {
+                let t = n as f32 / SAMPLE_RATE - 0.1;
+                if t < 0.0 {
+                    0.0
+                } else {
+                    amplitude * 0.1f32.powf(t * 10.0) * (2.0 * PI * 200.0 * t).sin()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn neutral() {
+        let mut shaper = TransientShaper::new(1);
+        shaper.prepare(SAMPLE_RATE, 512);
+        let input = hit(0.5);
+        let output = shaper.process(BufferView::new(&[&input]));
+        crate::assert_all_close!(output[0], input);
+
+        // The clipping leaves the peaks above its knee unchanged
+        shaper.reset();
+        let input = hit(0.9);
+        let output = shaper.process(BufferView::new(&[&input]));
+        crate::assert_all_close!(output[0], input);
+    }
+
+    #[test]
+    fn attack_and_sustain() {
+        let input = hit(0.1);
+        // The first 5 ms of the hit, and the tail after 100 ms
+        let (attack, tail) = (4800..5040, 9600..24000);
+
+        let mut shaper = TransientShaper::new(1);
+        shaper.set_attack_gain(12.0);
+        shaper.prepare(SAMPLE_RATE, 512);
+        let output = shaper.process(BufferView::new(&[&input]));
+        assert!(peak(&output[0][attack.clone()]) > 2.0 * peak(&input[attack.clone()]));
+        assert!(peak(&output[0][tail.clone()]) < 1.1 * peak(&input[tail.clone()]));
+
+        let mut shaper = TransientShaper::new(1);
+        shaper.set_sustain_gain(-12.0);
+        shaper.prepare(SAMPLE_RATE, 512);
+        let meter = shaper.gain_reduction_meter();
+        let output = shaper.process(BufferView::new(&[&input]));
+        assert!(peak(&output[0][attack.clone()]) > 0.95 * peak(&input[attack]));
+        assert!(peak(&output[0][tail.clone()]) < 0.5 * peak(&input[tail]));
+        assert!(meter.block_peak(0) > 6.0);
+    }
+
+    #[test]
+    fn linking() {
+        let mut shaper = TransientShaper::new(2);
+        shaper.set_attack_gain(-12.0);
+        shaper.prepare(SAMPLE_RATE, 512);
+
+        // The hit on the left also softens the steady tone on the right
+        let steady = sine(1000.0, 0.01, SAMPLE_RATE, 24000);
+        let output = shaper.process(BufferView::new(&[&hit(0.5), &steady]));
+        assert!(peak(&output[1][4800..5040]) < 0.5 * peak(&steady[4800..5040]));
+
+        shaper.set_linking(0.0);
+        shaper.reset();
+        let output = shaper.process(BufferView::new(&[&hit(0.5), &steady]));
+        assert!(peak(&output[1][4800..5040]) > 0.95 * peak(&steady[4800..5040]));
+    }
+
+    #[test]
+    fn clipping() {
+        let mut shaper = TransientShaper::new(1);
+        shaper.set_attack_gain(12.0);
+        shaper.prepare(SAMPLE_RATE, 512);
+        let output = shaper.process(BufferView::new(&[&hit(0.9)]));
+        assert!(peak(&output[0]) <= 1.0);
+
+        // Cut attacks are not clipped
+        shaper.set_attack_gain(-12.0);
+        shaper.reset();
+        let input = hit(0.9);
+        let output = shaper.process(BufferView::new(&[&input]));
+        assert!(output[0].iter().zip(input.iter()).all(|(y, x)| y.abs() <= x.abs()));
+        assert!(peak(&output[0][9600..]) > 0.99 * peak(&input[9600..]));
+        assert_eq!(soft_clip(-0.5), -0.5);
+        assert!(soft_clip(10.0) <= 1.0);
+    }
+}
@@ -0,0 +1,354 @@
+//! Pitch-shifting harmonizer and voice doubler.
+
+use std::f32::consts::{FRAC_PI_4, PI};
+
+use crate::buffer_view::BufferViewMut;
+use crate::effects::Effect;
+use crate::effects::delay::DelayLine;
+
+const MAX_VOICES: usize = 8;
+const MAX_VOICE_DELAY: f32 = 100.0; // ms
+/// The length of the grains of the pitch shifter.
+const GRAIN_TIME: f32 = 40.0; // ms
+
+const DEFAULT_DRY_GAIN: f32 = 1.0;
+const DEFAULT_VOICE_LEVEL: f32 = -6.0; // dB
+
+/// A voice of a [`Harmonizer`]: a pitch-shifted copy of the input with its own interval, detune,
+/// delay, pan and level.
+pub struct HarmonizerVoice {
+    num_channels: usize,
+    sample_rate: f32,
+
+    interval: f32,
+    detune: f32,
+    delay: f32,
+    pan: f32,
+    level: f32,
+
+    // Dependent parameters
+    grain_samples: f32,
+    /// The change of the grain phase per sample.
+    phase_increment: f32,
+    delay_samples: f32,
+    /// The gains of the voice in the left and right channels.
+    pan_gains: [f32; 2],
+
+    // Internal states
+    /// The phase of the first grain in `[0, 1)`. The second grain is half a grain apart.
+    phase: f32,
+}
+
+/// Harmonizer and voice doubler: up to 8 voices, each a pitch-shifted, detuned and delayed copy
+/// of the input, are mixed with the dry signal.
+///
+/// The pitch is shifted in the time domain by granular resampling: each voice reads the input
+/// from a delay line with two taps whose delays sweep through a 40 ms grain at a rate set by the
+/// pitch ratio, so the taps play the signal faster or slower. The taps are half a grain apart and
+/// crossfaded with `sin^2` windows, which sum to 1. An unshifted voice is delayed by its delay plus
+/// half a grain (20 ms).
+///
+/// The input channels are mixed down to mono for the voices. In stereo, the voices are panned with
+/// the constant-power law; in mono, the pans are ignored.
+///
+/// For a voice doubler, use [`doubler`](Self::doubler), which adds two slightly detuned and
+/// delayed voices panned to the sides.
+pub struct Harmonizer {
+    num_channels: usize,
+    sample_rate: f32,
+    dry_gain: f32,
+    voices: Vec<HarmonizerVoice>,
+
+    // Internal states
+    /// The delay line of the mono input.
+    line: DelayLine,
+}
+
+impl Effect for Harmonizer {
+    fn prepare(&mut self, sample_rate: f32, _block_size: usize) {
+        assert!(sample_rate > 0.0);
+        self.sample_rate = sample_rate;
+        let max_delay = (MAX_VOICE_DELAY + GRAIN_TIME) * sample_rate / 1000.0;
+        self.line = DelayLine::new(max_delay.ceil() as usize + 1);
+        self.voices.iter_mut().for_each(|voice| voice.prepare(sample_rate));
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        self.line.reset();
+        self.voices.iter_mut().for_each(|voice| voice.phase = 0.0);
+    }
+
+    fn process_inplace<'outer, 'inner>(
+        &mut self,
+        buffer: &'outer mut BufferViewMut<'outer, 'inner>,
+    ) {
+        // Check if the effect is prepared
+        if self.sample_rate == 0.0 {
+            return;
+        }
+
+        debug_assert_eq!(buffer.num_channels(), self.num_channels);
+        let num_samples = buffer.num_samples();
+        let channels: &mut [&mut [f32]] = buffer.channels_mut();
+
+        for n in 0..num_samples {
+            let input = channels.iter().map(|channel| channel[n]).sum::<f32>() / self.num_channels as f32;
+            self.line.push(input);
+
+            let mut wet = [0.0; 2];
+            for voice in self.voices.iter_mut() {
+                let y = voice.process(&self.line);
+                wet[0] += voice.pan_gains[0] * y;
+                wet[1] += voice.pan_gains[1] * y;
+            }
+
+            for (ch, channel) in channels.iter_mut().enumerate() {
+                channel[n] = self.dry_gain * channel[n] + wet[ch];
+            }
+        }
+    }
+}
+
+impl Harmonizer {
+    /// Create a harmonizer without voices.
+    ///
+    /// # Panics
+    ///
+    /// * If `num_channels` is not 1 or 2.
+    pub fn new(num_channels: usize) -> Self {
+        assert!((1..=2).contains(&num_channels), "num_channels must be 1 or 2");
+        Self {
+            num_channels,
+            sample_rate: 0.0,
+            dry_gain: DEFAULT_DRY_GAIN,
+            voices: vec![],
+            line: DelayLine::new(0),
+        }
+    }
+
+    /// Create a voice doubler: two voices detuned by -8 and +7 cents, delayed by 12 and 19 ms and
+    /// panned halfway to the left and to the right.
+    pub fn doubler(num_channels: usize) -> Self {
+        let mut doubler = Self::new(num_channels);
+        for (detune, delay, pan) in [(-8.0, 12.0, -0.5), (7.0, 19.0, 0.5)] {
+            let voice = doubler.add_voice(0.0);
+            doubler.voice_mut(voice).set_detune(detune);
+            doubler.voice_mut(voice).set_delay(delay);
+            doubler.voice_mut(voice).set_pan(pan);
+        }
+        doubler
+    }
+
+    /// Add a voice with the interval in semitones, and return its index. The voice has no detune
+    /// and no delay, and is centered at -6 dB.
+    ///
+    /// # Panics
+    ///
+    /// * If there are already 8 voices.
+    pub fn add_voice(&mut self, interval: f32) -> usize {
+        assert!(
+            self.voices.len() < MAX_VOICES,
+            "A harmonizer has at most {MAX_VOICES} voices"
+        );
+        let mut voice = HarmonizerVoice::new(self.num_channels, interval);
+        if self.sample_rate > 0.0 {
+            voice.prepare(self.sample_rate);
+        }
+        self.voices.push(voice);
+        self.voices.len() - 1
+    }
+
+    /// Remove a voice. The indices of the following voices are shifted down.
+    pub fn remove_voice(&mut self, voice: usize) {
+        self.voices.remove(voice);
+    }
+
+    pub fn num_voices(&self) -> usize {
+        self.voices.len()
+    }
+
+    pub fn voice(&self, voice: usize) -> &HarmonizerVoice {
+        &self.voices[voice]
+    }
+
+    /// A voice, for setting its parameters.
+    pub fn voice_mut(&mut self, voice: usize) -> &mut HarmonizerVoice {
+        &mut self.voices[voice]
+    }
+
+    pub fn set_dry_gain(&mut self, dry_gain: f32) {
+        assert!(dry_gain >= 0.0);
+        self.dry_gain = dry_gain;
+    }
+}
+
+impl HarmonizerVoice {
+    fn new(num_channels: usize, interval: f32) -> Self {
+        let mut voice = Self {
+            num_channels,
+            sample_rate: 0.0,
+            interval,
+            detune: 0.0,
+            delay: 0.0,
+            pan: 0.0,
+            level: DEFAULT_VOICE_LEVEL,
+            grain_samples: 0.0,
+            phase_increment: 0.0,
+            delay_samples: 0.0,
+            pan_gains: [0.0; 2],
+            phase: 0.0,
+        };
+        voice.update_pan_gains();
+        voice
+    }
+
+    /// Set the interval in semitones, e.g. 7.0 for a fifth above or -12.0 for an octave below.
+    pub fn set_interval(&mut self, interval: f32) {
+        self.interval = interval;
+        self.update_phase_increment();
+    }
+
+    /// Set the detune in cents, added to the interval.
+    pub fn set_detune(&mut self, detune: f32) {
+        self.detune = detune;
+        self.update_phase_increment();
+    }
+
+    /// Set the delay in ms, up to 100 ms.
+    pub fn set_delay(&mut self, delay: f32) {
+        assert!((0.0..=MAX_VOICE_DELAY).contains(&delay));
+        self.delay = delay;
+        self.delay_samples = delay * self.sample_rate / 1000.0;
+    }
+
+    /// Set the pan in `[-1, 1]`, from left to right.
+    pub fn set_pan(&mut self, pan: f32) {
+        assert!((-1.0..=1.0).contains(&pan));
+        self.pan = pan;
+        self.update_pan_gains();
+    }
+
+    /// Set the level in dB.
+    pub fn set_level(&mut self, level: f32) {
+        self.level = level;
+        self.update_pan_gains();
+    }
+
+    /// The pitch ratio of the voice, including the detune.
+    pub fn pitch_ratio(&self) -> f32 {
+        2.0f32.powf((self.interval + self.detune / 100.0) / 12.0)
+    }
+
+    fn prepare(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.grain_samples = GRAIN_TIME * sample_rate / 1000.0;
+        self.delay_samples = self.delay * sample_rate / 1000.0;
+        self.update_phase_increment();
+    }
+
+    /// Read the voice from the delay line of the input, and advance the grains.
+    fn process(&mut self, line: &DelayLine) -> f32 {
+        let mut y = 0.0;
+        for offset in [0.0, 0.5] {
+            let phase = (self.phase + offset) % 1.0;
+            let window = (PI * phase).sin().powi(2);
+            y += window * line.read(1.0 + self.delay_samples + phase * self.grain_samples);
+        }
+        self.phase = (self.phase + self.phase_increment).rem_euclid(1.0);
+        y
+    }
+
+    fn update_phase_increment(&mut self) {
+        // The delay grows by `1 - ratio` samples per sample, so the taps play at the pitch ratio
+        if self.grain_samples > 0.0 {
+            self.phase_increment = (1.0 - self.pitch_ratio()) / self.grain_samples;
+        }
+    }
+
+    fn update_pan_gains(&mut self) {
+        let gain = 10.0f32.powf(self.level / 20.0);
+        self.pan_gains = if self.num_channels == 1 {
+            [gain, 0.0]
+        } else {
+            let angle = (self.pan + 1.0) * FRAC_PI_4;
+            [gain * angle.cos(), gain * angle.sin()]
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer_view::BufferView;
+    use crate::utilities::testing::{magnitude, sine};
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    #[test]
+    fn pitch_shift() {
+        for (interval, freq) in [(12.0, 400.0), (-12.0, 100.0), (7.0, 200.0 * 1.5)] {
+            let mut harmonizer = Harmonizer::new(1);
+            harmonizer.set_dry_gain(0.0);
+            let voice = harmonizer.add_voice(interval);
+            harmonizer.voice_mut(voice).set_level(0.0);
+            harmonizer.prepare(SAMPLE_RATE, 512);
+
+            // 1 s of a 200 Hz sine, skipping the first grain
+            let output = harmonizer.process(BufferView::new(&[&sine(200.0, 1.0, SAMPLE_RATE, 48000)]));
+            let output = &output[0][4800..];
+            assert!(magnitude(output, freq, SAMPLE_RATE) > 0.7, "interval {interval}");
+            assert!(magnitude(output, 200.0, SAMPLE_RATE) < 0.1, "interval {interval}");
+        }
+    }
+
+    #[test]
+    fn unison_delay() {
+        let mut harmonizer = Harmonizer::new(1);
+        harmonizer.set_dry_gain(0.0);
+        let voice = harmonizer.add_voice(0.0);
+        harmonizer.voice_mut(voice).set_level(0.0);
+        harmonizer.voice_mut(voice).set_delay(10.0);
+        harmonizer.prepare(SAMPLE_RATE, 512);
+
+        // The voice is delayed by 10 ms and half a grain
+        let mut input = vec![0.0; 2400];
+        input[0] = 1.0;
+        let output = harmonizer.process(BufferView::new(&[&input]));
+        let delay = 480 + 960;
+        assert!((output[0][delay] - 1.0).abs() < 1e-6);
+        assert!(output[0].iter().enumerate().all(|(n, &y)| n == delay || y.abs() < 1e-6));
+    }
+
+    #[test]
+    fn pan_and_level() {
+        let mut harmonizer = Harmonizer::new(2);
+        harmonizer.set_dry_gain(0.0);
+        let voice = harmonizer.add_voice(0.0);
+        harmonizer.voice_mut(voice).set_pan(-1.0);
+        harmonizer.voice_mut(voice).set_level(-6.0);
+        harmonizer.prepare(SAMPLE_RATE, 512);
+
+        // The stereo input is mixed down to mono
+        let input = sine(200.0, 1.0, SAMPLE_RATE, 9600);
+        let output = harmonizer.process(BufferView::new(&[&input, &input]));
+        assert!((magnitude(&output[0][4800..], 200.0, SAMPLE_RATE) - 10.0f32.powf(-6.0 / 20.0)).abs() < 0.01);
+        assert!(output[1].iter().all(|y| y.abs() < 1e-6));
+    }
+
+    #[test]
+    fn doubler() {
+        let mut doubler = Harmonizer::doubler(2);
+        doubler.prepare(SAMPLE_RATE, 512);
+        assert_eq!(doubler.num_voices(), 2);
+        assert!(doubler.voice(0).pitch_ratio() < 1.0 && doubler.voice(1).pitch_ratio() > 1.0);
+
+        // The detuned voices differ from the dry signal in both channels
+        let input = sine(200.0, 1.0, SAMPLE_RATE, 9600);
+        let output = doubler.process(BufferView::new(&[&input, &input]));
+        for channel in output.iter() {
+            let difference: Vec<f32> = channel.iter().zip(input.iter()).map(|(y, x)| y - x).collect();
+            assert!(magnitude(&difference[4800..], 200.0, SAMPLE_RATE) > 0.1);
+        }
+    }
+}
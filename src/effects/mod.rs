@@ -14,9 +14,21 @@ use crate::buffer_view::{BufferView, BufferViewMut};
 
 mod dynamics;
 mod delay;
+mod harmonizer;
+mod reverb;
+mod tuner;
+mod wah;
 
 pub use delay::DigitalDelay;
-pub use dynamics::Compressor;
+pub use dynamics::{
+    AutoLeveler, Compressor, CrossoverMode, DeEsser, DeEsserMode, Detector, DynamicEq, DynamicEqBand,
+    EqBandShape, Expander, GainReductionMeter, GainSmoothing, Limiter, LinkMode, LoudnessMeasure,
+    MultibandCompressor, SidechainFilter, Topology, TransientShaper,
+};
+pub use harmonizer::{Harmonizer, HarmonizerVoice};
+pub use reverb::{ConvolutionReverb, FdnMatrix, FdnReverb, Freeverb};
+pub use tuner::{PitchAlgorithm, Tuner, TunerReading};
+pub use wah::{AutoWah, WahDirection};
 
 /// An effect is like a module that processes audio signals.
 pub trait Effect {
@@ -31,6 +43,13 @@ pub trait Effect {
     /// Reset the effect to its initial state.
     fn reset(&mut self);
 
+    /// The latency of the effect in samples, e.g. the lookahead of a limiter. The host should
+    /// delay the other signals by this amount to keep them aligned. It may change after `prepare`
+    /// or a parameter change.
+    fn latency(&self) -> usize {
+        0
+    }
+
     /// Process the input signal and return the output signal.
     fn process(&mut self, input: BufferView) -> Vec<Vec<f32>> {
         let mut output: Vec<Vec<f32>> = input.to_vec();
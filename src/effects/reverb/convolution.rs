@@ -0,0 +1,495 @@
+//! Convolution reverb with a sampled impulse response.
+
+use crate::buffer_view::BufferViewMut;
+use crate::effects::Effect;
+use crate::filter::{Filter, PartitionedConvolver, SosFilter};
+use crate::filter::design::{FirCoeffs, SecondOrderSection, SosCoeffs};
+use crate::filter::design::biquad::{self, Width};
+use crate::utilities::resample;
+
+const MAX_PRE_DELAY: f32 = 500.0; // ms
+
+/// The length of the fade-out applied at the end of a trimmed impulse response.
+const TRIM_FADE_OUT: f32 = 5.0; // ms
+
+/// The length of the head (direct convolution) and the smallest partition.
+const MIN_PARTITION_SIZE: usize = 128;
+/// The largest partition of the FFT convolution.
+const MAX_PARTITION_SIZE: usize = 4096;
+
+const DEFAULT_DRY_GAIN: f32 = 1.0;
+const DEFAULT_WET_GAIN: f32 = 0.25; // 25% = -12 dB
+
+/// A convolution reverb with a mono, stereo or true-stereo impulse response (IR).
+///
+/// The IR is resampled to the sample rate given in [`Effect::prepare`] and convolved with
+/// zero-latency partitioned convolution. The channels of the IR are used as follows:
+///
+/// - 1 channel: the same IR is applied to each channel.
+/// - 2 channels: the left and the right IRs are applied to the left and the right channels.
+/// - 4 channels (true stereo): the IRs from the left input to the left output, from the left
+///   input to the right output, from the right input to the left output and from the right input
+///   to the right output, in this order.
+///
+/// For a mono effect, a multi-channel IR is mixed down to mono.
+///
+/// Changing the IR, the trimming or the stretch factor rebuilds the convolution, which allocates
+/// memory and is not real-time safe.
+pub struct ConvolutionReverb {
+    num_channels: usize,
+    sample_rate: f32,
+    block_size: usize,
+
+    // Parameters
+    /// The IR channels, mixed down for a mono effect.
+    impulse_response: Vec<Vec<f32>>,
+    ir_sample_rate: f32,
+    pre_delay: f32,
+    trim_start: f32,
+    trim_length: Option<f32>,
+    stretch: f32,
+    dry_gain: f32,
+    wet_gain: f32,
+    low_cut: Option<f32>,
+    high_cut: Option<f32>,
+
+    // Dependent parameters
+    pre_delay_samples: usize,
+
+    // Internal states
+    paths: Vec<ConvolutionPath>,
+    /// The delay lines of the pre-delay, one for each input channel.
+    pre_delay_lines: Vec<Vec<f32>>,
+    pre_delay_index: usize,
+    /// The wet signal EQ (low cut and high cut), one for each output channel.
+    wet_eq: Vec<SosFilter>,
+    wet_inputs: Vec<Vec<f32>>,
+    wet_outputs: Vec<Vec<f32>>,
+    scratch: Vec<f32>,
+}
+
+/// The convolution from an input channel to an output channel.
+struct ConvolutionPath {
+    input: usize,
+    output: usize,
+    convolver: PartitionedConvolver,
+}
+
+impl Effect for ConvolutionReverb {
+    fn prepare(&mut self, sample_rate: f32, block_size: usize) {
+        assert!(sample_rate > 0.0);
+        assert!(block_size > 0);
+        self.sample_rate = sample_rate;
+        self.block_size = block_size;
+
+        self.pre_delay_samples = self.ms_to_samples(self.pre_delay);
+        let max_pre_delay_samples = self.ms_to_samples(MAX_PRE_DELAY);
+        self.pre_delay_lines = vec![vec![0.0; (max_pre_delay_samples + 1).next_power_of_two()]; self.num_channels];
+
+        self.wet_inputs = vec![vec![0.0; block_size]; self.num_channels];
+        self.wet_outputs = vec![vec![0.0; block_size]; self.num_channels];
+        self.scratch = vec![0.0; block_size];
+        self.wet_eq = (0..self.num_channels)
+            .map(|_| SosFilter::new(self.wet_eq_coeffs()))
+            .collect();
+
+        self.build_paths();
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        self.pre_delay_lines.iter_mut().for_each(|line| line.fill(0.0));
+        self.pre_delay_index = 0;
+        self.paths.iter_mut().for_each(|path| path.convolver.reset());
+        self.wet_eq.iter_mut().for_each(|eq| eq.reset());
+    }
+
+    fn process_inplace<'outer, 'inner>(
+        &mut self,
+        buffer: &'outer mut BufferViewMut<'outer, 'inner>,
+    ) {
+        // Check if the effect is prepared
+        if self.sample_rate == 0.0 {
+            return;
+        }
+
+        debug_assert_eq!(buffer.num_channels(), self.num_channels);
+        let num_samples = buffer.num_samples();
+        let channels: &mut [&mut [f32]] = buffer.channels_mut();
+
+        // Process in chunks of the prepared block size
+        let mut start = 0;
+        while start < num_samples {
+            let end = (start + self.block_size).min(num_samples);
+            self.process_chunk(channels, start, end);
+            start = end;
+        }
+    }
+}
+
+impl ConvolutionReverb {
+    /// Create a convolution reverb.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_channels` - The number of channels of the effect, 1 or 2.
+    /// * `impulse_response` - The channels of the IR. See [`ConvolutionReverb`] for the channel
+    ///   layouts.
+    /// * `ir_sample_rate` - The sample rate of the IR in Hz.
+    ///
+    /// # Panics
+    ///
+    /// * If `num_channels` is not 1 or 2.
+    /// * If the IR does not have 1, 2 or 4 channels of the same length.
+    pub fn new(num_channels: usize, impulse_response: Vec<Vec<f32>>, ir_sample_rate: f32) -> Self {
+        assert!((1..=2).contains(&num_channels), "num_channels must be 1 or 2");
+        let mut reverb = Self {
+            num_channels,
+            sample_rate: 0.0,
+            block_size: 0,
+            impulse_response: vec![],
+            ir_sample_rate: 0.0,
+            pre_delay: 0.0,
+            trim_start: 0.0,
+            trim_length: None,
+            stretch: 1.0,
+            dry_gain: DEFAULT_DRY_GAIN,
+            wet_gain: DEFAULT_WET_GAIN,
+            low_cut: None,
+            high_cut: None,
+            pre_delay_samples: 0,
+            paths: vec![],
+            pre_delay_lines: vec![],
+            pre_delay_index: 0,
+            wet_eq: vec![],
+            wet_inputs: vec![],
+            wet_outputs: vec![],
+            scratch: vec![],
+        };
+        reverb.set_impulse_response(impulse_response, ir_sample_rate);
+        reverb
+    }
+
+    /// Replace the IR. See [`ConvolutionReverb::new`] for the arguments.
+    pub fn set_impulse_response(&mut self, impulse_response: Vec<Vec<f32>>, ir_sample_rate: f32) {
+        assert!(
+            matches!(impulse_response.len(), 1 | 2 | 4),
+            "The impulse response must have 1, 2 or 4 channels"
+        );
+        assert!(
+            impulse_response.iter().all(|ch| ch.len() == impulse_response[0].len()),
+            "The channels of the impulse response must have the same length"
+        );
+        assert!(ir_sample_rate > 0.0);
+
+        self.impulse_response = if self.num_channels == 1 && impulse_response.len() > 1 {
+            // A mono input feeds all the IR inputs, and the left and the right outputs are averaged
+            let len = impulse_response[0].len();
+            vec![(0..len).map(|n| 0.5 * impulse_response.iter().map(|ch| ch[n]).sum::<f32>()).collect()]
+        } else {
+            impulse_response
+        };
+        self.ir_sample_rate = ir_sample_rate;
+        self.rebuild();
+    }
+
+    /// Set the pre-delay in ms, the delay of the wet signal before the IR.
+    pub fn set_pre_delay(&mut self, pre_delay: f32) {
+        assert!((0.0..=MAX_PRE_DELAY).contains(&pre_delay));
+        self.pre_delay = pre_delay;
+        self.pre_delay_samples = self.ms_to_samples(pre_delay);
+    }
+
+    /// Trim the IR in its original time scale, i.e. before stretching.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - The time in ms removed from the beginning of the IR.
+    /// * `length` - The maximum length of the IR in ms after `start`. A short fade-out is applied
+    ///   if the IR is shortened. The whole IR is used if not provided.
+    pub fn set_trim(&mut self, start: f32, length: Option<f32>) {
+        assert!(start >= 0.0);
+        assert!(length.is_none_or(|length| length > 0.0));
+        self.trim_start = start;
+        self.trim_length = length;
+        self.rebuild();
+    }
+
+    /// Set the stretch factor of the IR. A factor greater than 1.0 makes the IR (and the room)
+    /// longer, and a factor less than 1.0 makes it shorter.
+    pub fn set_stretch(&mut self, stretch: f32) {
+        assert!(stretch > 0.0);
+        self.stretch = stretch;
+        self.rebuild();
+    }
+
+    pub fn set_dry_gain(&mut self, dry_gain: f32) {
+        assert!(dry_gain >= 0.0);
+        self.dry_gain = dry_gain;
+    }
+
+    pub fn set_wet_gain(&mut self, wet_gain: f32) {
+        assert!(wet_gain >= 0.0);
+        self.wet_gain = wet_gain;
+    }
+
+    /// Set the cutoff frequency in Hz of the highpass filter on the wet signal, or disable it.
+    pub fn set_low_cut(&mut self, freq: Option<f32>) {
+        self.low_cut = freq;
+        self.update_wet_eq();
+    }
+
+    /// Set the cutoff frequency in Hz of the lowpass filter on the wet signal, or disable it.
+    pub fn set_high_cut(&mut self, freq: Option<f32>) {
+        self.high_cut = freq;
+        self.update_wet_eq();
+    }
+
+    fn ms_to_samples(&self, ms: f32) -> usize {
+        (ms * self.sample_rate / 1000.0).round() as usize
+    }
+
+    /// Rebuild the convolution paths if the effect is prepared.
+    fn rebuild(&mut self) {
+        if self.sample_rate > 0.0 {
+            self.build_paths();
+        }
+    }
+
+    fn build_paths(&mut self) {
+        // (input channel, IR channel, output channel)
+        let routing: &[(usize, usize, usize)] = match (self.num_channels, self.impulse_response.len()) {
+            (1, _) => &[(0, 0, 0)],
+            (2, 1) => &[(0, 0, 0), (1, 0, 1)],
+            (2, 2) => &[(0, 0, 0), (1, 1, 1)],
+            _ => &[(0, 0, 0), (0, 1, 1), (1, 2, 0), (1, 3, 1)],
+        };
+        self.paths = routing
+            .iter()
+            .map(|&(input, ir_channel, output)| {
+                let coeffs = FirCoeffs::new(self.prepare_ir(&self.impulse_response[ir_channel]));
+                ConvolutionPath {
+                    input,
+                    output,
+                    convolver: PartitionedConvolver::non_uniform(coeffs, MIN_PARTITION_SIZE, MAX_PARTITION_SIZE),
+                }
+            })
+            .collect();
+    }
+
+    /// Trim, stretch and resample an IR channel to the sample rate.
+    fn prepare_ir(&self, ir: &[f32]) -> Vec<f32> {
+        let samples_per_ms = self.ir_sample_rate / 1000.0;
+        let start = ((self.trim_start * samples_per_ms).round() as usize).min(ir.len());
+        let end = match self.trim_length {
+            Some(length) => (start + (length * samples_per_ms).round() as usize).min(ir.len()),
+            None => ir.len(),
+        };
+        let mut trimmed = ir[start..end].to_vec();
+        if end < ir.len() {
+            let fade_len = ((TRIM_FADE_OUT * samples_per_ms) as usize).min(trimmed.len());
+            let fade_start = trimmed.len() - fade_len;
+            for (i, x) in trimmed[fade_start..].iter_mut().enumerate() {
+                let phase = std::f32::consts::PI * (i + 1) as f32 / fade_len as f32;
+                *x *= 0.5 * (1.0 + phase.cos());
+            }
+        }
+
+        // Resampling alone keeps the frequency response of the IR, so the IR is scaled by the
+        // ratio of the sample rates. Stretching keeps the level of the reverb tail instead.
+        let rate_ratio = self.sample_rate as f64 / self.ir_sample_rate as f64;
+        let ratio = rate_ratio * self.stretch as f64;
+        if (ratio - 1.0).abs() < 1e-9 {
+            return trimmed;
+        }
+        let scale = (1.0 / rate_ratio) as f32;
+        resample(&trimmed, ratio).into_iter().map(|x| x * scale).collect()
+    }
+
+    fn wet_eq_coeffs(&self) -> SosCoeffs {
+        let bypass = || SecondOrderSection::new(1.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+        let q = Width::Q(std::f32::consts::FRAC_1_SQRT_2);
+        let nyquist = self.sample_rate / 2.0;
+        let low_cut = match self.low_cut {
+            Some(freq) if freq > 0.0 && freq < nyquist => biquad::highpass(self.sample_rate, freq, q),
+            _ => bypass(),
+        };
+        let high_cut = match self.high_cut {
+            Some(freq) if freq > 0.0 && freq < nyquist => biquad::lowpass(self.sample_rate, freq, q),
+            _ => bypass(),
+        };
+        SosCoeffs::new(vec![low_cut, high_cut])
+    }
+
+    fn update_wet_eq(&mut self) {
+        if self.sample_rate > 0.0 {
+            let coeffs = self.wet_eq_coeffs();
+            self.wet_eq.iter_mut().for_each(|eq| eq.set_coeffs(coeffs.clone()));
+        }
+    }
+
+    fn process_chunk(&mut self, channels: &mut [&mut [f32]], start: usize, end: usize) {
+        let num_samples = end - start;
+        let delay_line_mask = self.pre_delay_lines[0].len() - 1;
+
+        // Pre-delay
+        for (ch, channel) in channels.iter().enumerate() {
+            let line = &mut self.pre_delay_lines[ch];
+            for (n, (&x, wet)) in channel[start..end].iter().zip(self.wet_inputs[ch].iter_mut()).enumerate() {
+                let write_index = (self.pre_delay_index + n) & delay_line_mask;
+                line[write_index] = x;
+                *wet = line[write_index.wrapping_sub(self.pre_delay_samples) & delay_line_mask];
+            }
+        }
+        self.pre_delay_index = (self.pre_delay_index + num_samples) & delay_line_mask;
+
+        // Convolution
+        self.wet_outputs.iter_mut().for_each(|wet| wet[..num_samples].fill(0.0));
+        for path in self.paths.iter_mut() {
+            let scratch = &mut self.scratch[..num_samples];
+            scratch.copy_from_slice(&self.wet_inputs[path.input][..num_samples]);
+            path.convolver.process_inplace(scratch);
+            for (y, &s) in self.wet_outputs[path.output].iter_mut().zip(scratch.iter()) {
+                *y += s;
+            }
+        }
+
+        // Wet EQ and mixing
+        for (ch, channel) in channels.iter_mut().enumerate() {
+            let wet = &mut self.wet_outputs[ch][..num_samples];
+            self.wet_eq[ch].process_inplace(wet);
+            for (x, &y) in channel[start..end].iter_mut().zip(wet.iter()) {
+                *x = self.dry_gain * *x + self.wet_gain * y;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert_all_close;
+    use crate::utilities::testing::{impulse, peak, sine};
+    use approx::assert_abs_diff_eq;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    /// Process the channels with the effect set to wet only.
+    fn process_wet(reverb: &mut ConvolutionReverb, mut buffer: Vec<Vec<f32>>) -> Vec<Vec<f32>> {
+        reverb.set_dry_gain(0.0);
+        reverb.set_wet_gain(1.0);
+        let mut slices: Vec<&mut [f32]> = buffer.iter_mut().map(|ch| ch.as_mut_slice()).collect();
+        let mut view = BufferViewMut::new(&mut slices);
+        reverb.process_inplace(&mut view);
+        buffer
+    }
+
+    fn decaying_noise(len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|n| ((n * 7919) % 101) as f32 / 50.0 - 1.0)
+            .enumerate()
+            .map(|(n, x)| x * (-(n as f32) / 2000.0).exp())
+            .collect()
+    }
+
+    #[test]
+    fn mono_impulse_response() {
+        let ir = decaying_noise(5000);
+        let mut reverb = ConvolutionReverb::new(1, vec![ir.clone()], SAMPLE_RATE);
+        reverb.prepare(SAMPLE_RATE, 256);
+        let output = process_wet(&mut reverb, vec![impulse(6000)]);
+        assert_all_close!(output[0][..5000], ir, 1e-5);
+    }
+
+    #[test]
+    fn dry_wet_mix() {
+        let mut reverb = ConvolutionReverb::new(1, vec![vec![0.0, 1.0]], SAMPLE_RATE);
+        reverb.set_dry_gain(0.5);
+        reverb.set_wet_gain(0.25);
+        reverb.prepare(SAMPLE_RATE, 4);
+        let mut buffer = vec![1.0, 0.0, 0.0];
+        let mut slices: Vec<&mut [f32]> = vec![&mut buffer];
+        reverb.process_inplace(&mut BufferViewMut::new(&mut slices));
+        assert_all_close!(buffer, [0.5, 0.25, 0.0]);
+    }
+
+    #[test]
+    fn pre_delay() {
+        let ir = decaying_noise(1000);
+        let mut reverb = ConvolutionReverb::new(1, vec![ir.clone()], SAMPLE_RATE);
+        reverb.set_pre_delay(10.0);
+        reverb.prepare(SAMPLE_RATE, 100);
+        let output = process_wet(&mut reverb, vec![impulse(2000)]);
+        assert_all_close!(output[0][..480], [0.0; 480]);
+        assert_all_close!(output[0][480..1480], ir, 1e-5);
+    }
+
+    #[test]
+    fn resampling() {
+        // A smooth IR keeps its DC gain after resampling
+        let ir: Vec<f32> = (0..2400).map(|n| (-(n as f32) / 300.0).exp()).collect();
+        let dc_gain: f32 = ir.iter().sum();
+        let mut reverb = ConvolutionReverb::new(1, vec![ir], 24000.0);
+        reverb.prepare(SAMPLE_RATE, 512);
+        let output = process_wet(&mut reverb, vec![vec![1.0; 10000]]);
+        assert_abs_diff_eq!(output[0][9999], dc_gain, epsilon = dc_gain * 0.01);
+    }
+
+    #[test]
+    fn trim_and_stretch() {
+        let mut ir = vec![0.0; 1000];
+        ir[100] = 1.0;
+        ir[500] = 1.0;
+        let mut reverb = ConvolutionReverb::new(1, vec![ir], SAMPLE_RATE);
+        reverb.prepare(SAMPLE_RATE, 64);
+
+        // 1 ms trimmed from the start, and the second peak is cut off
+        reverb.set_trim(1.0, Some(8.0));
+        let output = process_wet(&mut reverb, vec![impulse(1000)]);
+        assert_abs_diff_eq!(output[0][52], 1.0, epsilon = 1e-6);
+        assert!(output[0][384..].iter().all(|&y| y.abs() < 1e-6));
+
+        // The first peak is moved to twice the time
+        reverb.set_trim(0.0, None);
+        reverb.set_stretch(2.0);
+        reverb.reset();
+        let output = process_wet(&mut reverb, vec![impulse(1200)]);
+        assert_abs_diff_eq!(output[0][200], 1.0, epsilon = 1e-3);
+        assert_abs_diff_eq!(output[0][1000], 1.0, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn true_stereo() {
+        // Each IR channel is a delayed impulse with a distinct delay
+        let irs: Vec<Vec<f32>> = (0..4)
+            .map(|i| {
+                let mut ir = vec![0.0; 50];
+                ir[10 * (i + 1)] = 1.0;
+                ir
+            })
+            .collect();
+        let mut reverb = ConvolutionReverb::new(2, irs, SAMPLE_RATE);
+        reverb.prepare(SAMPLE_RATE, 32);
+
+        let output = process_wet(&mut reverb, vec![impulse(100), vec![0.0; 100]]);
+        // Left to left and left to right
+        assert_abs_diff_eq!(output[0][10], 1.0);
+        assert_abs_diff_eq!(output[1][20], 1.0);
+
+        reverb.reset();
+        let output = process_wet(&mut reverb, vec![vec![0.0; 100], impulse(100)]);
+        // Right to left and right to right
+        assert_abs_diff_eq!(output[0][30], 1.0);
+        assert_abs_diff_eq!(output[1][40], 1.0);
+        assert_abs_diff_eq!(output[0].iter().sum::<f32>(), 1.0);
+    }
+
+    #[test]
+    fn wet_high_cut() {
+        let mut reverb = ConvolutionReverb::new(1, vec![vec![1.0]], SAMPLE_RATE);
+        reverb.prepare(SAMPLE_RATE, 256);
+        reverb.set_high_cut(Some(1000.0));
+        let output = process_wet(&mut reverb, vec![sine(10000.0, 1.0, SAMPLE_RATE, 4800)]);
+        assert!(peak(&output[0][2400..]) < 0.02);
+    }
+}
@@ -0,0 +1,489 @@
+//! Algorithmic reverb with a feedback delay network (FDN).
+
+use std::f32::consts::PI;
+
+use crate::buffer_view::BufferViewMut;
+use crate::effects::Effect;
+use crate::effects::delay::DelayLine;
+use crate::filter::Filter;
+use crate::filter::comb::SchroederAllpass;
+
+const MAX_PRE_DELAY: f32 = 500.0; // ms
+const MAX_SIZE: f32 = 2.0;
+const MIN_SIZE: f32 = 0.1;
+const MAX_MODULATION_DEPTH: f32 = 5.0; // ms
+
+/// The range of the delay line lengths at size 1.0.
+const MIN_LINE_DELAY: f32 = 25.0; // ms
+const MAX_LINE_DELAY: f32 = 75.0; // ms
+
+/// The delays of the input diffusion allpass filters at size 1.0.
+const DIFFUSER_DELAYS: [f32; 4] = [4.771, 3.595, 12.73, 9.307]; // ms
+/// The allpass coefficient at the maximum diffusion.
+const MAX_DIFFUSION_COEFF: f32 = 0.75;
+
+const DEFAULT_SIZE: f32 = 1.0;
+const DEFAULT_DECAY_TIME: f32 = 1.5; // s
+const DEFAULT_DAMPING: f32 = 0.5;
+const DEFAULT_DIFFUSION: f32 = 0.7;
+const DEFAULT_PRE_DELAY: f32 = 0.0; // ms
+const DEFAULT_WIDTH: f32 = 1.0;
+const DEFAULT_MODULATION_DEPTH: f32 = 0.5; // ms
+const DEFAULT_MODULATION_RATE: f32 = 0.8; // Hz
+const DEFAULT_DRY_GAIN: f32 = 1.0;
+const DEFAULT_WET_GAIN: f32 = 0.25; // 25% = -12 dB
+
+/// The feedback matrix of the FDN. Both matrices are orthogonal, so the network is lossless
+/// without the decay gains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FdnMatrix {
+    /// The normalized Hadamard matrix, which mixes every line into every other line with equal
+    /// weights.
+    #[default]
+    Hadamard,
+    /// The Householder reflection `I - 2/N * 1 1^T`, which is cheaper and feeds each line mostly
+    /// back into itself.
+    Householder,
+}
+
+/// An algorithmic reverb with a feedback delay network of 8 or 16 modulated delay lines.
+///
+/// The input channels are mixed down to mono, pre-delayed and diffused by a chain of allpass
+/// filters before entering the network. Each line has a damping filter that sets the decay time
+/// at low and high frequencies, and its length is slowly modulated to reduce the metallic
+/// coloration. The stereo output is taken from the lines with two orthogonal sign patterns.
+pub struct FdnReverb {
+    num_channels: usize,
+    sample_rate: f32,
+
+    // Parameters
+    matrix: FdnMatrix,
+    size: f32,
+    decay_time: f32,
+    damping: f32,
+    diffusion: f32,
+    pre_delay: f32,
+    width: f32,
+    modulation_depth: f32,
+    modulation_rate: f32,
+    dry_gain: f32,
+    wet_gain: f32,
+
+    // Dependent parameters
+    /// The nominal lengths of the delay lines in samples.
+    line_delays: Vec<f32>,
+    /// The gains of the damping filters at DC.
+    line_gains: Vec<f32>,
+    /// The poles of the one-pole damping filters.
+    damping_poles: Vec<f32>,
+    diffusion_coeff: f32,
+    pre_delay_samples: f32,
+    modulation_depth_samples: f32,
+    /// The phase increments of the modulation LFOs, one for each line.
+    lfo_increments: Vec<f32>,
+
+    // Internal states
+    lines: Vec<DelayLine>,
+    damping_states: Vec<f32>,
+    lfo_phases: Vec<f32>,
+    diffusers: Vec<SchroederAllpass>,
+    pre_delay_line: DelayLine,
+    /// Scratch buffer for the outputs of the lines.
+    line_outputs: Vec<f32>,
+}
+
+impl Effect for FdnReverb {
+    fn prepare(&mut self, sample_rate: f32, _block_size: usize) {
+        assert!(sample_rate > 0.0);
+        self.sample_rate = sample_rate;
+        let samples_per_ms = sample_rate / 1000.0;
+
+        // Allocate for the maximum size and modulation depth
+        let max_line_delay = (MAX_LINE_DELAY * MAX_SIZE + MAX_MODULATION_DEPTH) * samples_per_ms;
+        self.lines = (0..self.line_delays.len())
+            .map(|_| DelayLine::new(max_line_delay.ceil() as usize + 1))
+            .collect();
+        self.diffusers = DIFFUSER_DELAYS
+            .iter()
+            .map(|&delay| {
+                let max_delay = ((delay * MAX_SIZE * samples_per_ms).ceil() as usize).max(1);
+                SchroederAllpass::new(max_delay, self.diffusion_coeff)
+            })
+            .collect();
+        self.pre_delay_line = DelayLine::new((MAX_PRE_DELAY * samples_per_ms).ceil() as usize + 1);
+
+        self.update_delays();
+        self.update_modulation();
+        self.pre_delay_samples = self.pre_delay * samples_per_ms;
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        self.lines.iter_mut().for_each(|line| line.reset());
+        self.diffusers.iter_mut().for_each(|diffuser| diffuser.reset());
+        self.pre_delay_line.reset();
+        self.damping_states.fill(0.0);
+        // Spread the LFO phases so that the lines are not modulated in sync
+        let num_lines = self.lfo_phases.len();
+        for (i, phase) in self.lfo_phases.iter_mut().enumerate() {
+            *phase = 2.0 * PI * i as f32 / num_lines as f32;
+        }
+    }
+
+    fn process_inplace<'outer, 'inner>(
+        &mut self,
+        buffer: &'outer mut BufferViewMut<'outer, 'inner>,
+    ) {
+        // Check if the effect is prepared
+        if self.sample_rate == 0.0 {
+            return;
+        }
+
+        debug_assert_eq!(buffer.num_channels(), self.num_channels);
+        let num_samples = buffer.num_samples();
+        let channels: &mut [&mut [f32]] = buffer.channels_mut();
+
+        let num_lines = self.line_delays.len();
+        let input_gain = 1.0 / (num_lines as f32).sqrt();
+        for n in 0..num_samples {
+            let x = channels.iter().map(|ch| ch[n]).sum::<f32>() / self.num_channels as f32;
+
+            // Pre-delay and input diffusion
+            self.pre_delay_line.push(x);
+            let mut diffused = self.pre_delay_line.read(self.pre_delay_samples + 1.0);
+            for diffuser in self.diffusers.iter_mut() {
+                diffused = diffuser.process_sample(diffused);
+            }
+
+            // Read the modulated lines and apply the damping filters
+            for i in 0..num_lines {
+                let modulation = self.modulation_depth_samples * self.lfo_phases[i].sin();
+                let s = self.lines[i].read((self.line_delays[i] + modulation).max(1.0));
+                let pole = self.damping_poles[i];
+                self.damping_states[i] = self.line_gains[i] * (1.0 - pole) * s + pole * self.damping_states[i];
+                self.line_outputs[i] = self.damping_states[i];
+
+                self.lfo_phases[i] += self.lfo_increments[i];
+                if self.lfo_phases[i] > 2.0 * PI {
+                    self.lfo_phases[i] -= 2.0 * PI;
+                }
+            }
+
+            // Stereo taps with two orthogonal sign patterns
+            let (left, right) = self.line_outputs.iter().enumerate().fold(
+                (0.0, 0.0),
+                |(left, right), (i, &s)| {
+                    let left_sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+                    let right_sign = if (i / 2) % 2 == 0 { 1.0 } else { -1.0 };
+                    (left + left_sign * s, right + right_sign * s)
+                },
+            );
+
+            // Feedback through the mixing matrix
+            match self.matrix {
+                FdnMatrix::Hadamard => hadamard(&mut self.line_outputs),
+                FdnMatrix::Householder => householder(&mut self.line_outputs),
+            }
+            for (i, (line, &feedback)) in self.lines.iter_mut().zip(self.line_outputs.iter()).enumerate() {
+                let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+                line.push(feedback + sign * input_gain * diffused);
+            }
+
+            // Stereo width and mixing
+            if self.num_channels == 1 {
+                channels[0][n] = self.dry_gain * channels[0][n] + self.wet_gain * left;
+            } else {
+                let mid = (left + right) / 2.0;
+                let side = self.width * (left - right) / 2.0;
+                channels[0][n] = self.dry_gain * channels[0][n] + self.wet_gain * (mid + side);
+                channels[1][n] = self.dry_gain * channels[1][n] + self.wet_gain * (mid - side);
+            }
+        }
+    }
+}
+
+impl FdnReverb {
+    /// Create an FDN reverb.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_channels` - The number of channels, 1 or 2.
+    /// * `num_lines` - The number of delay lines, 8 or 16.
+    ///
+    /// # Panics
+    ///
+    /// * If `num_channels` is not 1 or 2, or `num_lines` is not 8 or 16.
+    pub fn new(num_channels: usize, num_lines: usize) -> Self {
+        assert!((1..=2).contains(&num_channels), "num_channels must be 1 or 2");
+        assert!(num_lines == 8 || num_lines == 16, "num_lines must be 8 or 16");
+        Self {
+            num_channels,
+            sample_rate: 0.0,
+            matrix: FdnMatrix::default(),
+            size: DEFAULT_SIZE,
+            decay_time: DEFAULT_DECAY_TIME,
+            damping: DEFAULT_DAMPING,
+            diffusion: DEFAULT_DIFFUSION,
+            pre_delay: DEFAULT_PRE_DELAY,
+            width: DEFAULT_WIDTH,
+            modulation_depth: DEFAULT_MODULATION_DEPTH,
+            modulation_rate: DEFAULT_MODULATION_RATE,
+            dry_gain: DEFAULT_DRY_GAIN,
+            wet_gain: DEFAULT_WET_GAIN,
+            line_delays: vec![0.0; num_lines],
+            line_gains: vec![0.0; num_lines],
+            damping_poles: vec![0.0; num_lines],
+            diffusion_coeff: DEFAULT_DIFFUSION * MAX_DIFFUSION_COEFF,
+            pre_delay_samples: 0.0,
+            modulation_depth_samples: 0.0,
+            lfo_increments: vec![0.0; num_lines],
+            lines: vec![],
+            damping_states: vec![0.0; num_lines],
+            lfo_phases: vec![0.0; num_lines],
+            diffusers: vec![],
+            pre_delay_line: DelayLine::new(0),
+            line_outputs: vec![0.0; num_lines],
+        }
+    }
+
+    pub fn set_matrix(&mut self, matrix: FdnMatrix) {
+        self.matrix = matrix;
+    }
+
+    /// Set the room size, which scales the lengths of the delay lines and the diffusers. 1.0 is
+    /// the nominal size, and the range is `[0.1, 2.0]`.
+    pub fn set_size(&mut self, size: f32) {
+        assert!((MIN_SIZE..=MAX_SIZE).contains(&size));
+        self.size = size;
+        self.update_delays();
+    }
+
+    /// Set the decay time (RT60) at low frequencies in seconds.
+    pub fn set_decay_time(&mut self, decay_time: f32) {
+        assert!(decay_time > 0.0);
+        self.decay_time = decay_time;
+        self.update_delays();
+    }
+
+    /// Set the high-frequency damping in `[0, 1)`. The decay time at the Nyquist frequency is
+    /// `1 - damping` times the decay time at DC.
+    pub fn set_damping(&mut self, damping: f32) {
+        assert!((0.0..1.0).contains(&damping));
+        self.damping = damping;
+        self.update_delays();
+    }
+
+    /// Set the input diffusion in `[0, 1]`. Higher diffusion smears the early reflections.
+    pub fn set_diffusion(&mut self, diffusion: f32) {
+        assert!((0.0..=1.0).contains(&diffusion));
+        self.diffusion = diffusion;
+        self.diffusion_coeff = diffusion * MAX_DIFFUSION_COEFF;
+        for diffuser in self.diffusers.iter_mut() {
+            diffuser.set_gain(self.diffusion_coeff);
+        }
+    }
+
+    /// Set the pre-delay in ms.
+    pub fn set_pre_delay(&mut self, pre_delay: f32) {
+        assert!((0.0..=MAX_PRE_DELAY).contains(&pre_delay));
+        self.pre_delay = pre_delay;
+        self.pre_delay_samples = pre_delay * self.sample_rate / 1000.0;
+    }
+
+    /// Set the stereo width of the wet signal, from 0.0 (mono) to 1.0 (full width).
+    pub fn set_width(&mut self, width: f32) {
+        assert!((0.0..=1.0).contains(&width));
+        self.width = width;
+    }
+
+    /// Set the modulation depth in ms and the modulation rate in Hz of the delay lines.
+    pub fn set_modulation(&mut self, depth: f32, rate: f32) {
+        assert!((0.0..=MAX_MODULATION_DEPTH).contains(&depth));
+        assert!(rate >= 0.0);
+        self.modulation_depth = depth;
+        self.modulation_rate = rate;
+        self.update_modulation();
+    }
+
+    pub fn set_dry_gain(&mut self, dry_gain: f32) {
+        assert!(dry_gain >= 0.0);
+        self.dry_gain = dry_gain;
+    }
+
+    pub fn set_wet_gain(&mut self, wet_gain: f32) {
+        assert!(wet_gain >= 0.0);
+        self.wet_gain = wet_gain;
+    }
+
+    /// Update the line lengths, the decay gains and the damping filters.
+    fn update_delays(&mut self) {
+        let samples_per_ms = self.sample_rate / 1000.0;
+        let num_lines = self.line_delays.len();
+        for i in 0..num_lines {
+            // Exponentially spaced lengths, rounded to primes to avoid common periods
+            let ratio = i as f32 / (num_lines - 1) as f32;
+            let delay_ms = MIN_LINE_DELAY * (MAX_LINE_DELAY / MIN_LINE_DELAY).powf(ratio) * self.size;
+            let delay = next_prime((delay_ms * samples_per_ms) as usize) as f32;
+            self.line_delays[i] = delay;
+
+            // The gains for the decay times at DC and at the Nyquist frequency
+            let gain_dc = 10.0f32.powf(-3.0 * delay / (self.decay_time * self.sample_rate));
+            let gain_nyquist = 10.0f32.powf(-3.0 * delay / ((1.0 - self.damping) * self.decay_time * self.sample_rate));
+            let ratio = gain_nyquist / gain_dc;
+            self.line_gains[i] = gain_dc;
+            self.damping_poles[i] = (1.0 - ratio) / (1.0 + ratio);
+        }
+        for (diffuser, &delay_ms) in self.diffusers.iter_mut().zip(DIFFUSER_DELAYS.iter()) {
+            diffuser.set_delay(((delay_ms * self.size * samples_per_ms) as usize).max(1));
+        }
+    }
+
+    fn update_modulation(&mut self) {
+        self.modulation_depth_samples = self.modulation_depth * self.sample_rate / 1000.0;
+        // Slightly different rates for the lines
+        let num_lines = self.lfo_increments.len();
+        for (i, increment) in self.lfo_increments.iter_mut().enumerate() {
+            let rate = self.modulation_rate * (1.0 + 0.5 * i as f32 / num_lines as f32);
+            *increment = 2.0 * PI * rate / self.sample_rate;
+        }
+    }
+}
+
+/// In-place normalized fast Walsh-Hadamard transform. The length must be a power of 2.
+fn hadamard(x: &mut [f32]) {
+    let n = x.len();
+    let mut h = 1;
+    while h < n {
+        for i in (0..n).step_by(2 * h) {
+            for j in i..i + h {
+                let (a, b) = (x[j], x[j + h]);
+                x[j] = a + b;
+                x[j + h] = a - b;
+            }
+        }
+        h *= 2;
+    }
+    let scale = 1.0 / (n as f32).sqrt();
+    x.iter_mut().for_each(|v| *v *= scale);
+}
+
+/// In-place Householder reflection `x - 2/N * sum(x)`.
+fn householder(x: &mut [f32]) {
+    let offset = 2.0 / x.len() as f32 * x.iter().sum::<f32>();
+    x.iter_mut().for_each(|v| *v -= offset);
+}
+
+/// The smallest prime not less than `n`.
+fn next_prime(n: usize) -> usize {
+    let is_prime = |m: usize| m >= 2 && (2..).take_while(|d| d * d <= m).all(|d| !m.is_multiple_of(d));
+    (n..).find(|&m| is_prime(m)).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer_view::BufferView;
+    use crate::utilities::testing::{energy, impulse};
+    use approx::assert_abs_diff_eq;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    fn wet_only(num_channels: usize, num_lines: usize) -> FdnReverb {
+        let mut reverb = FdnReverb::new(num_channels, num_lines);
+        reverb.set_dry_gain(0.0);
+        reverb.set_wet_gain(1.0);
+        reverb
+    }
+
+    #[test]
+    fn matrices_are_orthogonal() {
+        let x: Vec<f32> = (0..16).map(|i| (i as f32 * 1.7).sin()).collect();
+        let norm = |v: &[f32]| v.iter().map(|a| a * a).sum::<f32>();
+        for transform in [hadamard, householder] {
+            let mut y = x.clone();
+            transform(&mut y);
+            assert_abs_diff_eq!(norm(&y), norm(&x), epsilon = 1e-4);
+            // Both matrices are their own inverses
+            transform(&mut y);
+            crate::assert_all_close!(y, x, 1e-5);
+        }
+    }
+
+    #[test]
+    fn dry_only() {
+        let mut reverb = FdnReverb::new(2, 8);
+        reverb.set_wet_gain(0.0);
+        reverb.prepare(SAMPLE_RATE, 256);
+        let (left, right) = ([0.5, -0.25, 0.0, 1.0], [0.1, 0.2, 0.3, 0.4]);
+        let output = reverb.process(BufferView::new(&[&left, &right]));
+        assert_eq!(output, [left, right]);
+    }
+
+    #[test]
+    fn decay_time() {
+        // The tail decays by 60 dB in the decay time
+        for (num_lines, matrix) in [(8, FdnMatrix::Hadamard), (16, FdnMatrix::Householder)] {
+            let mut reverb = wet_only(1, num_lines);
+            reverb.set_matrix(matrix);
+            reverb.set_decay_time(0.5);
+            reverb.set_damping(0.0);
+            reverb.set_modulation(0.0, 0.0);
+            reverb.prepare(SAMPLE_RATE, 256);
+            let output = reverb.process(BufferView::new(&[&impulse(48000)]));
+            // The energy of 50 ms windows at 0.2 s and 0.7 s
+            let window = |time: f32| {
+                let start = (time * SAMPLE_RATE) as usize;
+                energy(&output[0][start..start + 2400])
+            };
+            let decay = 10.0 * (window(0.2) / window(0.7)).log10();
+            assert_abs_diff_eq!(decay, 60.0, epsilon = 6.0);
+        }
+    }
+
+    #[test]
+    fn damping() {
+        // The high frequencies decay faster with damping, so the tail gets darker
+        let mut reverb = wet_only(1, 8);
+        reverb.set_damping(0.8);
+        reverb.prepare(SAMPLE_RATE, 256);
+        let output = reverb.process(BufferView::new(&[&impulse(48000)]));
+        let start = 24000;
+        let tail = &output[0][start..start + 4800];
+        let diff_energy: f32 = tail.windows(2).map(|w| (w[1] - w[0]).powi(2)).sum();
+        // The energy of the first difference is small for a lowpass signal
+        assert!(diff_energy < 0.1 * energy(tail));
+    }
+
+    #[test]
+    fn pre_delay() {
+        let mut reverb = wet_only(1, 8);
+        reverb.set_pre_delay(20.0);
+        reverb.set_diffusion(0.0);
+        reverb.prepare(SAMPLE_RATE, 256);
+        let output = reverb.process(BufferView::new(&[&impulse(4800)]));
+        // No output before the pre-delay plus the shortest line
+        let silence = (20.0 + MIN_LINE_DELAY) as usize * 48 - 100;
+        assert!(output[0][..silence].iter().all(|&y| y == 0.0));
+        assert!(output[0][silence..].iter().any(|&y| y != 0.0));
+    }
+
+    #[test]
+    fn stereo_width() {
+        let mut reverb = wet_only(2, 16);
+        reverb.prepare(SAMPLE_RATE, 256);
+        let output = reverb.process(BufferView::new(&[&impulse(9600), &[0.0; 9600]]));
+        assert!(output[0].iter().zip(output[1].iter()).any(|(l, r)| (l - r).abs() > 1e-3));
+
+        let mut reverb = wet_only(2, 16);
+        reverb.set_width(0.0);
+        reverb.prepare(SAMPLE_RATE, 256);
+        let output = reverb.process(BufferView::new(&[&impulse(9600), &[0.0; 9600]]));
+        crate::assert_all_close!(output[0], output[1]);
+    }
+
+    #[test]
+    fn primes() {
+        assert_eq!(next_prime(1200), 1201);
+        assert_eq!(next_prime(13), 13);
+    }
+}
@@ -0,0 +1,305 @@
+//! Freeverb, the classic Schroeder-Moorer reverb by Jezar at Dreampoint.
+
+use crate::buffer_view::BufferViewMut;
+use crate::effects::Effect;
+use crate::filter::Filter;
+use crate::filter::comb::{CombFilter, SchroederAllpass};
+
+/// The sample rate of the original tunings.
+const TUNING_SAMPLE_RATE: f32 = 44100.0;
+/// The delays of the comb filters of the left channel at 44.1 kHz.
+const COMB_TUNINGS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+/// The delays of the allpass filters of the left channel at 44.1 kHz.
+const ALLPASS_TUNINGS: [usize; 4] = [556, 441, 341, 225];
+/// The extra delay of the right channel at 44.1 kHz.
+const STEREO_SPREAD: usize = 23;
+
+const ALLPASS_GAIN: f32 = 0.5;
+const FIXED_GAIN: f32 = 0.015;
+const SCALE_WET: f32 = 3.0;
+const SCALE_DRY: f32 = 2.0;
+const SCALE_DAMPING: f32 = 0.4;
+const SCALE_ROOM: f32 = 0.28;
+const OFFSET_ROOM: f32 = 0.7;
+
+const DEFAULT_ROOM_SIZE: f32 = 0.5;
+const DEFAULT_DAMPING: f32 = 0.5;
+const DEFAULT_WET: f32 = 1.0 / SCALE_WET;
+const DEFAULT_DRY: f32 = 0.0;
+const DEFAULT_WIDTH: f32 = 1.0;
+
+/// Freeverb: 8 parallel damped feedback comb filters followed by 4 series allpass filters for
+/// each channel, with the tunings and the parameter mappings of the original implementation.
+///
+/// The delays are scaled from the original 44.1 kHz tunings to the sample rate.
+pub struct Freeverb {
+    num_channels: usize,
+    sample_rate: f32,
+
+    // Parameters, all in [0, 1]
+    room_size: f32,
+    damping: f32,
+    wet: f32,
+    dry: f32,
+    width: f32,
+    freeze: bool,
+
+    // Internal states, one set for each channel
+    combs: Vec<Vec<CombFilter>>,
+    allpasses: Vec<Vec<SchroederAllpass>>,
+}
+
+impl Effect for Freeverb {
+    fn prepare(&mut self, sample_rate: f32, _block_size: usize) {
+        assert!(sample_rate > 0.0);
+        self.sample_rate = sample_rate;
+
+        let scale = |delay: usize| {
+            ((delay as f32 * sample_rate / TUNING_SAMPLE_RATE).round() as usize).max(1)
+        };
+        self.combs = (0..self.num_channels)
+            .map(|ch| {
+                COMB_TUNINGS
+                    .iter()
+                    .map(|&delay| CombFilter::feedback(scale(delay + ch * STEREO_SPREAD), 0.0, 0.0))
+                    .collect()
+            })
+            .collect();
+        self.allpasses = (0..self.num_channels)
+            .map(|ch| {
+                ALLPASS_TUNINGS
+                    .iter()
+                    .map(|&delay| {
+                        SchroederAllpass::freeverb(scale(delay + ch * STEREO_SPREAD), ALLPASS_GAIN)
+                    })
+                    .collect()
+            })
+            .collect();
+        self.update_combs();
+    }
+
+    fn reset(&mut self) {
+        self.combs
+            .iter_mut()
+            .flatten()
+            .for_each(|comb| comb.reset());
+        self.allpasses
+            .iter_mut()
+            .flatten()
+            .for_each(|allpass| allpass.reset());
+    }
+
+    fn process_inplace<'outer, 'inner>(
+        &mut self,
+        buffer: &'outer mut BufferViewMut<'outer, 'inner>,
+    ) {
+        // Check if the effect is prepared
+        if self.sample_rate == 0.0 {
+            return;
+        }
+
+        debug_assert_eq!(buffer.num_channels(), self.num_channels);
+        let num_samples = buffer.num_samples();
+        let channels: &mut [&mut [f32]] = buffer.channels_mut();
+
+        // No new input enters the reverb when it is frozen
+        let input_gain = if self.freeze { 0.0 } else { FIXED_GAIN };
+        let wet = self.wet * SCALE_WET;
+        let dry = self.dry * SCALE_DRY;
+        let wet1 = wet * (self.width / 2.0 + 0.5);
+        let wet2 = wet * ((1.0 - self.width) / 2.0);
+
+        let mut outputs = [0.0f32; 2];
+        for n in 0..num_samples {
+            let x = channels.iter().map(|ch| ch[n]).sum::<f32>() * input_gain;
+            for (ch, output) in outputs.iter_mut().enumerate().take(self.num_channels) {
+                let y: f32 = self.combs[ch]
+                    .iter_mut()
+                    .map(|comb| comb.process_sample(x))
+                    .sum();
+                *output = self.allpasses[ch]
+                    .iter_mut()
+                    .fold(y, |y, allpass| allpass.process_sample(y));
+            }
+
+            if self.num_channels == 1 {
+                channels[0][n] = outputs[0] * wet + channels[0][n] * dry;
+            } else {
+                let (left, right) = (outputs[0], outputs[1]);
+                channels[0][n] = left * wet1 + right * wet2 + channels[0][n] * dry;
+                channels[1][n] = right * wet1 + left * wet2 + channels[1][n] * dry;
+            }
+        }
+    }
+}
+
+impl Freeverb {
+    pub fn new(num_channels: usize) -> Self {
+        assert!(
+            (1..=2).contains(&num_channels),
+            "num_channels must be 1 or 2"
+        );
+        Self {
+            num_channels,
+            sample_rate: 0.0,
+            room_size: DEFAULT_ROOM_SIZE,
+            damping: DEFAULT_DAMPING,
+            wet: DEFAULT_WET,
+            dry: DEFAULT_DRY,
+            width: DEFAULT_WIDTH,
+            freeze: false,
+            combs: vec![],
+            allpasses: vec![],
+        }
+    }
+
+    /// Set the room size in `[0, 1]`, which controls the feedback of the comb filters.
+    pub fn set_room_size(&mut self, room_size: f32) {
+        assert!((0.0..=1.0).contains(&room_size));
+        self.room_size = room_size;
+        self.update_combs();
+    }
+
+    /// Set the high-frequency damping in `[0, 1]`.
+    pub fn set_damping(&mut self, damping: f32) {
+        assert!((0.0..=1.0).contains(&damping));
+        self.damping = damping;
+        self.update_combs();
+    }
+
+    /// Set the wet level in `[0, 1]`.
+    pub fn set_wet(&mut self, wet: f32) {
+        assert!((0.0..=1.0).contains(&wet));
+        self.wet = wet;
+    }
+
+    /// Set the dry level in `[0, 1]`.
+    pub fn set_dry(&mut self, dry: f32) {
+        assert!((0.0..=1.0).contains(&dry));
+        self.dry = dry;
+    }
+
+    /// Set the stereo width in `[0, 1]`.
+    pub fn set_width(&mut self, width: f32) {
+        assert!((0.0..=1.0).contains(&width));
+        self.width = width;
+    }
+
+    /// Freeze the reverb: the input is muted and the comb filters sustain forever.
+    pub fn set_freeze(&mut self, freeze: bool) {
+        self.freeze = freeze;
+        self.update_combs();
+    }
+
+    fn update_combs(&mut self) {
+        let (feedback, damping) = if self.freeze {
+            (1.0, 0.0)
+        } else {
+            (
+                self.room_size * SCALE_ROOM + OFFSET_ROOM,
+                self.damping * SCALE_DAMPING,
+            )
+        };
+        for comb in self.combs.iter_mut().flatten() {
+            comb.set_gain(feedback);
+            comb.set_damping(damping);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer_view::BufferView;
+    use crate::utilities::testing::{energy, impulse};
+
+    const SAMPLE_RATE: f32 = 44100.0;
+
+    /// The left output of the original Freeverb with the default parameters, written after its
+    /// `comb`, `allpass` and `revmodel` classes.
+    fn reference(input: &[f32]) -> Vec<f32> {
+        let feedback = DEFAULT_ROOM_SIZE * SCALE_ROOM + OFFSET_ROOM;
+        let damping = DEFAULT_DAMPING * SCALE_DAMPING;
+        let mut combs: Vec<_> = COMB_TUNINGS.iter().map(|&delay| (vec![0.0; delay], 0, 0.0)).collect();
+        let mut allpasses: Vec<_> = ALLPASS_TUNINGS.iter().map(|&delay| (vec![0.0; delay], 0)).collect();
+        input
+            .iter()
+            .map(|&x| {
+                let x = x * FIXED_GAIN;
+                let mut y = 0.0;
+                for (buffer, index, filter_store) in combs.iter_mut() {
+                    let output: f32 = buffer[*index];
+                    *filter_store = output * (1.0 - damping) + *filter_store * damping;
+                    buffer[*index] = x + *filter_store * feedback;
+                    *index = (*index + 1) % buffer.len();
+                    y += output;
+                }
+                for (buffer, index) in allpasses.iter_mut() {
+                    let buffer_out: f32 = buffer[*index];
+                    let output = -y + buffer_out;
+                    buffer[*index] = y + buffer_out * ALLPASS_GAIN;
+                    *index = (*index + 1) % buffer.len();
+                    y = output;
+                }
+                y * DEFAULT_WET * SCALE_WET
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_as_reference() {
+        let mut reverb = Freeverb::new(2);
+        reverb.prepare(SAMPLE_RATE, 512);
+        let output = reverb.process(BufferView::new(&[&impulse(8000), &[0.0; 8000]]));
+        crate::assert_all_close!(output[0], reference(&impulse(8000)));
+    }
+
+    #[test]
+    fn first_echo() {
+        // The first output is from the shortest comb filter
+        let mut reverb = Freeverb::new(2);
+        reverb.prepare(SAMPLE_RATE, 512);
+        let output = reverb.process(BufferView::new(&[&impulse(2000), &[0.0; 2000]]));
+        assert!(output[0][..1116].iter().all(|&y| y == 0.0));
+        assert!(output[0][1116] != 0.0);
+        assert!(output[1][..1116 + STEREO_SPREAD].iter().all(|&y| y == 0.0));
+    }
+
+    #[test]
+    fn room_size() {
+        // A larger room decays slower
+        let tail_energy = |room_size: f32| {
+            let mut reverb = Freeverb::new(1);
+            reverb.set_room_size(room_size);
+            reverb.prepare(SAMPLE_RATE, 512);
+            let output = reverb.process(BufferView::new(&[&impulse(44100)]));
+            energy(&output[0][22050..])
+        };
+        assert!(tail_energy(0.9) > 10.0 * tail_energy(0.3));
+    }
+
+    #[test]
+    fn freeze() {
+        let mut reverb = Freeverb::new(1);
+        reverb.prepare(SAMPLE_RATE, 512);
+        let _ = reverb.process(BufferView::new(&[&impulse(4410)]));
+        reverb.set_freeze(true);
+
+        // The frozen tail neither decays nor takes new input
+        let first = reverb.process(BufferView::new(&[&[1.0; 44100]]));
+        let second = reverb.process(BufferView::new(&[&[0.0; 44100]]));
+        let (first, second) = (energy(&first[0]), energy(&second[0]));
+        // The energy fluctuates a little with the beating of the combs
+        assert!(first > 0.0);
+        assert!((first - second).abs() < 0.1 * first);
+    }
+
+    #[test]
+    fn mono_width() {
+        let mut reverb = Freeverb::new(2);
+        reverb.set_width(0.0);
+        reverb.prepare(SAMPLE_RATE, 512);
+        let output = reverb.process(BufferView::new(&[&impulse(4000), &[0.0; 4000]]));
+        crate::assert_all_close!(output[0], output[1]);
+    }
+}
@@ -0,0 +1,9 @@
+//! Reverb effects.
+
+mod convolution;
+mod fdn;
+mod freeverb;
+
+pub use convolution::ConvolutionReverb;
+pub use fdn::{FdnMatrix, FdnReverb};
+pub use freeverb::Freeverb;
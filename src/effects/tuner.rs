@@ -0,0 +1,474 @@
+//! Monophonic pitch detection for tuners.
+
+use crate::buffer_view::{BufferView, BufferViewMut};
+use crate::complex::Complex;
+use crate::effects::Effect;
+use crate::fft::RealFft;
+
+/// The number of periods of the lowest frequency in the analysis window.
+const WINDOW_PERIODS: f32 = 2.0;
+/// The number of analyses per window.
+const ANALYSES_PER_WINDOW: usize = 4;
+/// The mean power below which the input is silence, i.e. -80 dBFS.
+const MIN_POWER: f32 = 1e-8;
+/// The key maxima of the NSDF above this fraction of the highest one are candidates of the period.
+const MPM_PEAK_RATIO: f32 = 0.9;
+/// The absolute threshold of the cumulative mean normalized difference of YIN.
+const YIN_THRESHOLD: f32 = 0.15;
+
+const DEFAULT_REFERENCE: f32 = 440.0; // Hz
+/// Below B0 (30.87 Hz), the lowest string of a 5-string bass.
+const DEFAULT_MIN_FREQUENCY: f32 = 25.0; // Hz
+const DEFAULT_MAX_FREQUENCY: f32 = 2000.0; // Hz
+const DEFAULT_CLARITY_THRESHOLD: f32 = 0.8;
+
+const NOTE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+/// The pitch detection algorithm of a [`Tuner`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PitchAlgorithm {
+    /// The McLeod pitch method: the period is the first key maximum of the normalized square
+    /// difference function (NSDF) that is close to the highest one. The clarity is the NSDF at the
+    /// period.
+    #[default]
+    Mpm,
+    /// YIN: the period is the first dip of the cumulative mean normalized difference function
+    /// below 0.15. The clarity is 1 minus the difference at the period.
+    Yin,
+}
+
+/// A pitch detected by a [`Tuner`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TunerReading {
+    /// The fundamental frequency in Hz.
+    pub frequency: f32,
+    /// How periodic the signal is, in `[0, 1]`.
+    pub clarity: f32,
+    /// The MIDI number of the nearest note, e.g. 69 for A4.
+    pub note: i32,
+    /// The deviation from the nearest note in cents, in `[-50, 50]`.
+    pub cents: f32,
+}
+
+impl TunerReading {
+    /// The name of the nearest note without the octave, e.g. "A" or "C#".
+    pub fn note_name(&self) -> &'static str {
+        NOTE_NAMES[self.note.rem_euclid(12) as usize]
+    }
+
+    /// The octave of the nearest note in scientific pitch notation, e.g. 4 for A4.
+    pub fn octave(&self) -> i32 {
+        self.note.div_euclid(12) - 1
+    }
+}
+
+/// Tuner: detects the fundamental frequency of a monophonic signal, and the nearest note in
+/// equal temperament with a configurable reference frequency of A4.
+///
+/// The channels are mixed down to mono, and the pitch is detected from a window of the latest
+/// samples that holds 2 periods of the lowest frequency, 4 times per window. With the default
+/// range of 25 Hz to 2 kHz, the window is about 80 ms, which covers the low B (30.87 Hz) of a
+/// 5-string bass. The autocorrelation is computed by FFT, and the period is refined by parabolic
+/// interpolation.
+///
+/// The tuner consumes blocks with [`analyze`](Self::analyze), or as an [`Effect`] that passes the
+/// audio through unchanged. The latest reading is returned by [`reading`](Self::reading), which
+/// is `None` for silence or when the clarity is below the threshold.
+pub struct Tuner {
+    sample_rate: f32,
+
+    algorithm: PitchAlgorithm,
+    reference: f32,
+    min_frequency: f32,
+    max_frequency: f32,
+    clarity_threshold: f32,
+
+    // Dependent parameters
+    window: usize,
+    hop: usize,
+    min_lag: usize,
+    max_lag: usize,
+    fft: RealFft,
+
+    // Internal states
+    /// Ring buffer of the latest mono samples.
+    history: Vec<f32>,
+    write_index: usize,
+    /// The number of samples until the next analysis.
+    hop_countdown: usize,
+    reading: Option<TunerReading>,
+
+    // Scratch buffers
+    frame: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+    /// The autocorrelation, and then the NSDF or the YIN difference function.
+    function: Vec<f32>,
+    /// The lags of the key maxima of the NSDF.
+    key_maxima: Vec<usize>,
+}
+
+impl Effect for Tuner {
+    fn prepare(&mut self, sample_rate: f32, _block_size: usize) {
+        assert!(sample_rate > 0.0);
+        self.sample_rate = sample_rate;
+        self.update_window();
+    }
+
+    fn reset(&mut self) {
+        self.history.fill(0.0);
+        self.write_index = 0;
+        self.hop_countdown = self.hop;
+        self.reading = None;
+    }
+
+    fn process_inplace<'outer, 'inner>(
+        &mut self,
+        buffer: &'outer mut BufferViewMut<'outer, 'inner>,
+    ) {
+        // Check if the effect is prepared
+        if self.sample_rate == 0.0 {
+            return;
+        }
+
+        let num_channels = buffer.num_channels();
+        let channels: &mut [&mut [f32]] = buffer.channels_mut();
+        for n in 0..channels.first().map_or(0, |ch| ch.len()) {
+            let x = channels.iter().map(|channel| channel[n]).sum::<f32>() / num_channels as f32;
+            self.push(x);
+        }
+    }
+}
+
+impl Default for Tuner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tuner {
+    pub fn new() -> Self {
+        Self {
+            sample_rate: 0.0,
+            algorithm: PitchAlgorithm::Mpm,
+            reference: DEFAULT_REFERENCE,
+            min_frequency: DEFAULT_MIN_FREQUENCY,
+            max_frequency: DEFAULT_MAX_FREQUENCY,
+            clarity_threshold: DEFAULT_CLARITY_THRESHOLD,
+            window: 0,
+            hop: 0,
+            min_lag: 0,
+            max_lag: 0,
+            fft: RealFft::new(2),
+            history: vec![],
+            write_index: 0,
+            hop_countdown: 0,
+            reading: None,
+            frame: vec![],
+            spectrum: vec![],
+            function: vec![],
+            key_maxima: vec![],
+        }
+    }
+
+    pub fn set_algorithm(&mut self, algorithm: PitchAlgorithm) {
+        self.algorithm = algorithm;
+    }
+
+    /// Set the frequency of A4 in Hz.
+    pub fn set_reference(&mut self, reference: f32) {
+        assert!(reference > 0.0);
+        self.reference = reference;
+    }
+
+    /// Set the range of the detected frequencies in Hz. The window grows with the period of the
+    /// lowest frequency, so the tuner is reset.
+    ///
+    /// # Panics
+    ///
+    /// * If the frequencies are not positive and ascending.
+    /// * If the highest frequency is not below the Nyquist frequency once the tuner is prepared.
+    pub fn set_frequency_range(&mut self, min_frequency: f32, max_frequency: f32) {
+        assert!(min_frequency > 0.0 && min_frequency < max_frequency);
+        self.min_frequency = min_frequency;
+        self.max_frequency = max_frequency;
+        if self.sample_rate > 0.0 {
+            self.update_window();
+        }
+    }
+
+    /// Set the clarity in `[0, 1]` below which no pitch is reported.
+    pub fn set_clarity_threshold(&mut self, clarity_threshold: f32) {
+        assert!((0.0..=1.0).contains(&clarity_threshold));
+        self.clarity_threshold = clarity_threshold;
+    }
+
+    /// The latest detected pitch, or `None` if the signal is silent or not periodic enough.
+    pub fn reading(&self) -> Option<TunerReading> {
+        self.reading
+    }
+
+    /// Feed a block of samples to the tuner, which updates the reading every quarter of a window.
+    pub fn analyze(&mut self, input: BufferView) {
+        // Check if the tuner is prepared
+        if self.sample_rate == 0.0 {
+            return;
+        }
+
+        let num_channels = input.num_channels();
+        for n in 0..input.num_samples() {
+            let x = input.channels().iter().map(|channel| channel[n]).sum::<f32>() / num_channels as f32;
+            self.push(x);
+        }
+    }
+
+    /// Push a mono sample into the window, and detect the pitch every hop.
+    fn push(&mut self, x: f32) {
+        self.history[self.write_index] = x;
+        self.write_index = (self.write_index + 1) & (self.window - 1);
+
+        self.hop_countdown -= 1;
+        if self.hop_countdown == 0 {
+            self.hop_countdown = self.hop;
+            self.reading = self.detect();
+        }
+    }
+
+    /// Detect the pitch in the window.
+    fn detect(&mut self) -> Option<TunerReading> {
+        // The window in chronological order, zero-padded so that the autocorrelation is linear.
+        // The oldest sample is at the write index.
+        let (newest, oldest) = self.history.split_at(self.write_index);
+        self.frame[..oldest.len()].copy_from_slice(oldest);
+        self.frame[oldest.len()..self.window].copy_from_slice(newest);
+        self.frame[self.window..].fill(0.0);
+
+        let energy: f32 = self.frame[..self.window].iter().map(|x| x * x).sum();
+        if energy / (self.window as f32) < MIN_POWER {
+            return None;
+        }
+
+        // Autocorrelation by the Wiener-Khinchin theorem
+        self.fft.forward(&self.frame, &mut self.spectrum);
+        self.spectrum
+            .iter_mut()
+            .for_each(|z| *z = Complex::new(z.norm_sqr(), 0.0));
+        self.fft.inverse(&mut self.spectrum, &mut self.function);
+
+        // `m(tau)`: the energy of the two overlapping parts of the window at each lag
+        let mut m = 2.0 * energy;
+        let x = &self.frame;
+        for tau in 0..=self.max_lag + 1 {
+            if tau > 0 {
+                m -= x[tau - 1] * x[tau - 1] + x[self.window - tau] * x[self.window - tau];
+            }
+            let r = self.function[tau];
+            self.function[tau] = match self.algorithm {
+                // The NSDF in [-1, 1]
+                PitchAlgorithm::Mpm => {
+                    if m > 0.0 {
+                        2.0 * r / m
+                    } else {
+                        0.0
+                    }
+                }
+                // The difference function
+                PitchAlgorithm::Yin => (m - 2.0 * r).max(0.0),
+            };
+        }
+
+        let (period, clarity) = match self.algorithm {
+            PitchAlgorithm::Mpm => self.pick_mpm_period()?,
+            PitchAlgorithm::Yin => self.pick_yin_period()?,
+        };
+        if clarity < self.clarity_threshold {
+            return None;
+        }
+
+        let frequency = self.sample_rate / period;
+        let semitones = 69.0 + 12.0 * (frequency / self.reference).log2();
+        let note = semitones.round();
+        Some(TunerReading {
+            frequency,
+            clarity,
+            note: note as i32,
+            cents: 100.0 * (semitones - note),
+        })
+    }
+
+    /// Pick the period in samples and the clarity from the NSDF.
+    fn pick_mpm_period(&mut self) -> Option<(f32, f32)> {
+        let nsdf = &self.function;
+        let key_maxima = &mut self.key_maxima;
+
+        // The highest point of each positive lobe after the first negative-going zero crossing
+        key_maxima.clear();
+        let start = (1..=self.max_lag).find(|&tau| nsdf[tau] < 0.0)?;
+        let mut lobe_max: Option<usize> = None;
+        for tau in start..=self.max_lag {
+            if nsdf[tau] > 0.0 {
+                if lobe_max.is_none_or(|max| nsdf[tau] > nsdf[max]) {
+                    lobe_max = Some(tau);
+                }
+            } else if let Some(max) = lobe_max.take() {
+                key_maxima.push(max);
+            }
+        }
+        key_maxima.extend(lobe_max);
+        key_maxima.retain(|&tau| tau >= self.min_lag);
+
+        let highest = key_maxima.iter().map(|&tau| nsdf[tau]).fold(0.0, f32::max);
+        let tau = *key_maxima.iter().find(|&&tau| nsdf[tau] >= MPM_PEAK_RATIO * highest)?;
+        Some(parabolic_peak(nsdf, tau))
+    }
+
+    /// Pick the period in samples and the clarity from the YIN difference function.
+    fn pick_yin_period(&mut self) -> Option<(f32, f32)> {
+        // The cumulative mean normalized difference
+        let mut sum = 0.0;
+        self.function[0] = 1.0;
+        for tau in 1..=self.max_lag + 1 {
+            sum += self.function[tau];
+            self.function[tau] = if sum > 0.0 {
+                self.function[tau] * tau as f32 / sum
+            } else {
+                1.0
+            };
+        }
+        let cmnd = &self.function;
+
+        // The first dip below the threshold, or the global minimum
+        let tau = match (self.min_lag..=self.max_lag).find(|&tau| cmnd[tau] < YIN_THRESHOLD) {
+            Some(mut tau) => {
+                while tau < self.max_lag && cmnd[tau + 1] < cmnd[tau] {
+                    tau += 1;
+                }
+                tau
+            }
+            None => (self.min_lag..=self.max_lag).min_by(|&a, &b| cmnd[a].total_cmp(&cmnd[b]))?,
+        };
+
+        // The parabola through the negated function peaks at the dip
+        let negated = [-cmnd[tau - 1], -cmnd[tau], -cmnd[tau + 1]];
+        let (position, value) = parabolic_peak(&negated, 1);
+        Some((tau as f32 + position - 1.0, (1.0 + value).clamp(0.0, 1.0)))
+    }
+
+    fn update_window(&mut self) {
+        assert!(
+            self.max_frequency < self.sample_rate / 2.0,
+            "The maximum frequency must be below Nyquist"
+        );
+        self.max_lag = (self.sample_rate / self.min_frequency).ceil() as usize;
+        self.min_lag = ((self.sample_rate / self.max_frequency).floor() as usize).max(2);
+        self.window = ((WINDOW_PERIODS * self.max_lag as f32).ceil() as usize).next_power_of_two();
+        self.hop = self.window / ANALYSES_PER_WINDOW;
+        self.fft = RealFft::new(2 * self.window);
+        self.history = vec![0.0; self.window];
+        self.frame = vec![0.0; 2 * self.window];
+        self.spectrum = vec![Complex::new(0.0, 0.0); self.fft.spectrum_size()];
+        self.function = vec![0.0; 2 * self.window];
+        // At most one key maximum per two lags
+        self.key_maxima = Vec::with_capacity(self.max_lag / 2 + 1);
+        self.reset();
+    }
+}
+
+/// The position and the value of the peak of the parabola through the points `tau - 1`, `tau`
+/// and `tau + 1` of the function.
+fn parabolic_peak(function: &[f32], tau: usize) -> (f32, f32) {
+    let (a, b, c) = (function[tau - 1], function[tau], function[tau + 1]);
+    let denominator = a - 2.0 * b + c;
+    if denominator == 0.0 {
+        return (tau as f32, b);
+    }
+    let offset = (0.5 * (a - c) / denominator).clamp(-1.0, 1.0);
+    (tau as f32 + offset, b - 0.25 * (a - c) * offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    /// A bass-like tone: a sawtooth with 8 harmonics, half a second long.
+    fn tone(freq: f32) -> Vec<f32> {
+        (0..24000)
+            .map(|n| {
+                let t = n as f32 / SAMPLE_RATE;
+                (1..=8)
+                    .map(|k| 0.3 * (2.0 * PI * k as f32 * freq * t).sin() / k as f32)
+                    .sum()
+            })
+            .collect()
+    }
+
+    fn detect(tuner: &mut Tuner, signal: &[f32]) -> Option<TunerReading> {
+        tuner.reset();
+        tuner.analyze(BufferView::new(&[signal]));
+        tuner.reading()
+    }
+
+    #[test]
+    fn low_b() {
+        for algorithm in [PitchAlgorithm::Mpm, PitchAlgorithm::Yin] {
+            let mut tuner = Tuner::new();
+            tuner.set_algorithm(algorithm);
+            tuner.prepare(SAMPLE_RATE, 512);
+
+            let reading = detect(&mut tuner, &tone(30.87)).unwrap();
+            assert!((reading.frequency - 30.87).abs() < 0.05, "{algorithm:?}: {reading:?}");
+            assert_eq!((reading.note_name(), reading.octave()), ("B", 0));
+            assert!(reading.cents.abs() < 3.0);
+            assert!(reading.clarity > 0.9);
+        }
+    }
+
+    #[test]
+    fn notes_and_cents() {
+        for algorithm in [PitchAlgorithm::Mpm, PitchAlgorithm::Yin] {
+            let mut tuner = Tuner::new();
+            tuner.set_algorithm(algorithm);
+            tuner.prepare(SAMPLE_RATE, 512);
+
+            // E1 of a bass, A4, and C#6
+            for (freq, name, octave) in [(41.2034, "E", 1), (440.0, "A", 4), (1108.73, "C#", 6)] {
+                let reading = detect(&mut tuner, &tone(freq)).unwrap();
+                assert!(
+                    ((reading.frequency / freq).log2() * 1200.0).abs() < 1.0,
+                    "{algorithm:?}: {reading:?}"
+                );
+                assert_eq!((reading.note_name(), reading.octave()), (name, octave));
+            }
+
+            // 445 Hz is 19.56 cents sharp of A4, or in tune with A4 = 445 Hz
+            let reading = detect(&mut tuner, &tone(445.0)).unwrap();
+            assert!((reading.cents - 19.56).abs() < 0.5);
+            tuner.set_reference(445.0);
+            let reading = detect(&mut tuner, &tone(445.0)).unwrap();
+            assert_eq!(reading.note, 69);
+            assert!(reading.cents.abs() < 0.5);
+        }
+    }
+
+    #[test]
+    fn no_pitch() {
+        let mut tuner = Tuner::new();
+        tuner.prepare(SAMPLE_RATE, 512);
+        assert!(detect(&mut tuner, &[0.0; 24000]).is_none());
+
+        let noise: Vec<f32> = (0..24000).map(|_| rand::random_range(-0.5..0.5)).collect();
+        assert!(detect(&mut tuner, &noise).is_none());
+    }
+
+    #[test]
+    fn pass_through() {
+        let mut tuner = Tuner::new();
+        tuner.prepare(SAMPLE_RATE, 512);
+        let input = tone(110.0);
+        let output = tuner.process(BufferView::new(&[&input, &input]));
+        assert_eq!(output, [input.clone(), input]);
+        assert_eq!(tuner.reading().unwrap().note, 45);
+    }
+}
@@ -0,0 +1,324 @@
+//! Envelope-following auto-wah for bass.
+
+use crate::buffer_view::BufferViewMut;
+use crate::effects::Effect;
+use crate::effects::dynamics::time_constant_coeff;
+use crate::filter::{Filter, StateVariableFilter, SvfMode};
+
+const DEFAULT_SENSITIVITY: f32 = 6.0; // dB
+const DEFAULT_MIN_FREQUENCY: f32 = 250.0; // Hz
+const DEFAULT_MAX_FREQUENCY: f32 = 2500.0; // Hz
+const DEFAULT_RESONANCE: f32 = 4.0;
+const DEFAULT_ATTACK: f32 = 5.0; // ms
+const DEFAULT_RELEASE: f32 = 100.0; // ms
+const DEFAULT_DRY_BLEND: f32 = 1.0;
+const DEFAULT_DRY_CROSSOVER: f32 = 150.0; // Hz
+/// The Q of the lowpass filters of the dry signal, i.e. Butterworth.
+const DRY_Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+/// The number of samples between the updates of the cutoff.
+const CONTROL_INTERVAL: usize = 16;
+
+/// The direction in which an [`AutoWah`] sweeps the cutoff as the input gets louder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WahDirection {
+    /// The cutoff rises from the lowest frequency with the envelope.
+    #[default]
+    Up,
+    /// The cutoff falls from the highest frequency with the envelope.
+    Down,
+}
+
+/// Auto-wah (envelope filter): the cutoff of a resonant state variable filter follows the
+/// envelope of the input.
+///
+/// The envelope is the peak of all channels, smoothed with the attack and release times and
+/// amplified by the sensitivity. It sweeps the cutoff logarithmically through the frequency
+/// range, reaching the other end when the amplified envelope reaches 0 dBFS. All channels share
+/// the cutoff, which is updated every 16 samples.
+///
+/// A wah filter thins out a bass, so the dry signal below a crossover frequency (150 Hz by
+/// default) is blended back into the output. With the default dry blend of 1.0, the low end is
+/// kept intact under the bandpass wah.
+pub struct AutoWah {
+    num_channels: usize,
+    sample_rate: f32,
+
+    mode: SvfMode,
+    sensitivity: f32,
+    min_frequency: f32,
+    max_frequency: f32,
+    direction: WahDirection,
+    resonance: f32,
+    attack: f32,
+    release: f32,
+    dry_blend: f32,
+    dry_crossover: f32,
+
+    // Dependent parameters
+    sensitivity_gain: f32,
+    attack_coeff: f32,
+    release_coeff: f32,
+
+    // Internal states
+    envelope: f32,
+    cutoff: f32,
+    control_countdown: usize,
+    filters: Vec<StateVariableFilter>,
+    /// The lowpass filters of the dry signal.
+    dry_filters: Vec<StateVariableFilter>,
+}
+
+impl Effect for AutoWah {
+    fn prepare(&mut self, sample_rate: f32, _block_size: usize) {
+        assert!(sample_rate > 0.0);
+        assert!(
+            self.max_frequency < sample_rate / 2.0 && self.dry_crossover < sample_rate / 2.0,
+            "The frequencies must be below Nyquist"
+        );
+        self.sample_rate = sample_rate;
+        self.attack_coeff = time_constant_coeff(self.attack, sample_rate);
+        self.release_coeff = time_constant_coeff(self.release, sample_rate);
+        self.filters = (0..self.num_channels)
+            .map(|_| StateVariableFilter::new(self.mode, sample_rate, self.rest_frequency(), self.resonance))
+            .collect();
+        self.dry_filters = (0..self.num_channels)
+            .map(|_| StateVariableFilter::new(SvfMode::Lowpass, sample_rate, self.dry_crossover, DRY_Q))
+            .collect();
+        self.reset();
+    }
+
+    fn reset(&mut self) {
+        self.envelope = 0.0;
+        self.cutoff = self.rest_frequency();
+        self.control_countdown = 0;
+        for filter in self.filters.iter_mut() {
+            filter.set_cutoff(self.cutoff);
+            filter.reset();
+        }
+        self.dry_filters.iter_mut().for_each(|filter| filter.reset());
+    }
+
+    fn process_inplace<'outer, 'inner>(
+        &mut self,
+        buffer: &'outer mut BufferViewMut<'outer, 'inner>,
+    ) {
+        // Check if the effect is prepared
+        if self.sample_rate == 0.0 {
+            return;
+        }
+
+        debug_assert_eq!(buffer.num_channels(), self.num_channels);
+        let num_samples = buffer.num_samples();
+        let channels: &mut [&mut [f32]] = buffer.channels_mut();
+
+        for n in 0..num_samples {
+            let peak = channels.iter().map(|channel| channel[n].abs()).fold(0.0, f32::max);
+            let coeff = if peak > self.envelope { self.attack_coeff } else { self.release_coeff };
+            self.envelope = peak + coeff * (self.envelope - peak);
+
+            if self.control_countdown == 0 {
+                self.control_countdown = CONTROL_INTERVAL - 1;
+                self.update_cutoff();
+            } else {
+                self.control_countdown -= 1;
+            }
+
+            for (ch, channel) in channels.iter_mut().enumerate() {
+                let x = channel[n];
+                let wet = self.filters[ch].process_sample(x);
+                let low = self.dry_filters[ch].process_sample(x);
+                channel[n] = wet + self.dry_blend * low;
+            }
+        }
+    }
+}
+
+impl AutoWah {
+    /// Create a bandpass auto-wah sweeping up from 250 Hz to 2.5 kHz with a Q of 4.
+    ///
+    /// # Panics
+    ///
+    /// * If `num_channels` is 0.
+    pub fn new(num_channels: usize) -> Self {
+        assert!(num_channels > 0, "num_channels must be greater than 0");
+        Self {
+            num_channels,
+            sample_rate: 0.0,
+            mode: SvfMode::Bandpass,
+            sensitivity: DEFAULT_SENSITIVITY,
+            min_frequency: DEFAULT_MIN_FREQUENCY,
+            max_frequency: DEFAULT_MAX_FREQUENCY,
+            direction: WahDirection::Up,
+            resonance: DEFAULT_RESONANCE,
+            attack: DEFAULT_ATTACK,
+            release: DEFAULT_RELEASE,
+            dry_blend: DEFAULT_DRY_BLEND,
+            dry_crossover: DEFAULT_DRY_CROSSOVER,
+            sensitivity_gain: 10.0f32.powf(DEFAULT_SENSITIVITY / 20.0),
+            attack_coeff: 0.0,
+            release_coeff: 0.0,
+            envelope: 0.0,
+            cutoff: DEFAULT_MIN_FREQUENCY,
+            control_countdown: 0,
+            filters: vec![],
+            dry_filters: vec![],
+        }
+    }
+
+    /// Set the response of the filter, e.g. [`SvfMode::Lowpass`] for a classic envelope filter.
+    pub fn set_mode(&mut self, mode: SvfMode) {
+        self.mode = mode;
+        self.filters.iter_mut().for_each(|filter| filter.set_mode(mode));
+    }
+
+    /// Set the gain in dB applied to the envelope. The higher the sensitivity, the further a
+    /// quiet note sweeps the filter.
+    pub fn set_sensitivity(&mut self, sensitivity: f32) {
+        self.sensitivity = sensitivity;
+        self.sensitivity_gain = 10.0f32.powf(sensitivity / 20.0);
+    }
+
+    /// Set the range of the cutoff in Hz.
+    ///
+    /// # Panics
+    ///
+    /// * If the frequencies are not positive and ascending.
+    /// * If the highest frequency is not below the Nyquist frequency once the effect is prepared.
+    pub fn set_frequency_range(&mut self, min_frequency: f32, max_frequency: f32) {
+        assert!(min_frequency > 0.0 && min_frequency < max_frequency);
+        assert!(self.sample_rate == 0.0 || max_frequency < self.sample_rate / 2.0);
+        self.min_frequency = min_frequency;
+        self.max_frequency = max_frequency;
+    }
+
+    pub fn set_direction(&mut self, direction: WahDirection) {
+        self.direction = direction;
+    }
+
+    /// Set the resonance as the Q of the filter.
+    pub fn set_resonance(&mut self, resonance: f32) {
+        assert!(resonance > 0.0);
+        self.resonance = resonance;
+        self.filters.iter_mut().for_each(|filter| filter.set_q(resonance));
+    }
+
+    /// Set the attack time of the envelope in ms.
+    pub fn set_attack(&mut self, attack: f32) {
+        assert!(attack >= 0.0);
+        self.attack = attack;
+        self.attack_coeff = time_constant_coeff(attack, self.sample_rate);
+    }
+
+    /// Set the release time of the envelope in ms.
+    pub fn set_release(&mut self, release: f32) {
+        assert!(release >= 0.0);
+        self.release = release;
+        self.release_coeff = time_constant_coeff(release, self.sample_rate);
+    }
+
+    /// Set the gain of the dry signal below the crossover frequency, blended into the output.
+    pub fn set_dry_blend(&mut self, dry_blend: f32) {
+        assert!(dry_blend >= 0.0);
+        self.dry_blend = dry_blend;
+    }
+
+    /// Set the frequency in Hz below which the dry signal is blended into the output.
+    ///
+    /// # Panics
+    ///
+    /// * If the frequency is not positive, or not below the Nyquist frequency once the effect is
+    ///   prepared.
+    pub fn set_dry_crossover(&mut self, dry_crossover: f32) {
+        assert!(dry_crossover > 0.0);
+        self.dry_crossover = dry_crossover;
+        self.dry_filters.iter_mut().for_each(|filter| filter.set_cutoff(dry_crossover));
+    }
+
+    /// The current cutoff of the filter in Hz.
+    pub fn cutoff(&self) -> f32 {
+        self.cutoff
+    }
+
+    /// Move the cutoff of the filters to follow the envelope.
+    fn update_cutoff(&mut self) {
+        // The position of the cutoff in the range, from the rest frequency to the other end
+        let amount = (self.envelope * self.sensitivity_gain).min(1.0);
+        let octaves = (self.max_frequency / self.min_frequency).log2();
+        self.cutoff = match self.direction {
+            WahDirection::Up => self.min_frequency * (amount * octaves).exp2(),
+            WahDirection::Down => self.max_frequency * (-amount * octaves).exp2(),
+        };
+        self.filters.iter_mut().for_each(|filter| filter.set_cutoff(self.cutoff));
+    }
+
+    /// The cutoff without input.
+    fn rest_frequency(&self) -> f32 {
+        match self.direction {
+            WahDirection::Up => self.min_frequency,
+            WahDirection::Down => self.max_frequency,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer_view::BufferView;
+    use crate::utilities::testing::{magnitude, sine};
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    #[test]
+    fn sweep() {
+        let cases = [(WahDirection::Up, 250.0, 2500.0), (WahDirection::Down, 2500.0, 250.0)];
+        for (direction, rest, swept) in cases {
+            let mut wah = AutoWah::new(1);
+            wah.set_direction(direction);
+            wah.prepare(SAMPLE_RATE, 512);
+            assert_eq!(wah.cutoff(), rest);
+
+            // A 0 dBFS note amplified by the default sensitivity of 6 dB sweeps the whole range
+            wah.process(BufferView::new(&[&sine(100.0, 1.0, SAMPLE_RATE, 4800)]));
+            assert!((wah.cutoff() / swept).log2().abs() < 0.05, "{direction:?}: {}", wah.cutoff());
+
+            // The filter returns to rest in silence
+            wah.process(BufferView::new(&[&[0.0; 48000]]));
+            assert!((wah.cutoff() / rest).log2().abs() < 0.01, "{direction:?}: {}", wah.cutoff());
+        }
+    }
+
+    #[test]
+    fn sensitivity() {
+        // A -20 dBFS note sweeps a tenth of the range at 0 dB, and the whole range above 20 dB
+        let mut wah = AutoWah::new(1);
+        wah.set_sensitivity(0.0);
+        wah.set_release(1000.0);
+        wah.prepare(SAMPLE_RATE, 512);
+        wah.process(BufferView::new(&[&sine(100.0, 0.1, SAMPLE_RATE, 4800)]));
+        let expected = 250.0 * 10.0f32.powf(0.1);
+        assert!((wah.cutoff() / expected).log2().abs() < 0.05, "{}", wah.cutoff());
+
+        wah.set_sensitivity(26.0);
+        wah.process(BufferView::new(&[&sine(100.0, 0.1, SAMPLE_RATE, 4800)]));
+        assert!((wah.cutoff() / 2500.0).log2().abs() < 0.05, "{}", wah.cutoff());
+    }
+
+    #[test]
+    fn dry_blend_keeps_low_end() {
+        // The fundamental of a low E (41.2 Hz) is removed by the wah alone
+        let input = sine(41.2, 0.5, SAMPLE_RATE, 48000);
+        let mut wah = AutoWah::new(2);
+        wah.set_dry_blend(0.0);
+        wah.prepare(SAMPLE_RATE, 512);
+        let output = wah.process(BufferView::new(&[&input, &input]));
+        assert!(magnitude(&output[0][24000..], 41.2, SAMPLE_RATE) < 0.05);
+
+        // And kept with the dry blend
+        wah.set_dry_blend(1.0);
+        wah.reset();
+        let output = wah.process(BufferView::new(&[&input, &input]));
+        for channel in output.iter() {
+            assert!((magnitude(&channel[24000..], 41.2, SAMPLE_RATE) - 0.5).abs() < 0.05);
+        }
+    }
+}
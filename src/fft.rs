@@ -0,0 +1,293 @@
+//! Radix-2 fast Fourier transform.
+//!
+//! [`Fft`] is an in-place complex FFT and [`RealFft`] is the FFT of real signals, computed with a
+//! complex FFT of half the length. Both of them precompute the twiddle factors on construction and
+//! do not allocate when transforming, so they can be used in real-time processing.
+//!
+//! The forward transforms are not scaled, and the inverse transforms are scaled by `1 / n`, so
+//! that a forward transform followed by an inverse transform gives back the input.
+
+use std::f64::consts::PI;
+
+use crate::complex::Complex;
+
+/// In-place radix-2 complex FFT of a fixed power-of-two length.
+#[derive(Debug, Clone)]
+pub struct Fft {
+    size: usize,
+    /// `exp(-2 pi j k / size)` for `k` in `[0, size / 2)`.
+    twiddles: Vec<Complex<f32>>,
+    /// The bit-reversed index of each index.
+    bit_reversed: Vec<usize>,
+}
+
+/// FFT of real signals of a fixed power-of-two length.
+///
+/// The spectrum of a real signal of length `n` is conjugate symmetric, so only the `n / 2 + 1`
+/// bins from DC to the Nyquist frequency are stored.
+#[derive(Debug, Clone)]
+pub struct RealFft {
+    size: usize,
+    half: Fft,
+    /// `exp(-2 pi j k / size)` for `k` in `[0, size / 2)`.
+    twiddles: Vec<Complex<f32>>,
+}
+
+impl Fft {
+    /// Create an FFT of the given length.
+    ///
+    /// # Panics
+    ///
+    /// * If `size` is not a power of two.
+    pub fn new(size: usize) -> Self {
+        assert!(size.is_power_of_two(), "The FFT size must be a power of two");
+        let bits = size.trailing_zeros();
+        let bit_reversed = (0..size)
+            .map(|i| if bits == 0 { 0 } else { i.reverse_bits() >> (usize::BITS - bits) })
+            .collect();
+        Self {
+            size,
+            twiddles: twiddles(size, size / 2),
+            bit_reversed,
+        }
+    }
+
+    /// The length of the transform.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Forward transform in place.
+    ///
+    /// # Panics
+    ///
+    /// * If the length of `data` is not the FFT size.
+    pub fn forward(&self, data: &mut [Complex<f32>]) {
+        self.transform(data, false);
+    }
+
+    /// Inverse transform in place, scaled by `1 / size`.
+    ///
+    /// # Panics
+    ///
+    /// * If the length of `data` is not the FFT size.
+    pub fn inverse(&self, data: &mut [Complex<f32>]) {
+        self.transform(data, true);
+        let scale = 1.0 / self.size as f32;
+        data.iter_mut().for_each(|x| *x *= scale);
+    }
+
+    /// Iterative decimation-in-time transform. The inverse transform uses the conjugate twiddle
+    /// factors and is not scaled.
+    fn transform(&self, data: &mut [Complex<f32>], inverse: bool) {
+        assert_eq!(data.len(), self.size, "The data length must be the FFT size");
+
+        for (i, &j) in self.bit_reversed.iter().enumerate() {
+            if i < j {
+                data.swap(i, j);
+            }
+        }
+
+        let mut len = 2;
+        while len <= self.size {
+            let half_len = len / 2;
+            let stride = self.size / len;
+            for chunk in data.chunks_exact_mut(len) {
+                let (lower, upper) = chunk.split_at_mut(half_len);
+                for (k, (a, b)) in lower.iter_mut().zip(upper.iter_mut()).enumerate() {
+                    let twiddle = self.twiddles[k * stride];
+                    let twiddle = if inverse { twiddle.conj() } else { twiddle };
+                    let t = *b * twiddle;
+                    *b = *a - t;
+                    *a += t;
+                }
+            }
+            len *= 2;
+        }
+    }
+}
+
+impl RealFft {
+    /// Create a real FFT of the given length.
+    ///
+    /// # Panics
+    ///
+    /// * If `size` is not a power of two, or is less than 2.
+    pub fn new(size: usize) -> Self {
+        assert!(size >= 2 && size.is_power_of_two(), "The FFT size must be a power of two and at least 2");
+        Self {
+            size,
+            half: Fft::new(size / 2),
+            twiddles: twiddles(size, size / 2),
+        }
+    }
+
+    /// The length of the real signals.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The number of bins of the spectrum, `size / 2 + 1`.
+    pub fn spectrum_size(&self) -> usize {
+        self.size / 2 + 1
+    }
+
+    /// Forward transform of a real signal.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The real signal with the length of the FFT size.
+    /// * `spectrum` - The output spectrum with `size / 2 + 1` bins.
+    ///
+    /// # Panics
+    ///
+    /// * If the lengths of `input` or `spectrum` are not as described above.
+    pub fn forward(&self, input: &[f32], spectrum: &mut [Complex<f32>]) {
+        assert_eq!(input.len(), self.size, "The input length must be the FFT size");
+        assert_eq!(spectrum.len(), self.spectrum_size(), "The spectrum length must be size / 2 + 1");
+        let half_size = self.size / 2;
+
+        // Transform the even samples as the real part and the odd samples as the imaginary part
+        for (z, pair) in spectrum.iter_mut().zip(input.chunks_exact(2)) {
+            *z = Complex::new(pair[0], pair[1]);
+        }
+        self.half.forward(&mut spectrum[..half_size]);
+
+        // Separate the spectra of the even and the odd samples, and combine them. The bins k and
+        // n / 2 - k depend on the same pair of bins, so they are computed together.
+        let z0 = spectrum[0];
+        spectrum[0] = Complex::new(z0.re + z0.im, 0.0);
+        spectrum[half_size] = Complex::new(z0.re - z0.im, 0.0);
+        for k in 1..=half_size / 2 {
+            let (zk, zm) = (spectrum[k], spectrum[half_size - k]);
+            spectrum[k] = self.combine(zk, zm, k);
+            spectrum[half_size - k] = self.combine(zm, zk, half_size - k);
+        }
+    }
+
+    /// Inverse transform to a real signal, scaled by `1 / size`.
+    ///
+    /// # Arguments
+    ///
+    /// * `spectrum` - The spectrum with `size / 2 + 1` bins. It is used as the working buffer, so
+    ///   its content is destroyed.
+    /// * `output` - The output real signal with the length of the FFT size.
+    ///
+    /// # Panics
+    ///
+    /// * If the lengths of `spectrum` or `output` are not as described above.
+    pub fn inverse(&self, spectrum: &mut [Complex<f32>], output: &mut [f32]) {
+        assert_eq!(spectrum.len(), self.spectrum_size(), "The spectrum length must be size / 2 + 1");
+        assert_eq!(output.len(), self.size, "The output length must be the FFT size");
+        let half_size = self.size / 2;
+
+        // Recover the spectrum of the even samples plus j times the spectrum of the odd samples
+        let (x0, xn) = (spectrum[0], spectrum[half_size]);
+        spectrum[0] = Complex::new((x0.re + xn.re) / 2.0, (x0.re - xn.re) / 2.0);
+        for k in 1..=half_size / 2 {
+            let (xk, xm) = (spectrum[k], spectrum[half_size - k]);
+            spectrum[k] = self.split(xk, xm, k);
+            spectrum[half_size - k] = self.split(xm, xk, half_size - k);
+        }
+
+        self.half.inverse(&mut spectrum[..half_size]);
+        for (pair, z) in output.chunks_exact_mut(2).zip(spectrum.iter()) {
+            pair[0] = z.re;
+            pair[1] = z.im;
+        }
+    }
+
+    /// Bin `k` of the real spectrum from the bins `k` and `n / 2 - k` of the half-length spectrum.
+    fn combine(&self, zk: Complex<f32>, zm: Complex<f32>, k: usize) -> Complex<f32> {
+        let even = (zk + zm.conj()) * 0.5;
+        let odd = (zk - zm.conj()) * Complex::new(0.0, -0.5);
+        even + self.twiddles[k] * odd
+    }
+
+    /// The inverse of [`combine`](Self::combine).
+    fn split(&self, xk: Complex<f32>, xm: Complex<f32>, k: usize) -> Complex<f32> {
+        let even = (xk + xm.conj()) * 0.5;
+        let odd = (xk - xm.conj()) * self.twiddles[k].conj() * 0.5;
+        even + Complex::<f32>::I * odd
+    }
+}
+
+/// The first `count` powers of `exp(-2 pi j / size)`, computed in double precision.
+fn twiddles(size: usize, count: usize) -> Vec<Complex<f32>> {
+    (0..count)
+        .map(|k| {
+            let phase = -2.0 * PI * k as f64 / size as f64;
+            Complex::new(phase.cos() as f32, phase.sin() as f32)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    /// Direct evaluation of the DFT.
+    fn dft(input: &[Complex<f32>]) -> Vec<Complex<f32>> {
+        let n = input.len();
+        (0..n)
+            .map(|k| {
+                input.iter().enumerate().fold(Complex::<f32>::ZERO, |sum, (i, &x)| {
+                    let phase = -2.0 * PI * ((i * k) % n) as f64 / n as f64;
+                    sum + x * Complex::new(phase.cos() as f32, phase.sin() as f32)
+                })
+            })
+            .collect()
+    }
+
+    fn test_signal(n: usize) -> Vec<f32> {
+        (0..n).map(|i| ((i * 7 + 3) % 11) as f32 / 5.0 - 1.0 + (i as f32 * 0.3).sin()).collect()
+    }
+
+    fn assert_complex_close(a: &[Complex<f32>], b: &[Complex<f32>]) {
+        assert_eq!(a.len(), b.len());
+        for (x, y) in a.iter().zip(b.iter()) {
+            assert_abs_diff_eq!(x.re, y.re, epsilon = 1e-4);
+            assert_abs_diff_eq!(x.im, y.im, epsilon = 1e-4);
+        }
+    }
+
+    #[test]
+    fn complex_matches_dft() {
+        for size in [1, 2, 4, 8, 64] {
+            let signal = test_signal(2 * size);
+            let input: Vec<Complex<f32>> = signal.chunks(2).map(|c| Complex::new(c[0], c[1])).collect();
+            let fft = Fft::new(size);
+            let mut data = input.clone();
+            fft.forward(&mut data);
+            assert_complex_close(&data, &dft(&input));
+
+            fft.inverse(&mut data);
+            assert_complex_close(&data, &input);
+        }
+    }
+
+    #[test]
+    fn real_matches_dft() {
+        for size in [2, 4, 8, 16, 128] {
+            let input = test_signal(size);
+            let fft = RealFft::new(size);
+            let mut spectrum = vec![Complex::<f32>::ZERO; fft.spectrum_size()];
+            fft.forward(&input, &mut spectrum);
+
+            let complex_input: Vec<Complex<f32>> = input.iter().map(|&x| Complex::from(x)).collect();
+            let expected = dft(&complex_input);
+            assert_complex_close(&spectrum, &expected[..size / 2 + 1]);
+
+            let mut output = vec![0.0; size];
+            fft.inverse(&mut spectrum, &mut output);
+            crate::assert_all_close!(output, input, 1e-5);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn non_power_of_two() {
+        let _ = Fft::new(12);
+    }
+}
@@ -0,0 +1,321 @@
+//! Comb and allpass filters with integer delays, the building blocks of Schroeder-style reverbs,
+//! flangers and diffusers.
+
+use crate::filter::Filter;
+
+/// The structure of a [`CombFilter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CombType {
+    /// `y[n] = x[n] + g x[n - D]`.
+    Feedforward,
+    /// `H(z) = z^-D / (1 - g L(z) z^-D)`, where `L(z)` is the in-loop damping lowpass filter.
+    /// The output is taken after the delay as in Freeverb, so it starts `D` samples after the
+    /// input.
+    Feedback,
+}
+
+/// The structure of a [`SchroederAllpass`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllpassType {
+    /// `H(z) = (-g + z^-D) / (1 - g z^-D)`, which is truly allpass.
+    Schroeder,
+    /// The "allpass" of Freeverb, `H(z) = (-1 + (1 + g) z^-D) / (1 - g z^-D)`. Its magnitude
+    /// response is flat only for `g = (sqrt(5) - 1) / 2`, so it colors the sound otherwise.
+    Freeverb,
+}
+
+/// Feedforward or feedback comb filter.
+///
+/// The feedback comb filter has a one-pole lowpass filter `L(z) = (1 - d) / (1 - d z^-1)` in its
+/// loop, so that the high frequencies decay faster, where `d` is the damping in `[0, 1)`.
+pub struct CombFilter {
+    comb_type: CombType,
+    delay: usize,
+    gain: f32,
+    damping: f32,
+    /// The state of the damping lowpass filter.
+    damping_state: f32,
+    /// The delay buffer. The length is a power of 2 greater than the delay.
+    buffer: Vec<f32>,
+    /// Index of the next sample to be written to the buffer.
+    buffer_index: usize,
+}
+
+/// Schroeder allpass filter `H(z) = (-g + z^-D) / (1 - g z^-D)`.
+///
+/// The magnitude response is flat, while the impulse response is a train of decaying echoes,
+/// which makes it a good diffuser. The variant of Freeverb is available through
+/// [`freeverb`](Self::freeverb).
+pub struct SchroederAllpass {
+    allpass_type: AllpassType,
+    delay: usize,
+    gain: f32,
+    /// The delay buffer. The length is a power of 2 greater than the delay.
+    buffer: Vec<f32>,
+    /// Index of the next sample to be written to the buffer.
+    buffer_index: usize,
+}
+
+impl Filter for CombFilter {
+    fn process_inplace(&mut self, buffer: &mut [f32]) {
+        for sample in buffer.iter_mut() {
+            *sample = self.process_sample(*sample);
+        }
+    }
+
+    fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        let mut output = input.to_vec();
+        self.process_inplace(&mut output);
+        output
+    }
+
+    fn reset(&mut self) {
+        self.buffer.fill(0.0);
+        self.buffer_index = 0;
+        self.damping_state = 0.0;
+    }
+}
+
+impl CombFilter {
+    /// Create a feedforward comb filter `y[n] = x[n] + g x[n - D]`.
+    ///
+    /// # Panics
+    ///
+    /// * If `delay` is zero.
+    pub fn feedforward(delay: usize, gain: f32) -> Self {
+        Self::new(CombType::Feedforward, delay, gain, 0.0)
+    }
+
+    /// Create a feedback comb filter with in-loop damping.
+    ///
+    /// # Arguments
+    ///
+    /// * `delay` - The loop delay in samples.
+    /// * `feedback` - The loop gain, which must be in `(-1, 1)` for the filter to be stable.
+    /// * `damping` - The pole of the in-loop lowpass filter in `[0, 1)`. 0.0 means no damping.
+    ///
+    /// # Panics
+    ///
+    /// * If `delay` is zero.
+    pub fn feedback(delay: usize, feedback: f32, damping: f32) -> Self {
+        Self::new(CombType::Feedback, delay, feedback, damping)
+    }
+
+    fn new(comb_type: CombType, delay: usize, gain: f32, damping: f32) -> Self {
+        assert!(delay > 0, "The delay must be greater than 0");
+        let mut filter = Self {
+            comb_type,
+            delay,
+            gain,
+            damping: 0.0,
+            damping_state: 0.0,
+            buffer: vec![0.0; (delay + 1).next_power_of_two()],
+            buffer_index: 0,
+        };
+        filter.set_damping(damping);
+        filter
+    }
+
+    pub fn comb_type(&self) -> CombType {
+        self.comb_type
+    }
+
+    /// Set the delay in samples. The buffer is reallocated (and cleared) only if the delay exceeds
+    /// its capacity.
+    ///
+    /// # Panics
+    ///
+    /// * If `delay` is zero.
+    pub fn set_delay(&mut self, delay: usize) {
+        assert!(delay > 0, "The delay must be greater than 0");
+        if delay >= self.buffer.len() {
+            self.buffer = vec![0.0; (delay + 1).next_power_of_two()];
+            self.buffer_index = 0;
+        }
+        self.delay = delay;
+    }
+
+    /// Set the feedforward gain or the feedback gain.
+    pub fn set_gain(&mut self, gain: f32) {
+        self.gain = gain;
+    }
+
+    /// Set the damping of the feedback loop in `[0, 1)`. It has no effect on feedforward filters.
+    pub fn set_damping(&mut self, damping: f32) {
+        assert!(
+            (0.0..1.0).contains(&damping),
+            "The damping must be in the range [0, 1)"
+        );
+        self.damping = damping;
+    }
+
+    pub fn process_sample(&mut self, x: f32) -> f32 {
+        let mask = self.buffer.len() - 1;
+        let delayed = self.buffer[self.buffer_index.wrapping_sub(self.delay) & mask];
+        let (stored, y) = match self.comb_type {
+            CombType::Feedforward => (x, x + self.gain * delayed),
+            CombType::Feedback => {
+                self.damping_state =
+                    (1.0 - self.damping) * delayed + self.damping * self.damping_state;
+                (x + self.gain * self.damping_state, delayed)
+            }
+        };
+        self.buffer[self.buffer_index] = stored;
+        self.buffer_index = (self.buffer_index + 1) & mask;
+        y
+    }
+}
+
+impl Filter for SchroederAllpass {
+    fn process_inplace(&mut self, buffer: &mut [f32]) {
+        for sample in buffer.iter_mut() {
+            *sample = self.process_sample(*sample);
+        }
+    }
+
+    fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        let mut output = input.to_vec();
+        self.process_inplace(&mut output);
+        output
+    }
+
+    fn reset(&mut self) {
+        self.buffer.fill(0.0);
+        self.buffer_index = 0;
+    }
+}
+
+impl SchroederAllpass {
+    /// Create a Schroeder allpass filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `delay` - The delay in samples.
+    /// * `gain` - The allpass gain, which must be in `(-1, 1)` for the filter to be stable.
+    ///
+    /// # Panics
+    ///
+    /// * If `delay` is zero.
+    pub fn new(delay: usize, gain: f32) -> Self {
+        Self::with_type(AllpassType::Schroeder, delay, gain)
+    }
+
+    /// Create the allpass filter of Freeverb: `y[n] = -x[n] + v[n - D]`, where
+    /// `v[n] = x[n] + g v[n - D]`.
+    ///
+    /// # Panics
+    ///
+    /// * If `delay` is zero.
+    pub fn freeverb(delay: usize, gain: f32) -> Self {
+        Self::with_type(AllpassType::Freeverb, delay, gain)
+    }
+
+    fn with_type(allpass_type: AllpassType, delay: usize, gain: f32) -> Self {
+        assert!(delay > 0, "The delay must be greater than 0");
+        Self {
+            allpass_type,
+            delay,
+            gain,
+            buffer: vec![0.0; (delay + 1).next_power_of_two()],
+            buffer_index: 0,
+        }
+    }
+
+    /// Set the delay in samples. The buffer is reallocated (and cleared) only if the delay exceeds
+    /// its capacity.
+    ///
+    /// # Panics
+    ///
+    /// * If `delay` is zero.
+    pub fn set_delay(&mut self, delay: usize) {
+        assert!(delay > 0, "The delay must be greater than 0");
+        if delay >= self.buffer.len() {
+            self.buffer = vec![0.0; (delay + 1).next_power_of_two()];
+            self.buffer_index = 0;
+        }
+        self.delay = delay;
+    }
+
+    pub fn allpass_type(&self) -> AllpassType {
+        self.allpass_type
+    }
+
+    pub fn set_gain(&mut self, gain: f32) {
+        self.gain = gain;
+    }
+
+    pub fn process_sample(&mut self, x: f32) -> f32 {
+        let mask = self.buffer.len() - 1;
+        let delayed = self.buffer[self.buffer_index.wrapping_sub(self.delay) & mask];
+        let v = x + self.gain * delayed;
+        self.buffer[self.buffer_index] = v;
+        self.buffer_index = (self.buffer_index + 1) & mask;
+        match self.allpass_type {
+            AllpassType::Schroeder => delayed - self.gain * v,
+            AllpassType::Freeverb => delayed - x,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert_all_close;
+    use crate::filter::design::response::FrequencyResponse;
+    use crate::filter::design::FirCoeffs;
+    use crate::utilities::testing::impulse;
+
+    #[test]
+    fn feedforward() {
+        let mut filter = CombFilter::feedforward(3, 0.5);
+        let output = filter.process(&impulse(8));
+        assert_all_close!(output, [1.0, 0.0, 0.0, 0.5, 0.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn feedback() {
+        let mut filter = CombFilter::feedback(2, 0.5, 0.0);
+        let output = filter.process(&impulse(8));
+        assert_all_close!(output, [0.0, 0.0, 1.0, 0.0, 0.5, 0.0, 0.25, 0.0]);
+    }
+
+    #[test]
+    fn feedback_damping() {
+        // The echoes are smeared by the lowpass filter in the loop
+        let mut filter = CombFilter::feedback(4, 0.8, 0.5);
+        let output = filter.process(&impulse(12));
+        assert_all_close!(output[..8], [0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0]);
+        assert_all_close!(output[8..], [0.4, 0.2, 0.1, 0.05]);
+
+        filter.reset();
+        let output = filter.process(&[0.0; 12]);
+        assert!(output.iter().all(|&y| y == 0.0));
+    }
+
+    #[test]
+    fn allpass_is_flat() {
+        let mut filter = SchroederAllpass::new(7, 0.6);
+        let output = filter.process(&impulse(400));
+        let sample_rate = 48000.0;
+        let freqs: Vec<f32> = (0..50).map(|i| i as f32 * 480.0).collect();
+        for magnitude in FirCoeffs::new(output).magnitude_db(&freqs, sample_rate) {
+            assert!(magnitude.abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn freeverb_allpass() {
+        let mut filter = SchroederAllpass::freeverb(2, 0.5);
+        let output = filter.process(&impulse(8));
+        assert_all_close!(output, [-1.0, 0.0, 1.0, 0.0, 0.5, 0.0, 0.25, 0.0]);
+    }
+
+    #[test]
+    fn set_delay() {
+        let mut filter = SchroederAllpass::new(2, 0.5);
+        filter.set_delay(20);
+        let output = filter.process(&impulse(21));
+        assert_all_close!(output[..2], [-0.5, 0.0]);
+        assert_all_close!(output[20..], [0.75]);
+    }
+}
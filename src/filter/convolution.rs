@@ -0,0 +1,350 @@
+//! Partitioned FFT convolution for long FIR filters.
+//!
+//! The impulse response is split into partitions. The first partition (the head) is convolved
+//! directly in the time domain, so no latency is added. The rest of the impulse response is
+//! convolved in the frequency domain by stages, each of which is a uniformly partitioned
+//! overlap-save convolver with its own block size. A stage with the block size `B` produces its
+//! output `B` samples after the input, so it can only handle the part of the impulse response
+//! starting at or after `B`. Longer partitions are thus placed later in the impulse response.
+//!
+//! The outputs of all the stages are accumulated in a shared output ring buffer, in which each
+//! stage writes its output ahead of time.
+
+use crate::complex::Complex;
+use crate::fft::RealFft;
+use crate::filter::Filter;
+use crate::filter::design::FirCoeffs;
+
+/// Zero-latency partitioned convolver for long FIR filters.
+///
+/// The uniform partitioning ([`PartitionedConvolver::new`]) uses one block size, which is simple
+/// and suitable for moderate lengths. The non-uniform partitioning
+/// ([`PartitionedConvolver::non_uniform`]) doubles the block size along the impulse response,
+/// which reduces the computation for long impulse responses (e.g. reverb) while keeping the head
+/// short.
+///
+/// Note that the FFT of a stage is computed all at once when its input block is complete, so the
+/// computation per sample is uneven and the host block size should be considered when choosing
+/// the block sizes.
+pub struct PartitionedConvolver {
+    /// The head of the impulse response, convolved directly.
+    head: Vec<f32>,
+    stages: Vec<Stage>,
+    /// The input history shared by the head and the stages. The length is a power of 2.
+    input: Vec<f32>,
+    /// The accumulated output of the stages. The length is a power of 2.
+    output: Vec<f32>,
+    /// The number of samples processed (wrapping).
+    time: usize,
+}
+
+/// A uniformly partitioned overlap-save convolver for a segment of the impulse response.
+struct Stage {
+    block_size: usize,
+    /// The position of the segment in the impulse response.
+    offset: usize,
+    fft: RealFft,
+    /// The spectra of the partitions of the segment.
+    partitions: Vec<Vec<Complex<f32>>>,
+    /// Frequency-domain delay line: the spectra of the recent input blocks.
+    delay_line: Vec<Vec<Complex<f32>>>,
+    /// Index of the most recent spectrum in the delay line.
+    delay_line_index: usize,
+    time_buffer: Vec<f32>,
+    accumulator: Vec<Complex<f32>>,
+}
+
+impl PartitionedConvolver {
+    /// Create a convolver with uniform partitions.
+    ///
+    /// # Arguments
+    ///
+    /// * `coeffs` - The FIR filter coefficients.
+    /// * `block_size` - The length of the partitions, which must be a power of 2. The first
+    ///   partition is convolved directly.
+    ///
+    /// # Panics
+    ///
+    /// * If `block_size` is not a power of 2.
+    pub fn new(coeffs: FirCoeffs, block_size: usize) -> Self {
+        assert!(block_size.is_power_of_two(), "The block size must be a power of 2");
+        let len = coeffs.b.len();
+        let stages = if len > block_size {
+            vec![(block_size, block_size, len - block_size)]
+        } else {
+            vec![]
+        };
+        Self::with_scheme(coeffs.b, block_size, &stages)
+    }
+
+    /// Create a convolver with non-uniform partitions.
+    ///
+    /// The head has `min_block_size` taps, and each following stage covers two partitions with
+    /// double the block size of the previous stage, until `max_block_size` is reached. The last
+    /// stage covers the rest of the impulse response.
+    ///
+    /// # Arguments
+    ///
+    /// * `coeffs` - The FIR filter coefficients.
+    /// * `min_block_size` - The length of the head and the block size of the first stage.
+    /// * `max_block_size` - The largest block size.
+    ///
+    /// # Panics
+    ///
+    /// * If `min_block_size` or `max_block_size` is not a power of 2.
+    /// * If `max_block_size` is less than `min_block_size`.
+    pub fn non_uniform(coeffs: FirCoeffs, min_block_size: usize, max_block_size: usize) -> Self {
+        assert!(
+            min_block_size.is_power_of_two() && max_block_size.is_power_of_two(),
+            "The block sizes must be powers of 2"
+        );
+        assert!(
+            max_block_size >= min_block_size,
+            "The maximum block size must not be less than the minimum block size"
+        );
+
+        let len = coeffs.b.len();
+        let mut stages = Vec::new();
+        let mut offset = min_block_size;
+        let mut block_size = min_block_size;
+        while offset < len {
+            // The offset is always at least the block size: it grows by twice the block size
+            // before the block size doubles
+            let segment_len = if block_size == max_block_size {
+                len - offset
+            } else {
+                (2 * block_size).min(len - offset)
+            };
+            stages.push((block_size, offset, segment_len));
+            offset += segment_len;
+            block_size = (2 * block_size).min(max_block_size);
+        }
+        Self::with_scheme(coeffs.b, min_block_size, &stages)
+    }
+
+    /// Create a convolver from the head length and the `(block_size, offset, len)` of the stages.
+    fn with_scheme(b: Vec<f32>, head_len: usize, stages: &[(usize, usize, usize)]) -> Self {
+        let head: Vec<f32> = b[..head_len.min(b.len())].to_vec();
+        let stages: Vec<Stage> = stages
+            .iter()
+            .map(|&(block_size, offset, len)| Stage::new(&b[offset..offset + len], block_size, offset))
+            .collect();
+
+        let max_block_size = stages.iter().map(|s| s.block_size).max().unwrap_or(1);
+        let max_offset = stages.iter().map(|s| s.offset).max().unwrap_or(0);
+        Self {
+            input: vec![0.0; (2 * max_block_size).max(head.len()).next_power_of_two()],
+            output: vec![0.0; (max_offset + 1).next_power_of_two()],
+            head,
+            stages,
+            time: 0,
+        }
+    }
+
+    /// The number of partitions convolved in the frequency domain by each stage.
+    pub fn num_partitions(&self) -> Vec<usize> {
+        self.stages.iter().map(|s| s.partitions.len()).collect()
+    }
+
+    fn process_sample(&mut self, x: f32) -> f32 {
+        let input_mask = self.input.len() - 1;
+        let output_mask = self.output.len() - 1;
+        self.input[self.time & input_mask] = x;
+
+        // Head in the time domain
+        let mut y: f32 = self.head
+            .iter()
+            .enumerate()
+            .map(|(i, &coeff)| coeff * self.input[self.time.wrapping_sub(i) & input_mask])
+            .sum();
+
+        // Output of the stages computed in the previous blocks
+        let output_index = self.time & output_mask;
+        y += self.output[output_index];
+        self.output[output_index] = 0.0;
+
+        // Run the stages of which the input block is complete. Their outputs start from the next
+        // sample at the earliest.
+        let end = self.time.wrapping_add(1);
+        for stage in self.stages.iter_mut() {
+            if end & (stage.block_size - 1) == 0 {
+                stage.process_block(&self.input, end, &mut self.output);
+            }
+        }
+
+        self.time = end;
+        y
+    }
+}
+
+impl Filter for PartitionedConvolver {
+    fn process_inplace(&mut self, buffer: &mut [f32]) {
+        for sample in buffer.iter_mut() {
+            *sample = self.process_sample(*sample);
+        }
+    }
+
+    fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        let mut output = input.to_vec();
+        self.process_inplace(&mut output);
+        output
+    }
+
+    fn reset(&mut self) {
+        self.input.fill(0.0);
+        self.output.fill(0.0);
+        self.time = 0;
+        for stage in self.stages.iter_mut() {
+            stage.reset();
+        }
+    }
+}
+
+impl Stage {
+    fn new(segment: &[f32], block_size: usize, offset: usize) -> Self {
+        debug_assert!(offset >= block_size);
+        let fft = RealFft::new(2 * block_size);
+        let spectrum_size = fft.spectrum_size();
+
+        // Each partition is zero-padded to the FFT size
+        let mut time_buffer = vec![0.0; 2 * block_size];
+        let partitions: Vec<Vec<Complex<f32>>> = segment
+            .chunks(block_size)
+            .map(|chunk| {
+                time_buffer.fill(0.0);
+                time_buffer[..chunk.len()].copy_from_slice(chunk);
+                let mut spectrum = vec![Complex::<f32>::ZERO; spectrum_size];
+                fft.forward(&time_buffer, &mut spectrum);
+                spectrum
+            })
+            .collect();
+
+        Self {
+            block_size,
+            offset,
+            delay_line: vec![vec![Complex::<f32>::ZERO; spectrum_size]; partitions.len()],
+            delay_line_index: 0,
+            partitions,
+            fft,
+            time_buffer,
+            accumulator: vec![Complex::<f32>::ZERO; spectrum_size],
+        }
+    }
+
+    /// Convolve the input block ending before `end` and add the result to the output ring buffer.
+    fn process_block(&mut self, input: &[f32], end: usize, output: &mut [f32]) {
+        let input_mask = input.len() - 1;
+        let output_mask = output.len() - 1;
+        let block_size = self.block_size;
+
+        // The last two blocks of the input
+        let start = end.wrapping_sub(2 * block_size);
+        for (i, x) in self.time_buffer.iter_mut().enumerate() {
+            *x = input[start.wrapping_add(i) & input_mask];
+        }
+        let num_partitions = self.partitions.len();
+        self.delay_line_index = (self.delay_line_index + 1) % num_partitions;
+        self.fft.forward(&self.time_buffer, &mut self.delay_line[self.delay_line_index]);
+
+        // Multiply the input spectra by the partition spectra
+        self.accumulator.fill(Complex::<f32>::ZERO);
+        for (p, partition) in self.partitions.iter().enumerate() {
+            let spectrum = &self.delay_line[(self.delay_line_index + num_partitions - p) % num_partitions];
+            for ((acc, &x), &h) in self.accumulator.iter_mut().zip(spectrum.iter()).zip(partition.iter()) {
+                *acc += x * h;
+            }
+        }
+        self.fft.inverse(&mut self.accumulator, &mut self.time_buffer);
+
+        // The second half is the valid (overlap-save) output of the block, delayed by the offset
+        let block_start = end.wrapping_sub(block_size);
+        for (i, &y) in self.time_buffer[block_size..].iter().enumerate() {
+            output[block_start.wrapping_add(self.offset + i) & output_mask] += y;
+        }
+    }
+
+    fn reset(&mut self) {
+        for spectrum in self.delay_line.iter_mut() {
+            spectrum.fill(Complex::<f32>::ZERO);
+        }
+        self.delay_line_index = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::FirFilter;
+    use rand::Rng;
+
+    fn random_signal(len: usize) -> Vec<f32> {
+        let mut rng = rand::rng();
+        (0..len).map(|_| rng.random_range(-1.0..1.0)).collect()
+    }
+
+    /// Process `input` in chunks of irregular lengths and compare with the direct convolution.
+    fn assert_matches_direct(mut convolver: PartitionedConvolver, coeffs: FirCoeffs) {
+        let input = random_signal(5000);
+        let mut direct = FirFilter::new(coeffs);
+        let expected = direct.process(&input);
+
+        let mut output = Vec::new();
+        let mut start = 0;
+        for len in [1, 7, 64, 100, 333].iter().cycle() {
+            if start >= input.len() {
+                break;
+            }
+            let end = (start + len).min(input.len());
+            output.extend(convolver.process(&input[start..end]));
+            start = end;
+        }
+        crate::assert_all_close!(output, expected, 1e-3);
+    }
+
+    #[test]
+    fn impulse_response() {
+        let taps: Vec<f32> = (0..300).map(|i| 1.0 / (i + 1) as f32).collect();
+        let mut convolver = PartitionedConvolver::new(FirCoeffs::new(taps.clone()), 32);
+        let mut impulse = vec![0.0; 400];
+        impulse[0] = 1.0;
+        let output = convolver.process(&impulse);
+        crate::assert_all_close!(output[..300], taps, 1e-5);
+        crate::assert_all_close!(output[300..], [0.0; 100], 1e-5);
+    }
+
+    #[test]
+    fn uniform() {
+        let coeffs = FirCoeffs::new(random_signal(1000));
+        let convolver = PartitionedConvolver::new(coeffs.clone(), 64);
+        assert_eq!(convolver.num_partitions(), [15]);
+        assert_matches_direct(convolver, coeffs);
+    }
+
+    #[test]
+    fn non_uniform() {
+        let coeffs = FirCoeffs::new(random_signal(3000));
+        let convolver = PartitionedConvolver::non_uniform(coeffs.clone(), 16, 256);
+        // 16 + 2 * (16 + 32 + 64 + 128) taps before the last stage of 256
+        assert_eq!(convolver.num_partitions(), [2, 2, 2, 2, 10]);
+        assert_matches_direct(convolver, coeffs);
+    }
+
+    #[test]
+    fn short_filter() {
+        // No stage is needed if the filter fits in the head
+        let coeffs = FirCoeffs::new(vec![0.5, -0.25, 0.125]);
+        let convolver = PartitionedConvolver::non_uniform(coeffs.clone(), 64, 1024);
+        assert!(convolver.num_partitions().is_empty());
+        assert_matches_direct(convolver, coeffs);
+    }
+
+    #[test]
+    fn reset() {
+        let coeffs = FirCoeffs::new(random_signal(500));
+        let mut convolver = PartitionedConvolver::new(coeffs, 32);
+        let _ = convolver.process(&random_signal(200));
+        convolver.reset();
+        let output = convolver.process(&[0.0; 600]);
+        assert!(output.iter().all(|&y| y == 0.0));
+    }
+}
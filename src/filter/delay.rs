@@ -19,6 +19,7 @@ use crate::filter::design::delay::{
     linear_interpolation,
     sinc_interpolation,
 };
+use crate::filter::design::window::Window;
 
 pub trait DelayFilter: Filter {
     fn delay(&self) -> f32;
@@ -85,7 +86,7 @@ impl Filter for SincInterpDelay {
 }
 
 impl SincInterpDelay {
-    pub fn new(delay: f32, sinc_half_width: Option<usize>, window_type: Option<&str>) -> Self {
+    pub fn new(delay: f32, sinc_half_width: Option<usize>, window_type: Option<Window>) -> Self {
         let coeffs = sinc_interpolation(delay, sinc_half_width, window_type);
         Self {
             delay,
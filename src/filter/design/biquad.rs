@@ -0,0 +1,328 @@
+//! Biquad filter design based on Robert Bristow-Johnson's "Audio EQ Cookbook".
+//!
+//! Every function in this module returns a single [`SecondOrderSection`], which can be run with
+//! [`SosFilter`](crate::filter::SosFilter) or combined with other sections into a
+//! [`SosCoeffs`](super::SosCoeffs) cascade. The width of the filters is given by [`Width`], so the
+//! quality factor, the bandwidth and the shelf slope forms of the cookbook are all available.
+//!
+//! The coefficients are computed in double precision and then rounded to `f32`.
+
+use std::f64::consts::{LN_2, PI};
+
+use super::SecondOrderSection;
+
+/// The width of a biquad filter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Width {
+    /// The quality factor. `1 / sqrt(2)` gives the Butterworth response for lowpass and highpass
+    /// filters.
+    Q(f32),
+    /// The bandwidth in octaves. For bandpass and notch filters, it is the bandwidth between the
+    /// -3 dB frequencies; for peaking filters, it is the bandwidth between the midpoint (dB gain)
+    /// frequencies.
+    Bandwidth(f32),
+    /// The shelf slope. When it is 1.0, the shelf is as steep as it can be while remaining
+    /// monotonically increasing or decreasing. Mainly meaningful for the shelving filters.
+    Slope(f32),
+}
+
+/// Lowpass filter. The response is `H(s) = 1 / (s^2 + s/Q + 1)`.
+///
+/// # Arguments
+///
+/// * `sample_rate` - The sample rate in Hz.
+/// * `freq` - The cutoff frequency in Hz.
+/// * `width` - The width (resonance) of the filter.
+///
+/// # Panics
+///
+/// * If `sample_rate` is not positive.
+/// * If `freq` is not in the range `(0, sample_rate / 2)`.
+pub fn lowpass(sample_rate: f32, freq: f32, width: Width) -> SecondOrderSection {
+    let (cos_w0, alpha) = intermediates(sample_rate, freq, width, 0.0);
+    section(
+        (1.0 - cos_w0) / 2.0,
+        1.0 - cos_w0,
+        (1.0 - cos_w0) / 2.0,
+        1.0 + alpha,
+        -2.0 * cos_w0,
+        1.0 - alpha,
+    )
+}
+
+/// Highpass filter. The response is `H(s) = s^2 / (s^2 + s/Q + 1)`.
+///
+/// The arguments and the panics are the same as [`lowpass`].
+pub fn highpass(sample_rate: f32, freq: f32, width: Width) -> SecondOrderSection {
+    let (cos_w0, alpha) = intermediates(sample_rate, freq, width, 0.0);
+    section(
+        (1.0 + cos_w0) / 2.0,
+        -(1.0 + cos_w0),
+        (1.0 + cos_w0) / 2.0,
+        1.0 + alpha,
+        -2.0 * cos_w0,
+        1.0 - alpha,
+    )
+}
+
+/// Bandpass filter with a constant 0 dB peak gain. The response is
+/// `H(s) = (s/Q) / (s^2 + s/Q + 1)`.
+///
+/// The arguments and the panics are the same as [`lowpass`], except that `freq` is the center
+/// frequency.
+pub fn bandpass(sample_rate: f32, freq: f32, width: Width) -> SecondOrderSection {
+    let (cos_w0, alpha) = intermediates(sample_rate, freq, width, 0.0);
+    section(
+        alpha,
+        0.0,
+        -alpha,
+        1.0 + alpha,
+        -2.0 * cos_w0,
+        1.0 - alpha,
+    )
+}
+
+/// Notch filter. The response is `H(s) = (s^2 + 1) / (s^2 + s/Q + 1)`.
+///
+/// The arguments and the panics are the same as [`lowpass`], except that `freq` is the center
+/// frequency.
+pub fn notch(sample_rate: f32, freq: f32, width: Width) -> SecondOrderSection {
+    let (cos_w0, alpha) = intermediates(sample_rate, freq, width, 0.0);
+    section(
+        1.0,
+        -2.0 * cos_w0,
+        1.0,
+        1.0 + alpha,
+        -2.0 * cos_w0,
+        1.0 - alpha,
+    )
+}
+
+/// Allpass filter. The response is `H(s) = (s^2 - s/Q + 1) / (s^2 + s/Q + 1)`.
+///
+/// The arguments and the panics are the same as [`lowpass`], except that `freq` is the frequency
+/// where the phase shift is 180 degrees.
+pub fn allpass(sample_rate: f32, freq: f32, width: Width) -> SecondOrderSection {
+    let (cos_w0, alpha) = intermediates(sample_rate, freq, width, 0.0);
+    section(
+        1.0 - alpha,
+        -2.0 * cos_w0,
+        1.0 + alpha,
+        1.0 + alpha,
+        -2.0 * cos_w0,
+        1.0 - alpha,
+    )
+}
+
+/// Peaking EQ filter. The response is `H(s) = (s^2 + s*(A/Q) + 1) / (s^2 + s/(A*Q) + 1)`, where
+/// `A = 10^(gain_db / 40)`.
+///
+/// # Arguments
+///
+/// * `sample_rate` - The sample rate in Hz.
+/// * `freq` - The center frequency in Hz.
+/// * `width` - The width of the boost or the cut.
+/// * `gain_db` - The gain at the center frequency in dB.
+///
+/// # Panics
+///
+/// * If `sample_rate` is not positive.
+/// * If `freq` is not in the range `(0, sample_rate / 2)`.
+pub fn peaking(sample_rate: f32, freq: f32, width: Width, gain_db: f32) -> SecondOrderSection {
+    let a = amplitude(gain_db);
+    let (cos_w0, alpha) = intermediates(sample_rate, freq, width, gain_db);
+    section(
+        1.0 + alpha * a,
+        -2.0 * cos_w0,
+        1.0 - alpha * a,
+        1.0 + alpha / a,
+        -2.0 * cos_w0,
+        1.0 - alpha / a,
+    )
+}
+
+/// Low-shelf filter. The gain below `freq` is `gain_db`, and the gain above it is 0 dB.
+///
+/// The arguments and the panics are the same as [`peaking`], except that `freq` is the midpoint
+/// (half of the dB gain) frequency of the shelf.
+pub fn low_shelf(sample_rate: f32, freq: f32, width: Width, gain_db: f32) -> SecondOrderSection {
+    let a = amplitude(gain_db);
+    let (cos_w0, alpha) = intermediates(sample_rate, freq, width, gain_db);
+    let two_sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+    section(
+        a * ((a + 1.0) - (a - 1.0) * cos_w0 + two_sqrt_a_alpha),
+        2.0 * a * ((a - 1.0) - (a + 1.0) * cos_w0),
+        a * ((a + 1.0) - (a - 1.0) * cos_w0 - two_sqrt_a_alpha),
+        (a + 1.0) + (a - 1.0) * cos_w0 + two_sqrt_a_alpha,
+        -2.0 * ((a - 1.0) + (a + 1.0) * cos_w0),
+        (a + 1.0) + (a - 1.0) * cos_w0 - two_sqrt_a_alpha,
+    )
+}
+
+/// High-shelf filter. The gain above `freq` is `gain_db`, and the gain below it is 0 dB.
+///
+/// The arguments and the panics are the same as [`peaking`], except that `freq` is the midpoint
+/// (half of the dB gain) frequency of the shelf.
+pub fn high_shelf(sample_rate: f32, freq: f32, width: Width, gain_db: f32) -> SecondOrderSection {
+    let a = amplitude(gain_db);
+    let (cos_w0, alpha) = intermediates(sample_rate, freq, width, gain_db);
+    let two_sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+    section(
+        a * ((a + 1.0) + (a - 1.0) * cos_w0 + two_sqrt_a_alpha),
+        -2.0 * a * ((a - 1.0) + (a + 1.0) * cos_w0),
+        a * ((a + 1.0) + (a - 1.0) * cos_w0 - two_sqrt_a_alpha),
+        (a + 1.0) - (a - 1.0) * cos_w0 + two_sqrt_a_alpha,
+        2.0 * ((a - 1.0) - (a + 1.0) * cos_w0),
+        (a + 1.0) - (a - 1.0) * cos_w0 - two_sqrt_a_alpha,
+    )
+}
+
+/// The amplitude `A` of the cookbook formulae for the peaking and shelving filters.
+fn amplitude(gain_db: f32) -> f64 {
+    10.0f64.powf(gain_db as f64 / 40.0)
+}
+
+/// Compute `cos(w0)` and `alpha` of the cookbook formulae.
+fn intermediates(sample_rate: f32, freq: f32, width: Width, gain_db: f32) -> (f64, f64) {
+    assert!(sample_rate > 0.0, "The sample rate must be positive");
+    assert!(
+        freq > 0.0 && freq < sample_rate / 2.0,
+        "The frequency must be in the range (0, sample_rate / 2)"
+    );
+
+    let w0 = 2.0 * PI * freq as f64 / sample_rate as f64;
+    let sin_w0 = w0.sin();
+    let alpha = match width {
+        Width::Q(q) => {
+            assert!(q > 0.0, "Q must be positive");
+            sin_w0 / (2.0 * q as f64)
+        }
+        Width::Bandwidth(bw) => {
+            assert!(bw > 0.0, "The bandwidth must be positive");
+            sin_w0 * (LN_2 / 2.0 * bw as f64 * w0 / sin_w0).sinh()
+        }
+        Width::Slope(slope) => {
+            assert!(slope > 0.0, "The shelf slope must be positive");
+            let a = amplitude(gain_db);
+            sin_w0 / 2.0 * ((a + 1.0 / a) * (1.0 / slope as f64 - 1.0) + 2.0).sqrt()
+        }
+    };
+    (w0.cos(), alpha)
+}
+
+fn section(b0: f64, b1: f64, b2: f64, a0: f64, a1: f64, a2: f64) -> SecondOrderSection {
+    SecondOrderSection {
+        b0: (b0 / a0) as f32,
+        b1: (b1 / a0) as f32,
+        b2: (b2 / a0) as f32,
+        a1: (a1 / a0) as f32,
+        a2: (a2 / a0) as f32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use crate::filter::design::response::FrequencyResponse;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    fn magnitude_db(section: &SecondOrderSection, freq: f32) -> f32 {
+        section.magnitude_db(&[freq], SAMPLE_RATE)[0]
+    }
+
+    #[test]
+    fn lowpass_response() {
+        let section = lowpass(SAMPLE_RATE, 1000.0, Width::Q(std::f32::consts::FRAC_1_SQRT_2));
+        assert_abs_diff_eq!(magnitude_db(&section, 1.0), 0.0, epsilon = 1e-3);
+        assert_abs_diff_eq!(magnitude_db(&section, 1000.0), -3.0103, epsilon = 1e-2);
+        assert!(magnitude_db(&section, 20000.0) < -50.0);
+    }
+
+    #[test]
+    fn highpass_response() {
+        let section = highpass(SAMPLE_RATE, 1000.0, Width::Q(std::f32::consts::FRAC_1_SQRT_2));
+        assert!(magnitude_db(&section, 10.0) < -70.0);
+        assert_abs_diff_eq!(magnitude_db(&section, 1000.0), -3.0103, epsilon = 1e-2);
+        assert_abs_diff_eq!(magnitude_db(&section, 23999.0), 0.0, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn bandpass_response() {
+        let section = bandpass(SAMPLE_RATE, 2000.0, Width::Bandwidth(1.0));
+        assert_abs_diff_eq!(magnitude_db(&section, 2000.0), 0.0, epsilon = 1e-3);
+        assert!(magnitude_db(&section, 100.0) < -20.0);
+        assert!(magnitude_db(&section, 20000.0) < -20.0);
+    }
+
+    #[test]
+    fn notch_response() {
+        let section = notch(SAMPLE_RATE, 2000.0, Width::Q(2.0));
+        assert!(magnitude_db(&section, 2000.0) < -60.0);
+        assert_abs_diff_eq!(magnitude_db(&section, 10.0), 0.0, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn allpass_response() {
+        let section = allpass(SAMPLE_RATE, 3000.0, Width::Q(0.7));
+        for freq in [10.0, 100.0, 1000.0, 3000.0, 10000.0, 20000.0] {
+            assert_abs_diff_eq!(magnitude_db(&section, freq), 0.0, epsilon = 1e-3);
+        }
+    }
+
+    #[test]
+    fn peaking_response() {
+        let section = peaking(SAMPLE_RATE, 1000.0, Width::Q(1.0), 6.0);
+        assert_abs_diff_eq!(magnitude_db(&section, 1000.0), 6.0, epsilon = 1e-3);
+        assert_abs_diff_eq!(magnitude_db(&section, 10.0), 0.0, epsilon = 1e-2);
+
+        let section = peaking(SAMPLE_RATE, 1000.0, Width::Bandwidth(2.0), -9.0);
+        assert_abs_diff_eq!(magnitude_db(&section, 1000.0), -9.0, epsilon = 1e-3);
+        // The bandwidth is defined between the midpoint gain frequencies
+        assert_abs_diff_eq!(magnitude_db(&section, 500.0), -4.5, epsilon = 0.1);
+        assert_abs_diff_eq!(magnitude_db(&section, 2000.0), -4.5, epsilon = 0.1);
+    }
+
+    #[test]
+    fn low_shelf_response() {
+        let section = low_shelf(SAMPLE_RATE, 500.0, Width::Slope(1.0), 6.0);
+        assert_abs_diff_eq!(magnitude_db(&section, 1.0), 6.0, epsilon = 1e-2);
+        assert_abs_diff_eq!(magnitude_db(&section, 500.0), 3.0, epsilon = 1e-2);
+        assert_abs_diff_eq!(magnitude_db(&section, 20000.0), 0.0, epsilon = 1e-2);
+    }
+
+    #[test]
+    fn high_shelf_response() {
+        let section = high_shelf(SAMPLE_RATE, 5000.0, Width::Slope(1.0), -6.0);
+        assert_abs_diff_eq!(magnitude_db(&section, 10.0), 0.0, epsilon = 1e-2);
+        assert_abs_diff_eq!(magnitude_db(&section, 5000.0), -3.0, epsilon = 1e-2);
+        assert_abs_diff_eq!(magnitude_db(&section, 23999.0), -6.0, epsilon = 1e-2);
+    }
+
+    #[test]
+    fn zero_gain_is_identity() {
+        let width = Width::Q(1.0);
+        for section in [
+            peaking(SAMPLE_RATE, 1000.0, width, 0.0),
+            low_shelf(SAMPLE_RATE, 1000.0, width, 0.0),
+            high_shelf(SAMPLE_RATE, 1000.0, width, 0.0),
+        ] {
+            for freq in [10.0, 1000.0, 20000.0] {
+                assert_abs_diff_eq!(magnitude_db(&section, freq), 0.0, epsilon = 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn frequency_above_nyquist() {
+        let _ = lowpass(SAMPLE_RATE, 30000.0, Width::Q(0.7));
+    }
+
+    #[test]
+    #[should_panic]
+    fn non_positive_q() {
+        let _ = lowpass(SAMPLE_RATE, 1000.0, Width::Q(0.0));
+    }
+}
@@ -6,7 +6,7 @@
 use log::warn;
 use std::cmp::min;
 
-use crate::filter::design::window::{hamming, hann};
+use crate::filter::design::window::Window;
 use crate::utilities::sinc;
 use super::FirCoeffs;
 
@@ -19,7 +19,7 @@ const MAX_SINC_HALF_WIDTH: usize = 32;
 /// Delay filter design using linear interpolation.
 ///
 /// Linear interpolation is the simplest way to implement fractional delay, with the cost of
-/// attenuation of high frequencies. Also, the group delay is not constant and is greater than the
+/// attenuation of high frequencies. Also, the group delay is not constant and deviates from the
 /// desired delay in the higher frequencies.
 ///
 /// # Arguments
@@ -52,8 +52,6 @@ pub fn linear_interpolation(delay: f32) -> FirCoeffs {
     FirCoeffs { b: coeffs }
 }
 
-// TODO Replace the window type with a enum
-
 /// Delay filter design using (windowed) sinc interpolation.
 ///
 /// This delay filter is composed of a normal integer delay (delta function) and a fractional
@@ -69,8 +67,8 @@ pub fn linear_interpolation(delay: f32) -> FirCoeffs {
 /// * `sinc_half_width` - The half width of the sinc filter in samples. If not provided, a proper
 ///   value is automatically chosen. Note that because the sinc filter also introduces a delay,
 ///   `sinc_half_width` must not be greater than `delay + 0.5`.
-/// * `window` - The window function to use for the sinc filter. For the available window
-///   functions, see [`window`](super::window). The default window is the Hamming window.
+/// * `window` - The window function to use for the sinc filter. The default window is the Hamming
+///   window.
 ///
 /// # Returns
 ///
@@ -80,7 +78,7 @@ pub fn linear_interpolation(delay: f32) -> FirCoeffs {
 ///
 /// * If `delay` is negative.
 /// * If `sinc_half_width` is not greater than 0.
-pub fn sinc_interpolation(delay: f32, sinc_half_width: Option<usize>, window: Option<&str>) -> FirCoeffs {
+pub fn sinc_interpolation(delay: f32, sinc_half_width: Option<usize>, window: Option<Window>) -> FirCoeffs {
     // Check the delay
     assert!(delay >= 0.0, "The delay must not be negative");
     if delay < EPSILON {
@@ -123,17 +121,12 @@ pub fn sinc_interpolation(delay: f32, sinc_half_width: Option<usize>, window: Op
 }
 
 /// Fractional delay filter that introduces a delay of `sinc_half_width + frac_delay` samples.
-fn sinc_fractional_delay(sinc_half_width: usize, frac_delay: f32, window: Option<&str>) -> Vec<f32> {
+fn sinc_fractional_delay(sinc_half_width: usize, frac_delay: f32, window: Option<Window>) -> Vec<f32> {
     assert!((-0.5..=0.5).contains(&frac_delay), "The fractional delay must be in the range [-0.5, 0.5]");
     let sinc_width = sinc_half_width * 2 + 1;
 
     // Determine the window function
-    let window: &str = window.unwrap_or("hamming");
-    let window_coeffs: Vec<f32> = match window {
-        "hamming" => hamming(sinc_width, true),
-        "hann" => hann(sinc_width, true),
-        _ => panic!("Invalid window function: {}", window),
-    };
+    let window_coeffs: Vec<f32> = window.unwrap_or_default().coefficients(sinc_width, true);
 
     // Construct the filter coefficients
     let mut coeffs: Vec<f32> = vec![0.0; sinc_width];
@@ -147,10 +140,14 @@ fn sinc_fractional_delay(sinc_half_width: usize, frac_delay: f32, window: Option
 #[cfg(test)]
 mod tests {
     use super::*;
+    use approx::assert_abs_diff_eq;
     use crate::{
         assert_all_close,
         assert_all_eq,
     };
+    use crate::filter::design::response::FrequencyResponse;
+
+    const SAMPLE_RATE: f32 = 48000.0;
 
     mod linear_interpolation {
         use super::*;
@@ -182,6 +179,21 @@ mod tests {
             let coeffs = linear_interpolation(delay);
             assert_all_close!(coeffs.b, [0.0, 0.0, 0.0, 0.7, 0.3]);
         }
+
+        #[test]
+        fn response() {
+            let coeffs = linear_interpolation(3.3);
+            // The exact delay at DC, but the high frequencies are attenuated and delayed differently
+            let magnitude = coeffs.magnitude_db(&[0.0, 12000.0, 24000.0], SAMPLE_RATE);
+            assert_abs_diff_eq!(magnitude[0], 0.0, epsilon = 1e-5);
+            assert!(magnitude[1] < -2.0);
+            assert_abs_diff_eq!(magnitude[2], 20.0 * 0.4f32.log10(), epsilon = 1e-3);
+
+            let delay = coeffs.group_delay(&[0.0, 12000.0, 24000.0], SAMPLE_RATE);
+            assert_abs_diff_eq!(delay[0], 3.3, epsilon = 1e-5);
+            assert!((delay[1] - 3.3).abs() > 0.1);
+            assert!((delay[2] - 3.3).abs() > 1.0);
+        }
     }
 
     mod sinc_interpolation {
@@ -227,7 +239,7 @@ mod tests {
         #[test]
         fn case_2() {
             let delay = 10.7;
-            let coeffs = sinc_interpolation(delay, None, Some("hamming"));
+            let coeffs = sinc_interpolation(delay, None, Some(Window::Hamming));
             let expected: Vec<f32> = vec![
                  0.00192537, -0.00261854,  0.00452946, -0.00798520,  0.01341051,
                 -0.02143884,  0.03317408, -0.05088355,  0.08023462, -0.14041957,
@@ -241,7 +253,7 @@ mod tests {
         #[test]
         fn case_3() {
             let delay = 20.7;
-            let coeffs = sinc_interpolation(delay, Some(11), Some("hamming"));
+            let coeffs = sinc_interpolation(delay, Some(11), Some(Window::Hamming));
             let expected: Vec<f32> = vec![
                  0.00000000,  0.00000000,  0.00000000,  0.00000000,  0.00000000,
                  0.00000000,  0.00000000,  0.00000000,  0.00000000,  0.00000000,
@@ -253,5 +265,18 @@ mod tests {
             ];
             assert_all_close!(coeffs.b, expected, 2e-6);
         }
+
+        #[test]
+        fn response() {
+            // Flat magnitude and nearly constant group delay up to 40% of the sample rate
+            let coeffs = sinc_interpolation(10.7, None, None);
+            let freqs: Vec<f32> = (0..=96).map(|i| i as f32 * 200.0).collect();
+            for magnitude in coeffs.magnitude_db(&freqs, SAMPLE_RATE) {
+                assert_abs_diff_eq!(magnitude, 0.0, epsilon = 0.05);
+            }
+            for delay in coeffs.group_delay(&freqs, SAMPLE_RATE) {
+                assert_abs_diff_eq!(delay, 10.7, epsilon = 0.05);
+            }
+        }
     }
 }
@@ -0,0 +1,537 @@
+//! Linear-phase FIR filter design.
+//!
+//! - [`firwin`] designs lowpass, highpass, bandpass and bandstop filters with the window method,
+//!   i.e. the ideal (sinc) impulse response truncated by a window function.
+//! - [`firwin2`] designs filters with arbitrary magnitude targets by frequency sampling: the
+//!   target is sampled on a dense grid, transformed to the time domain and then windowed.
+//! - [`firls`] designs filters with piecewise-linear magnitude targets that minimize the weighted
+//!   integral squared error over the given bands.
+//!
+//! The designs follow `scipy.signal.firwin`, `scipy.signal.firwin2` and `scipy.signal.firls`,
+//! and all of them produce symmetric (linear-phase) taps.
+
+use std::f64::consts::PI;
+
+use crate::filter::design::window::Window;
+use crate::utilities::sinc;
+use super::{Band, FirCoeffs};
+
+/// Windowed-sinc FIR filter design.
+///
+/// The filter is scaled so that the gain at the center of the first passband is exactly 1.0,
+/// i.e. at DC for the lowpass and bandstop filters, at the Nyquist frequency for the highpass
+/// filters, and at the center of the band for the bandpass filters.
+///
+/// # Arguments
+///
+/// * `num_taps` - The length of the filter.
+/// * `band` - The band type and the cutoff frequencies in Hz. The cutoff frequencies are where
+///   the gain is approximately -6 dB.
+/// * `window` - The window function. The default window is the Hamming window.
+/// * `sample_rate` - The sample rate in Hz.
+///
+/// # Returns
+///
+/// The FIR filter coefficients object.
+///
+/// # Panics
+///
+/// * If `num_taps` is zero.
+/// * If `num_taps` is even for a highpass or bandstop filter, because such a filter has a zero at
+///   the Nyquist frequency.
+/// * If any cutoff frequency is not in the range `(0, sample_rate / 2)`, or the lower edge is not
+///   below the upper edge.
+pub fn firwin(num_taps: usize, band: Band, window: Option<Window>, sample_rate: f32) -> FirCoeffs {
+    assert!(num_taps > 0, "The number of taps must be greater than 0");
+    assert!(sample_rate > 0.0, "The sample rate must be positive");
+
+    // Cutoff frequencies normalized to the Nyquist frequency
+    let nyquist = sample_rate / 2.0;
+    let normalize = |freq: f32| {
+        assert!(
+            freq > 0.0 && freq < nyquist,
+            "The cutoff frequencies must be in the range (0, sample_rate / 2)"
+        );
+        freq / nyquist
+    };
+    let edges = |low: f32, high: f32| {
+        assert!(low < high, "The lower edge must be below the upper edge");
+        (normalize(low), normalize(high))
+    };
+
+    // The passbands as (left, right) pairs, and the frequency to normalize the gain at
+    let (passbands, scale_freq): (Vec<(f32, f32)>, f32) = match band {
+        Band::Lowpass(cutoff) => (vec![(0.0, normalize(cutoff))], 0.0),
+        Band::Highpass(cutoff) => (vec![(normalize(cutoff), 1.0)], 1.0),
+        Band::Bandpass(low, high) => {
+            let (low, high) = edges(low, high);
+            (vec![(low, high)], (low + high) / 2.0)
+        }
+        Band::Bandstop(low, high) => {
+            let (low, high) = edges(low, high);
+            (vec![(0.0, low), (high, 1.0)], 0.0)
+        }
+    };
+    let passes_nyquist = passbands.iter().any(|&(_, right)| right == 1.0);
+    assert!(
+        !(passes_nyquist && num_taps.is_multiple_of(2)),
+        "The number of taps must be odd for a filter that passes the Nyquist frequency"
+    );
+
+    // Sum of the ideal bandpass responses of all the passbands, then windowed
+    let window_coeffs = window.unwrap_or_default().coefficients(num_taps, true);
+    let alpha = (num_taps - 1) as f32 / 2.0;
+    let mut coeffs: Vec<f32> = (0..num_taps)
+        .map(|n| {
+            let m = n as f32 - alpha;
+            let h: f32 = passbands
+                .iter()
+                .map(|&(left, right)| right * sinc(right * m) - left * sinc(left * m))
+                .sum();
+            h * window_coeffs[n]
+        })
+        .collect();
+
+    // Normalize the gain at the center of the first passband
+    let gain: f32 = coeffs
+        .iter()
+        .enumerate()
+        .map(|(n, &h)| h * (std::f32::consts::PI * (n as f32 - alpha) * scale_freq).cos())
+        .sum();
+    coeffs.iter_mut().for_each(|h| *h /= gain);
+
+    FirCoeffs { b: coeffs }
+}
+
+/// Frequency-sampling FIR filter design with an arbitrary magnitude target.
+///
+/// The target is linearly interpolated on a dense uniform grid, given a linear phase, and
+/// transformed to the time domain. The impulse response is then truncated and windowed. Steps in
+/// the target can be specified by repeating a frequency.
+///
+/// # Arguments
+///
+/// * `num_taps` - The length of the filter.
+/// * `freqs` - The frequency points of the target in Hz. They must be non-decreasing, start at 0
+///   and end at `sample_rate / 2`. A frequency can be repeated at most once for a step.
+/// * `gains` - The target gains (linear) at the frequency points.
+/// * `window` - The window function. The default window is the Hamming window.
+/// * `sample_rate` - The sample rate in Hz.
+///
+/// # Returns
+///
+/// The FIR filter coefficients object.
+///
+/// # Panics
+///
+/// * If `num_taps` is zero.
+/// * If `freqs` and `gains` have different lengths, or less than 2 points.
+/// * If `freqs` does not start at 0, does not end at `sample_rate / 2`, or is decreasing.
+/// * If a frequency appears more than twice in `freqs`.
+/// * If `num_taps` is even and the gain at the Nyquist frequency is not zero.
+pub fn firwin2(
+    num_taps: usize,
+    freqs: &[f32],
+    gains: &[f32],
+    window: Option<Window>,
+    sample_rate: f32,
+) -> FirCoeffs {
+    assert!(num_taps > 0, "The number of taps must be greater than 0");
+    assert!(sample_rate > 0.0, "The sample rate must be positive");
+    assert_eq!(freqs.len(), gains.len(), "The frequencies and the gains must have the same length");
+    assert!(freqs.len() >= 2, "At least 2 frequency points are required");
+
+    let nyquist = sample_rate / 2.0;
+    assert!(freqs[0] == 0.0, "The first frequency must be 0");
+    assert!(freqs[freqs.len() - 1] == nyquist, "The last frequency must be sample_rate / 2");
+    assert!(
+        freqs.windows(2).all(|w| w[0] <= w[1]),
+        "The frequencies must be non-decreasing"
+    );
+    assert!(
+        freqs.windows(3).all(|w| w[0] != w[2]),
+        "A frequency must not appear more than twice"
+    );
+    assert!(
+        num_taps % 2 == 1 || gains[gains.len() - 1] == 0.0,
+        "The gain at the Nyquist frequency must be 0 for an even number of taps"
+    );
+
+    // Sample the target on a uniform grid from DC to the Nyquist frequency
+    let num_points = num_taps.next_power_of_two() + 1;
+    let grid: Vec<f64> = (0..num_points)
+        .map(|k| {
+            let freq = k as f64 / (num_points - 1) as f64 * nyquist as f64;
+            interpolate(freqs, gains, freq)
+        })
+        .collect();
+
+    // Inverse real DFT of the linear-phase spectrum, evaluated only at the needed taps
+    let dft_len = 2 * (num_points - 1);
+    let alpha = (num_taps - 1) as f64 / 2.0;
+    let window_coeffs = window.unwrap_or_default().coefficients(num_taps, true);
+    let coeffs = (0..num_taps)
+        .map(|n| {
+            let h: f64 = grid
+                .iter()
+                .enumerate()
+                .map(|(k, &gain)| {
+                    // The DC and the Nyquist bins are not mirrored
+                    let multiplicity = if k == 0 || k == num_points - 1 { 1.0 } else { 2.0 };
+                    let phase = 2.0 * PI * k as f64 * (n as f64 - alpha) / dft_len as f64;
+                    multiplicity * gain * phase.cos()
+                })
+                .sum();
+            (h / dft_len as f64) as f32 * window_coeffs[n]
+        })
+        .collect();
+
+    FirCoeffs { b: coeffs }
+}
+
+/// Least-squares FIR filter design.
+///
+/// The filter minimizes the weighted integral of the squared error between its magnitude response
+/// and the piecewise-linear target over the given bands. The regions between the bands are
+/// "don't care" regions.
+///
+/// # Arguments
+///
+/// * `num_taps` - The length of the filter, which must be odd.
+/// * `bands` - The bands as `(start, end)` pairs in Hz, which must be within `[0, sample_rate / 2]`
+///   and must not overlap.
+/// * `desired` - The target gains (linear) at the start and the end of each band.
+/// * `weights` - The relative weight of each band. All the bands have the same weight if not
+///   provided.
+/// * `sample_rate` - The sample rate in Hz.
+///
+/// # Returns
+///
+/// The FIR filter coefficients object.
+///
+/// # Panics
+///
+/// * If `num_taps` is even.
+/// * If `bands`, `desired` and `weights` have different lengths, or `bands` is empty.
+/// * If the bands are out of range, reversed or overlapping.
+/// * If any weight is negative.
+pub fn firls(
+    num_taps: usize,
+    bands: &[(f32, f32)],
+    desired: &[(f32, f32)],
+    weights: Option<&[f32]>,
+    sample_rate: f32,
+) -> FirCoeffs {
+    assert!(num_taps % 2 == 1, "The number of taps must be odd");
+    assert!(sample_rate > 0.0, "The sample rate must be positive");
+    assert!(!bands.is_empty(), "At least one band is required");
+    assert_eq!(bands.len(), desired.len(), "Each band must have its desired gains");
+    let default_weights = vec![1.0; bands.len()];
+    let weights = weights.unwrap_or(&default_weights);
+    assert_eq!(bands.len(), weights.len(), "Each band must have its weight");
+    assert!(weights.iter().all(|&w| w >= 0.0), "The weights must not be negative");
+
+    // Band edges normalized to the Nyquist frequency
+    let nyquist = sample_rate / 2.0;
+    let bands: Vec<(f64, f64)> = bands
+        .iter()
+        .map(|&(start, end)| {
+            assert!(
+                0.0 <= start && start < end && end <= nyquist,
+                "The bands must be within [0, sample_rate / 2] and have positive widths"
+            );
+            (start as f64 / nyquist as f64, end as f64 / nyquist as f64)
+        })
+        .collect();
+    assert!(
+        bands.windows(2).all(|w| w[0].1 <= w[1].0),
+        "The bands must be increasing and must not overlap"
+    );
+
+    let sinc64 = |x: f64| if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+    let half_order = (num_taps - 1) / 2;
+
+    // The entries of the (Toeplitz plus Hankel) matrix of the normal equations
+    let q: Vec<f64> = (0..num_taps)
+        .map(|n| {
+            bands
+                .iter()
+                .zip(weights.iter())
+                .map(|(&(f0, f1), &w)| {
+                    let n = n as f64;
+                    w as f64 * (f1 * sinc64(f1 * n) - f0 * sinc64(f0 * n))
+                })
+                .sum()
+        })
+        .collect();
+    let matrix: Vec<Vec<f64>> = (0..=half_order)
+        .map(|i| (0..=half_order).map(|j| q[i.abs_diff(j)] + q[i + j]).collect())
+        .collect();
+
+    // The right-hand side, the integral of the target times cos(pi f n) over each band
+    let rhs: Vec<f64> = (0..=half_order)
+        .map(|n| {
+            bands
+                .iter()
+                .zip(desired.iter())
+                .zip(weights.iter())
+                .map(|((&(f0, f1), &(d0, d1)), &w)| {
+                    let slope = (d1 as f64 - d0 as f64) / (f1 - f0);
+                    let intercept = d0 as f64 - f0 * slope;
+                    let antiderivative = |f: f64| {
+                        let n = n as f64;
+                        let mut value = f * (slope * f + intercept) * sinc64(f * n);
+                        if n == 0.0 {
+                            value -= slope * f * f / 2.0;
+                        } else {
+                            value += slope * (PI * n * f).cos() / (PI * n).powi(2);
+                        }
+                        value
+                    };
+                    w as f64 * (antiderivative(f1) - antiderivative(f0))
+                })
+                .sum()
+        })
+        .collect();
+
+    let a = solve_linear_system(matrix, rhs);
+
+    // Assemble the symmetric impulse response
+    let coeffs: Vec<f32> = (0..num_taps)
+        .map(|n| {
+            let k = n.abs_diff(half_order);
+            (if k == 0 { 2.0 * a[0] } else { a[k] }) as f32
+        })
+        .collect();
+
+    FirCoeffs { b: coeffs }
+}
+
+/// Linear interpolation of the target gain. At a repeated frequency, the gain after the step is
+/// used.
+fn interpolate(freqs: &[f32], gains: &[f32], freq: f64) -> f64 {
+    let index = freqs.iter().rposition(|&f| f as f64 <= freq).unwrap_or(0);
+    if index + 1 >= freqs.len() {
+        return gains[freqs.len() - 1] as f64;
+    }
+    let (f0, f1) = (freqs[index] as f64, freqs[index + 1] as f64);
+    let (g0, g1) = (gains[index] as f64, gains[index + 1] as f64);
+    g0 + (g1 - g0) * (freq - f0) / (f1 - f0)
+}
+
+/// Solve the linear system `A x = b` with Gaussian elimination and partial pivoting.
+pub(crate) fn solve_linear_system(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Vec<f64> {
+    let n = b.len();
+    for col in 0..n {
+        // Partial pivoting
+        let pivot = (col..n)
+            .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+            .unwrap();
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        for row in col + 1..n {
+            let factor = a[row][col] / a[col][col];
+            if factor == 0.0 {
+                continue;
+            }
+            let (upper, lower) = a.split_at_mut(row);
+            for (x, &y) in lower[0][col..].iter_mut().zip(upper[col][col..].iter()) {
+                *x -= factor * y;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+
+    // Back substitution
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n).map(|col| a[row][col] * x[col]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use crate::filter::design::response::FrequencyResponse;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    fn magnitude_db(coeffs: &FirCoeffs, freq: f32) -> f32 {
+        coeffs.magnitude_db(&[freq], SAMPLE_RATE)[0]
+    }
+
+    fn assert_symmetric(coeffs: &FirCoeffs) {
+        let b = coeffs.b();
+        for n in 0..b.len() / 2 {
+            assert_abs_diff_eq!(b[n], b[b.len() - 1 - n], epsilon = 1e-6);
+        }
+    }
+
+    mod firwin {
+        use super::*;
+
+        #[test]
+        fn lowpass() {
+            let coeffs = firwin(101, Band::Lowpass(4000.0), None, SAMPLE_RATE);
+            assert_eq!(coeffs.b().len(), 101);
+            assert_symmetric(&coeffs);
+            assert_abs_diff_eq!(magnitude_db(&coeffs, 0.0), 0.0, epsilon = 1e-4);
+            assert_abs_diff_eq!(magnitude_db(&coeffs, 4000.0), -6.02, epsilon = 0.1);
+            // The Hamming window gives about 53 dB of stopband attenuation
+            for freq in (0..50).map(|i| 6000.0 + i as f32 * 350.0) {
+                assert!(magnitude_db(&coeffs, freq) < -50.0);
+            }
+        }
+
+        #[test]
+        fn highpass() {
+            let coeffs = firwin(51, Band::Highpass(10000.0), Some(Window::Hann), SAMPLE_RATE);
+            assert_symmetric(&coeffs);
+            assert_abs_diff_eq!(magnitude_db(&coeffs, 24000.0), 0.0, epsilon = 1e-4);
+            assert!(magnitude_db(&coeffs, 1000.0) < -40.0);
+        }
+
+        #[test]
+        fn bandpass() {
+            let coeffs = firwin(80, Band::Bandpass(2000.0, 6000.0), None, SAMPLE_RATE);
+            assert_symmetric(&coeffs);
+            assert_abs_diff_eq!(magnitude_db(&coeffs, 4000.0), 0.0, epsilon = 1e-4);
+            assert!(magnitude_db(&coeffs, 100.0) < -40.0);
+            assert!(magnitude_db(&coeffs, 15000.0) < -40.0);
+        }
+
+        #[test]
+        fn bandstop() {
+            let coeffs = firwin(201, Band::Bandstop(5000.0, 10000.0), None, SAMPLE_RATE);
+            assert_symmetric(&coeffs);
+            assert_abs_diff_eq!(magnitude_db(&coeffs, 0.0), 0.0, epsilon = 1e-4);
+            assert_abs_diff_eq!(magnitude_db(&coeffs, 20000.0), 0.0, epsilon = 0.05);
+            assert!(magnitude_db(&coeffs, 7500.0) < -50.0);
+        }
+
+        #[test]
+        #[should_panic]
+        fn even_highpass() {
+            let _ = firwin(50, Band::Highpass(10000.0), None, SAMPLE_RATE);
+        }
+
+        #[test]
+        fn kaiser_window() {
+            // The Kaiser window reaches the attenuation it is designed for
+            let beta = crate::filter::design::window::kaiser_beta(80.0);
+            let window = Window::Kaiser { beta };
+            let coeffs = firwin(201, Band::Lowpass(4000.0), Some(window), SAMPLE_RATE);
+            for freq in (0..50).map(|i| 6000.0 + i as f32 * 350.0) {
+                assert!(magnitude_db(&coeffs, freq) < -79.0);
+            }
+        }
+    }
+
+    mod firwin2 {
+        use super::*;
+
+        #[test]
+        fn lowpass_step() {
+            let coeffs = firwin2(
+                151,
+                &[0.0, 6000.0, 6000.0, 24000.0],
+                &[1.0, 1.0, 0.0, 0.0],
+                None,
+                SAMPLE_RATE,
+            );
+            assert_symmetric(&coeffs);
+            assert_abs_diff_eq!(magnitude_db(&coeffs, 0.0), 0.0, epsilon = 0.01);
+            assert!(magnitude_db(&coeffs, 12000.0) < -50.0);
+        }
+
+        #[test]
+        fn ramp() {
+            // Gain linearly increasing from 0 at DC to 1 at the Nyquist frequency
+            let coeffs = firwin2(101, &[0.0, 24000.0], &[0.0, 1.0], None, SAMPLE_RATE);
+            assert_symmetric(&coeffs);
+            for freq in [6000.0, 12000.0, 18000.0] {
+                let expected = 20.0 * (freq / 24000.0f32).log10();
+                assert_abs_diff_eq!(magnitude_db(&coeffs, freq), expected, epsilon = 0.05);
+            }
+        }
+
+        #[test]
+        #[should_panic]
+        fn even_taps_nonzero_nyquist() {
+            let _ = firwin2(100, &[0.0, 24000.0], &[1.0, 1.0], None, SAMPLE_RATE);
+        }
+
+        #[test]
+        #[should_panic(expected = "The frequencies must be non-decreasing")]
+        fn decreasing_freqs() {
+            let freqs = [0.0, 8000.0, 6000.0, 24000.0];
+            let _ = firwin2(101, &freqs, &[1.0, 1.0, 0.0, 0.0], None, SAMPLE_RATE);
+        }
+
+        #[test]
+        #[should_panic(expected = "A frequency must not appear more than twice")]
+        fn tripled_freq() {
+            let freqs = [0.0, 6000.0, 6000.0, 6000.0, 24000.0];
+            let _ = firwin2(101, &freqs, &[1.0, 1.0, 0.5, 0.0, 0.0], None, SAMPLE_RATE);
+        }
+    }
+
+    mod firls {
+        use super::*;
+
+        #[test]
+        fn allpass() {
+            // A flat target over the whole band is a (delayed) delta function
+            let coeffs = firls(5, &[(0.0, 24000.0)], &[(1.0, 1.0)], None, SAMPLE_RATE);
+            crate::assert_all_close!(coeffs.b(), [0.0, 0.0, 1.0, 0.0, 0.0], 1e-5);
+        }
+
+        #[test]
+        fn lowpass() {
+            let coeffs = firls(
+                101,
+                &[(0.0, 4000.0), (6000.0, 24000.0)],
+                &[(1.0, 1.0), (0.0, 0.0)],
+                Some(&[1.0, 10.0]),
+                SAMPLE_RATE,
+            );
+            assert_symmetric(&coeffs);
+            for freq in (0..40).map(|i| i as f32 * 100.0) {
+                assert_abs_diff_eq!(magnitude_db(&coeffs, freq), 0.0, epsilon = 0.1);
+            }
+            for freq in (0..60).map(|i| 6000.0 + i as f32 * 300.0) {
+                assert!(magnitude_db(&coeffs, freq) < -50.0);
+            }
+        }
+
+        #[test]
+        #[should_panic]
+        fn even_taps() {
+            let _ = firls(100, &[(0.0, 24000.0)], &[(1.0, 1.0)], None, SAMPLE_RATE);
+        }
+
+        #[test]
+        #[should_panic]
+        fn overlapping_bands() {
+            let _ = firls(
+                11,
+                &[(0.0, 6000.0), (5000.0, 24000.0)],
+                &[(1.0, 1.0), (0.0, 0.0)],
+                None,
+                SAMPLE_RATE,
+            );
+        }
+    }
+
+    #[test]
+    fn linear_system() {
+        let a = vec![vec![2.0, 1.0, -1.0], vec![-3.0, -1.0, 2.0], vec![-2.0, 1.0, 2.0]];
+        let b = vec![8.0, -11.0, -3.0];
+        let x = solve_linear_system(a, b);
+        crate::assert_all_close!(x, [2.0, 3.0, -1.0], 1e-12);
+    }
+}
@@ -0,0 +1,852 @@
+//! Classic IIR filter design from analog prototypes.
+//!
+//! The designs follow the same procedure as `scipy.signal.iirfilter`: a normalized analog lowpass
+//! prototype (Butterworth, Chebyshev type I/II, elliptic or Bessel) is transformed into the
+//! desired band type, and then discretized with the bilinear transform. The band edges are
+//! prewarped, so the digital filter has its edges exactly at the given frequencies.
+//!
+//! The result is returned as [`SosCoeffs`], because the direct form is numerically unreliable for
+//! the orders these designs are usually used with. All the intermediate computations are done in
+//! double precision.
+//!
+//! Note that, like SciPy, the order of the bandpass and bandstop filters is twice the order of the
+//! prototype.
+
+use std::f64::consts::PI;
+
+use crate::complex::Complex;
+use super::{Band, SecondOrderSection, SosCoeffs};
+
+type C64 = Complex<f64>;
+
+/// The maximum order of the Bessel prototype. The roots of higher-order Bessel polynomials cannot
+/// be found reliably in double precision.
+const MAX_BESSEL_ORDER: usize = 25;
+/// The maximum number of iterations of the polynomial root finder.
+const MAX_ROOT_ITERATIONS: usize = 500;
+/// The relative tolerance for treating a pole or a zero as real.
+const REAL_TOLERANCE: f64 = 1e-10;
+
+/// The analog lowpass prototype of the design.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Prototype {
+    /// Maximally flat passband.
+    Butterworth,
+    /// Equiripple passband with the given peak-to-peak ripple in dB.
+    ChebyshevI { ripple_db: f32 },
+    /// Equiripple stopband with the given minimum attenuation in dB.
+    ChebyshevII { attenuation_db: f32 },
+    /// Equiripple passband and stopband.
+    Elliptic { ripple_db: f32, attenuation_db: f32 },
+    /// Maximally flat group delay. The prototype is normalized like SciPy's `norm='phase'`, so the
+    /// high-frequency asymptote is the same as the Butterworth filter of the same order.
+    Bessel,
+}
+
+/// Design an IIR filter from an analog prototype.
+///
+/// # Arguments
+///
+/// * `order` - The order of the prototype. The order of the bandpass and bandstop filters is
+///   twice this value.
+/// * `band` - The band type and the critical frequencies. For the Butterworth, Chebyshev type I
+///   and elliptic filters, the critical frequencies are the passband edges (-3 dB for Butterworth,
+///   `-ripple_db` for the others). For the Chebyshev type II filters, they are the stopband
+///   edges. For the Bessel filters, they are where the phase is half of the maximum phase shift.
+/// * `prototype` - The analog lowpass prototype.
+/// * `sample_rate` - The sample rate in Hz.
+///
+/// # Returns
+///
+/// The second-order sections of the filter. The sections with the poles closest to the unit
+/// circle come last.
+///
+/// # Panics
+///
+/// * If `order` is zero, or greater than 25 for the Bessel prototype.
+/// * If any critical frequency is not in the range `(0, sample_rate / 2)`, or the lower edge is
+///   not below the upper edge.
+/// * If a ripple or an attenuation is not positive.
+pub fn iirfilter(order: usize, band: Band, prototype: Prototype, sample_rate: f32) -> SosCoeffs {
+    assert!(order > 0, "The order must be greater than 0");
+    assert!(sample_rate > 0.0, "The sample rate must be positive");
+
+    let analog = match prototype {
+        Prototype::Butterworth => butterworth_prototype(order),
+        Prototype::ChebyshevI { ripple_db } => chebyshev1_prototype(order, ripple_db as f64),
+        Prototype::ChebyshevII { attenuation_db } => {
+            chebyshev2_prototype(order, attenuation_db as f64)
+        }
+        Prototype::Elliptic { ripple_db, attenuation_db } => {
+            elliptic_prototype(order, ripple_db as f64, attenuation_db as f64)
+        }
+        Prototype::Bessel => bessel_prototype(order),
+    };
+
+    // Prewarp the critical frequencies for the bilinear transform
+    let fs = sample_rate as f64;
+    let warp = |freq: f32| {
+        assert!(
+            freq > 0.0 && freq < sample_rate / 2.0,
+            "The critical frequencies must be in the range (0, sample_rate / 2)"
+        );
+        2.0 * fs * (PI * freq as f64 / fs).tan()
+    };
+    let band_edges = |low: f32, high: f32| {
+        assert!(low < high, "The lower edge must be below the upper edge");
+        let (low, high) = (warp(low), warp(high));
+        ((low * high).sqrt(), high - low)
+    };
+
+    let analog = match band {
+        Band::Lowpass(freq) => analog.lowpass_to_lowpass(warp(freq)),
+        Band::Highpass(freq) => analog.lowpass_to_highpass(warp(freq)),
+        Band::Bandpass(low, high) => {
+            let (center, bandwidth) = band_edges(low, high);
+            analog.lowpass_to_bandpass(center, bandwidth)
+        }
+        Band::Bandstop(low, high) => {
+            let (center, bandwidth) = band_edges(low, high);
+            analog.lowpass_to_bandstop(center, bandwidth)
+        }
+    };
+
+    analog.bilinear(fs).to_sos()
+}
+
+/// Butterworth filter design. See [`iirfilter`] for the details.
+pub fn butter(order: usize, band: Band, sample_rate: f32) -> SosCoeffs {
+    iirfilter(order, band, Prototype::Butterworth, sample_rate)
+}
+
+/// Chebyshev type I filter design with `ripple_db` of passband ripple. See [`iirfilter`] for the
+/// details.
+pub fn cheby1(order: usize, ripple_db: f32, band: Band, sample_rate: f32) -> SosCoeffs {
+    iirfilter(order, band, Prototype::ChebyshevI { ripple_db }, sample_rate)
+}
+
+/// Chebyshev type II filter design with `attenuation_db` of minimum stopband attenuation. See
+/// [`iirfilter`] for the details.
+pub fn cheby2(order: usize, attenuation_db: f32, band: Band, sample_rate: f32) -> SosCoeffs {
+    iirfilter(order, band, Prototype::ChebyshevII { attenuation_db }, sample_rate)
+}
+
+/// Elliptic (Cauer) filter design with `ripple_db` of passband ripple and `attenuation_db` of
+/// minimum stopband attenuation. See [`iirfilter`] for the details.
+pub fn ellip(
+    order: usize,
+    ripple_db: f32,
+    attenuation_db: f32,
+    band: Band,
+    sample_rate: f32,
+) -> SosCoeffs {
+    iirfilter(order, band, Prototype::Elliptic { ripple_db, attenuation_db }, sample_rate)
+}
+
+/// Bessel (Thomson) filter design. See [`iirfilter`] for the details.
+pub fn bessel(order: usize, band: Band, sample_rate: f32) -> SosCoeffs {
+    iirfilter(order, band, Prototype::Bessel, sample_rate)
+}
+
+/// A transfer function in the zero-pole-gain form, `k * prod(s - z) / prod(s - p)`.
+#[derive(Debug, Clone)]
+struct Zpk {
+    zeros: Vec<C64>,
+    poles: Vec<C64>,
+    gain: f64,
+}
+
+fn product(values: &[C64]) -> C64 {
+    values.iter().fold(C64::ONE, |acc, &x| acc * x)
+}
+
+/// `[-n + 1, -n + 3, ..., n - 3, n - 1]`, the indices used by the prototype formulae.
+fn symmetric_indices(n: usize) -> impl Iterator<Item = f64> {
+    (0..n).map(move |i| (2 * i) as f64 - n as f64 + 1.0)
+}
+
+fn butterworth_prototype(n: usize) -> Zpk {
+    let poles = symmetric_indices(n)
+        .map(|m| -C64::cis(PI * m / (2.0 * n as f64)))
+        .collect();
+    Zpk { zeros: vec![], poles, gain: 1.0 }
+}
+
+fn chebyshev1_prototype(n: usize, ripple_db: f64) -> Zpk {
+    assert!(ripple_db > 0.0, "The passband ripple must be positive");
+    let eps = (10.0f64.powf(0.1 * ripple_db) - 1.0).sqrt();
+    let mu = (1.0 / eps).asinh() / n as f64;
+
+    let poles: Vec<C64> = symmetric_indices(n)
+        .map(|m| -C64::new(mu, PI * m / (2.0 * n as f64)).sinh())
+        .collect();
+    let neg_poles: Vec<C64> = poles.iter().map(|&p| -p).collect();
+    // The DC gain is 1.0 for odd orders and -ripple_db for even orders
+    let dc_gain = if n % 2 == 1 { 1.0 } else { 1.0 / (1.0 + eps * eps).sqrt() };
+    let gain = dc_gain * product(&neg_poles).re;
+    Zpk { zeros: vec![], poles, gain }
+}
+
+fn chebyshev2_prototype(n: usize, attenuation_db: f64) -> Zpk {
+    assert!(attenuation_db > 0.0, "The stopband attenuation must be positive");
+    let de = 1.0 / (10.0f64.powf(0.1 * attenuation_db) - 1.0).sqrt();
+    let mu = (1.0 / de).asinh() / n as f64;
+
+    // For odd orders, the zero at infinity is skipped
+    let zeros: Vec<C64> = symmetric_indices(n)
+        .filter(|&m| m != 0.0)
+        .map(|m| C64::new(0.0, 1.0 / (m * PI / (2.0 * n as f64)).sin()))
+        .collect();
+    let poles: Vec<C64> = symmetric_indices(n)
+        .map(|m| {
+            let p = -C64::cis(PI * m / (2.0 * n as f64));
+            C64::new(mu.sinh() * p.re, mu.cosh() * p.im).inv()
+        })
+        .collect();
+
+    let neg_poles: Vec<C64> = poles.iter().map(|&p| -p).collect();
+    let neg_zeros: Vec<C64> = zeros.iter().map(|&z| -z).collect();
+    let gain = (product(&neg_poles) / product(&neg_zeros)).re;
+    Zpk { zeros, poles, gain }
+}
+
+fn elliptic_prototype(n: usize, ripple_db: f64, attenuation_db: f64) -> Zpk {
+    assert!(ripple_db > 0.0, "The passband ripple must be positive");
+    assert!(attenuation_db > 0.0, "The stopband attenuation must be positive");
+
+    // Follows S. J. Orfanidis, "Lecture Notes on Elliptic Filter Design"
+    let ep = (10.0f64.powf(0.1 * ripple_db) - 1.0).sqrt();
+    let es = (10.0f64.powf(0.1 * attenuation_db) - 1.0).sqrt();
+    let k1 = ep / es;
+    let k = elliptic_degree(n, k1);
+
+    let v0 = -C64::I * elliptic::asne(C64::I / ep, k1) / n as f64;
+
+    let mut zeros = Vec::with_capacity(n);
+    let mut poles = Vec::with_capacity(n);
+    for i in 1..=n / 2 {
+        let u = (2 * i - 1) as f64 / n as f64;
+        let zeta = elliptic::cde(C64::from(u), k);
+        let zero = C64::I / (zeta * k);
+        let pole = C64::I * elliptic::cde(C64::from(u) - C64::I * v0, k);
+        zeros.extend([zero, zero.conj()]);
+        poles.extend([pole, pole.conj()]);
+    }
+    if n % 2 == 1 {
+        let pole = C64::I * elliptic::sne(C64::I * v0, k);
+        poles.push(C64::from(pole.re));
+    }
+
+    // The DC gain is 1.0 for odd orders and -ripple_db for even orders
+    let dc_gain = if n % 2 == 1 { 1.0 } else { 1.0 / (1.0 + ep * ep).sqrt() };
+    let neg_poles: Vec<C64> = poles.iter().map(|&p| -p).collect();
+    let neg_zeros: Vec<C64> = zeros.iter().map(|&z| -z).collect();
+    let gain = dc_gain * (product(&neg_poles) / product(&neg_zeros)).re;
+    Zpk { zeros, poles, gain }
+}
+
+/// Solve the degree equation of the elliptic filter, i.e. find the selectivity modulus `k` given
+/// the order `n` and the discrimination modulus `k1`.
+fn elliptic_degree(n: usize, k1: f64) -> f64 {
+    let k1p = (1.0 - k1 * k1).sqrt();
+    let sne_product: f64 = (1..=n / 2)
+        .map(|i| elliptic::sne(C64::from((2 * i - 1) as f64 / n as f64), k1p).re)
+        .product();
+    let kp = k1p.powi(n as i32) * sne_product.powi(4);
+    (1.0 - kp * kp).sqrt()
+}
+
+fn bessel_prototype(n: usize) -> Zpk {
+    assert!(n <= MAX_BESSEL_ORDER, "The order of the Bessel filter must not exceed {}", MAX_BESSEL_ORDER);
+
+    // Coefficients of the reverse Bessel polynomial, a_k = (2n - k)! / (2^(n - k) k! (n - k)!)
+    let coeffs: Vec<f64> = (0..=n)
+        .map(|k| {
+            let numerator: f64 = (n - k + 1..=2 * n - k).map(|x| x as f64).product();
+            let denominator: f64 = (1..=k).map(|x| x as f64).product();
+            numerator / denominator / 2.0f64.powi((n - k) as i32)
+        })
+        .collect();
+
+    // Scale the polynomial so that the product of the roots is 1 (the "phase" normalization),
+    // which also improves the conditioning of the root finding.
+    let scale = coeffs[0].powf(1.0 / n as f64);
+    let scaled: Vec<f64> = coeffs
+        .iter()
+        .enumerate()
+        .map(|(k, &a)| a / scale.powi((n - k) as i32))
+        .collect();
+
+    Zpk { zeros: vec![], poles: polynomial_roots(&scaled), gain: 1.0 }
+}
+
+/// Find the roots of a monic polynomial with the Aberth-Ehrlich method. The coefficients are in
+/// ascending powers.
+fn polynomial_roots(coeffs: &[f64]) -> Vec<C64> {
+    let n = coeffs.len() - 1;
+    let radius = coeffs[0].abs().powf(1.0 / n as f64).max(f64::EPSILON);
+    let mut roots: Vec<C64> = (0..n)
+        .map(|i| C64::from_polar(radius, 2.0 * PI * i as f64 / n as f64 + 0.4))
+        .collect();
+
+    for _ in 0..MAX_ROOT_ITERATIONS {
+        let mut max_step: f64 = 0.0;
+        for i in 0..n {
+            let z = roots[i];
+            // Horner's method for the polynomial and its derivative
+            let (value, derivative) = coeffs.iter().rev().fold(
+                (C64::ZERO, C64::ZERO),
+                |(p, dp), &c| (p * z + c, dp * z + p),
+            );
+            let ratio = value / derivative;
+            let repulsion = roots
+                .iter()
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .fold(C64::ZERO, |acc, (_, &r)| acc + (z - r).inv());
+            let step = ratio / (C64::ONE - ratio * repulsion);
+            roots[i] = z - step;
+            max_step = max_step.max(step.norm() / roots[i].norm().max(f64::EPSILON));
+        }
+        if max_step < 1e-15 {
+            break;
+        }
+    }
+    roots
+}
+
+impl Zpk {
+    /// The number of poles in excess of the zeros.
+    fn relative_degree(&self) -> usize {
+        debug_assert!(self.poles.len() >= self.zeros.len());
+        self.poles.len() - self.zeros.len()
+    }
+
+    /// `prod(-z) / prod(-p)`, which is the ratio of the gains needed by some transforms.
+    fn zero_pole_ratio(&self) -> f64 {
+        let neg_zeros: Vec<C64> = self.zeros.iter().map(|&z| -z).collect();
+        let neg_poles: Vec<C64> = self.poles.iter().map(|&p| -p).collect();
+        (product(&neg_zeros) / product(&neg_poles)).re
+    }
+
+    fn lowpass_to_lowpass(self, cutoff: f64) -> Self {
+        let degree = self.relative_degree();
+        Self {
+            zeros: self.zeros.iter().map(|&z| z * cutoff).collect(),
+            poles: self.poles.iter().map(|&p| p * cutoff).collect(),
+            gain: self.gain * cutoff.powi(degree as i32),
+        }
+    }
+
+    fn lowpass_to_highpass(self, cutoff: f64) -> Self {
+        let degree = self.relative_degree();
+        let gain = self.gain * self.zero_pole_ratio();
+        let mut zeros: Vec<C64> = self.zeros.iter().map(|&z| z.inv() * cutoff).collect();
+        zeros.extend(std::iter::repeat_n(C64::ZERO, degree));
+        Self {
+            zeros,
+            poles: self.poles.iter().map(|&p| p.inv() * cutoff).collect(),
+            gain,
+        }
+    }
+
+    fn lowpass_to_bandpass(self, center: f64, bandwidth: f64) -> Self {
+        let degree = self.relative_degree();
+        let transform = |roots: &[C64]| -> Vec<C64> {
+            let scaled: Vec<C64> = roots.iter().map(|&r| r * (bandwidth / 2.0)).collect();
+            let offsets: Vec<C64> = scaled
+                .iter()
+                .map(|&r| (r * r - center * center).sqrt())
+                .collect();
+            scaled.iter().zip(offsets.iter()).map(|(&r, &d)| r + d)
+                .chain(scaled.iter().zip(offsets.iter()).map(|(&r, &d)| r - d))
+                .collect()
+        };
+        let mut zeros = transform(&self.zeros);
+        zeros.extend(std::iter::repeat_n(C64::ZERO, degree));
+        Self {
+            zeros,
+            poles: transform(&self.poles),
+            gain: self.gain * bandwidth.powi(degree as i32),
+        }
+    }
+
+    fn lowpass_to_bandstop(self, center: f64, bandwidth: f64) -> Self {
+        let degree = self.relative_degree();
+        let gain = self.gain * self.zero_pole_ratio();
+        let transform = |roots: &[C64]| -> Vec<C64> {
+            let inverted: Vec<C64> = roots.iter().map(|&r| r.inv() * (bandwidth / 2.0)).collect();
+            let offsets: Vec<C64> = inverted
+                .iter()
+                .map(|&r| (r * r - center * center).sqrt())
+                .collect();
+            inverted.iter().zip(offsets.iter()).map(|(&r, &d)| r + d)
+                .chain(inverted.iter().zip(offsets.iter()).map(|(&r, &d)| r - d))
+                .collect()
+        };
+        let mut zeros = transform(&self.zeros);
+        zeros.extend(std::iter::repeat_n(C64::new(0.0, center), degree));
+        zeros.extend(std::iter::repeat_n(C64::new(0.0, -center), degree));
+        Self {
+            zeros,
+            poles: transform(&self.poles),
+            gain,
+        }
+    }
+
+    /// Discretize the analog filter with the bilinear transform.
+    fn bilinear(self, sample_rate: f64) -> Self {
+        let degree = self.relative_degree();
+        let fs2 = 2.0 * sample_rate;
+        let map = |s: C64| (C64::from(fs2) + s) / (C64::from(fs2) - s);
+
+        let shifted_zeros: Vec<C64> = self.zeros.iter().map(|&z| C64::from(fs2) - z).collect();
+        let shifted_poles: Vec<C64> = self.poles.iter().map(|&p| C64::from(fs2) - p).collect();
+        let gain = self.gain * (product(&shifted_zeros) / product(&shifted_poles)).re;
+
+        // The zeros at infinity are mapped to the Nyquist frequency
+        let mut zeros: Vec<C64> = self.zeros.iter().map(|&z| map(z)).collect();
+        zeros.extend(std::iter::repeat_n(-C64::ONE, degree));
+        Self {
+            zeros,
+            poles: self.poles.iter().map(|&p| map(p)).collect(),
+            gain,
+        }
+    }
+
+    /// Convert a digital filter to second-order sections. Each pole pair is matched with the
+    /// nearest zeros, starting from the poles closest to the unit circle.
+    fn to_sos(&self) -> SosCoeffs {
+        let (pole_pairs, mut real_poles) = split_conjugates(&self.poles);
+        let (mut zero_pairs, mut real_zeros) = split_conjugates(&self.zeros);
+
+        // Group the poles into sections, sorted by the distance to the unit circle (descending)
+        real_poles.sort_by(|a, b| a.abs().total_cmp(&b.abs()));
+        let mut groups: Vec<Roots> = pole_pairs.into_iter().map(Roots::Pair).collect();
+        let (single, paired) = real_poles.split_at(real_poles.len() % 2);
+        groups.extend(single.iter().map(|&p| Roots::Single(p)));
+        groups.extend(paired.chunks(2).map(|c| Roots::TwoReal(c[0], c[1])));
+        groups.sort_by(|a, b| a.max_norm().total_cmp(&b.max_norm()));
+
+        // Match the zeros, starting from the poles closest to the unit circle
+        let mut sections: Vec<SecondOrderSection> = groups
+            .iter()
+            .rev()
+            .map(|poles| {
+                let zeros = match *poles {
+                    Roots::Single(p) => take_nearest_real(&mut real_zeros, p)
+                        .map_or(Roots::None, Roots::Single),
+                    _ => take_nearest_pair(&mut zero_pairs, &mut real_zeros, poles.reference()),
+                };
+                let (b1, b2) = zeros.polynomial();
+                let (a1, a2) = poles.polynomial();
+                SecondOrderSection {
+                    b0: 1.0,
+                    b1: b1 as f32,
+                    b2: b2 as f32,
+                    a1: a1 as f32,
+                    a2: a2 as f32,
+                }
+            })
+            .collect();
+        sections.reverse();
+
+        // Apply the overall gain to the first section
+        let gain = self.gain as f32;
+        if let Some(first) = sections.first_mut() {
+            first.b0 *= gain;
+            first.b1 *= gain;
+            first.b2 *= gain;
+        }
+        SosCoeffs { sections }
+    }
+}
+
+/// The roots of one section.
+#[derive(Debug, Clone, Copy)]
+enum Roots {
+    None,
+    Single(f64),
+    TwoReal(f64, f64),
+    /// A complex conjugate pair, represented by the root with a positive imaginary part.
+    Pair(C64),
+}
+
+impl Roots {
+    fn max_norm(&self) -> f64 {
+        match *self {
+            Roots::None => 0.0,
+            Roots::Single(r) => r.abs(),
+            Roots::TwoReal(r1, r2) => r1.abs().max(r2.abs()),
+            Roots::Pair(r) => r.norm(),
+        }
+    }
+
+    /// The root closest to the unit circle, used for matching.
+    fn reference(&self) -> C64 {
+        match *self {
+            Roots::None => C64::ZERO,
+            Roots::Single(r) => C64::from(r),
+            Roots::TwoReal(r1, r2) => C64::from(if r1.abs() > r2.abs() { r1 } else { r2 }),
+            Roots::Pair(r) => r,
+        }
+    }
+
+    /// The coefficients `(c1, c2)` of the monic polynomial `1 + c1 z^-1 + c2 z^-2`.
+    fn polynomial(&self) -> (f64, f64) {
+        match *self {
+            Roots::None => (0.0, 0.0),
+            Roots::Single(r) => (-r, 0.0),
+            Roots::TwoReal(r1, r2) => (-(r1 + r2), r1 * r2),
+            Roots::Pair(r) => (-2.0 * r.re, r.norm_sqr()),
+        }
+    }
+}
+
+/// Split the roots into complex conjugate pairs (represented by the roots with positive imaginary
+/// parts) and real roots.
+fn split_conjugates(roots: &[C64]) -> (Vec<C64>, Vec<f64>) {
+    let mut pairs = Vec::new();
+    let mut reals = Vec::new();
+    for &r in roots {
+        if r.im.abs() <= REAL_TOLERANCE * r.norm().max(1.0) {
+            reals.push(r.re);
+        } else if r.im > 0.0 {
+            pairs.push(r);
+        }
+    }
+    (pairs, reals)
+}
+
+fn take_nearest_real(reals: &mut Vec<f64>, target: f64) -> Option<f64> {
+    let index = reals
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| (*a - target).abs().total_cmp(&(*b - target).abs()))
+        .map(|(i, _)| i)?;
+    Some(reals.swap_remove(index))
+}
+
+/// Take the complex zero pair or the two real zeros nearest to the target pole.
+fn take_nearest_pair(pairs: &mut Vec<C64>, reals: &mut Vec<f64>, target: C64) -> Roots {
+    let nearest_pair = pairs
+        .iter()
+        .enumerate()
+        .map(|(i, &z)| (i, (z - target).norm()))
+        .min_by(|(_, a), (_, b)| a.total_cmp(b));
+    let nearest_real = reals
+        .iter()
+        .map(|&z| (C64::from(z) - target).norm())
+        .min_by(|a, b| a.total_cmp(b));
+
+    match (nearest_pair, nearest_real) {
+        (Some((i, pair_distance)), Some(real_distance))
+            if reals.len() < 2 || pair_distance <= real_distance =>
+        {
+            Roots::Pair(pairs.swap_remove(i))
+        }
+        (Some((i, _)), None) => Roots::Pair(pairs.swap_remove(i)),
+        (_, Some(_)) => {
+            let target = target.re;
+            let z1 = take_nearest_real(reals, target).unwrap();
+            match take_nearest_real(reals, target) {
+                Some(z2) => Roots::TwoReal(z1, z2),
+                None => Roots::Single(z1),
+            }
+        }
+        (None, None) => Roots::None,
+    }
+}
+
+/// Jacobi elliptic functions with normalized arguments, computed with the Landen transformations.
+/// The argument `u` is normalized by the complete elliptic integral `K(k)`, e.g.
+/// `cde(u, k) = cd(u * K(k), k)`.
+mod elliptic {
+    use super::{C64, PI};
+
+    /// The number of Landen iterations, which is enough for double precision.
+    const LANDEN_ITERATIONS: usize = 7;
+
+    /// The descending Landen sequence of the elliptic modulus `k`.
+    fn landen(k: f64) -> Vec<f64> {
+        let mut moduli = Vec::with_capacity(LANDEN_ITERATIONS);
+        let mut k = k;
+        for _ in 0..LANDEN_ITERATIONS {
+            let kp = (1.0 - k * k).sqrt();
+            k = (1.0 - kp) / (1.0 + kp);
+            moduli.push(k);
+        }
+        moduli
+    }
+
+    /// The complete elliptic integral of the first kind `K(k)` and its complement `K'(k)`.
+    fn ellipk(k: f64) -> (f64, f64) {
+        let integral = |k: f64| PI / 2.0 * landen(k).iter().map(|v| 1.0 + v).product::<f64>();
+        (integral(k), integral((1.0 - k * k).sqrt()))
+    }
+
+    /// Ascending Landen transformation from `w = cos(u * pi / 2)` or `sin(u * pi / 2)`.
+    fn ascend(mut w: C64, k: f64) -> C64 {
+        for &v in landen(k).iter().rev() {
+            w = w * (1.0 + v) / (C64::ONE + w * w * v);
+        }
+        w
+    }
+
+    /// `cd(u * K, k)`.
+    pub fn cde(u: C64, k: f64) -> C64 {
+        ascend((u * (PI / 2.0)).cos(), k)
+    }
+
+    /// `sn(u * K, k)`.
+    pub fn sne(u: C64, k: f64) -> C64 {
+        ascend((u * (PI / 2.0)).sin(), k)
+    }
+
+    /// The inverse of [`cde`].
+    pub fn acde(w: C64, k: f64) -> C64 {
+        let moduli = landen(k);
+        let mut w = w;
+        let mut previous = k;
+        for &v in moduli.iter() {
+            let root = (C64::ONE - w * w * (previous * previous)).sqrt();
+            w = w / (C64::ONE + root) * (2.0 / (1.0 + v));
+            previous = v;
+        }
+        let u = w.acos() * (2.0 / PI);
+
+        // Reduce to the fundamental period
+        let (kk, kkp) = ellipk(k);
+        let ratio = kkp / kk;
+        C64::new(symmetric_remainder(u.re, 4.0), symmetric_remainder(u.im, 2.0 * ratio))
+    }
+
+    /// The inverse of [`sne`].
+    pub fn asne(w: C64, k: f64) -> C64 {
+        C64::ONE - acde(w, k)
+    }
+
+    /// The remainder of `x / y` in the range `[-y / 2, y / 2]`.
+    fn symmetric_remainder(x: f64, y: f64) -> f64 {
+        let z = x % y;
+        if z.abs() > y / 2.0 {
+            z - y * z.signum()
+        } else {
+            z
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use crate::filter::design::response::FrequencyResponse;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    fn magnitude_db(sos: &SosCoeffs, freq: f32) -> f32 {
+        sos.magnitude_db(&[freq], SAMPLE_RATE)[0]
+    }
+
+    fn assert_stable(sos: &SosCoeffs) {
+        for section in sos.sections() {
+            // Stability triangle of a second-order section
+            assert!(section.a2.abs() < 1.0, "Unstable section: {:?}", section);
+            assert!(section.a1.abs() < 1.0 + section.a2, "Unstable section: {:?}", section);
+        }
+    }
+
+    mod butterworth {
+        use super::*;
+
+        #[test]
+        fn lowpass() {
+            let sos = butter(4, Band::Lowpass(1000.0), SAMPLE_RATE);
+            assert_eq!(sos.sections().len(), 2);
+            assert_stable(&sos);
+            assert_abs_diff_eq!(magnitude_db(&sos, 1.0), 0.0, epsilon = 1e-3);
+            assert_abs_diff_eq!(magnitude_db(&sos, 1000.0), -3.0103, epsilon = 1e-2);
+            // 24 dB/octave asymptotically
+            assert!(magnitude_db(&sos, 8000.0) < -70.0);
+        }
+
+        #[test]
+        fn odd_order_highpass() {
+            let sos = butter(5, Band::Highpass(200.0), SAMPLE_RATE);
+            assert_eq!(sos.sections().len(), 3);
+            assert_stable(&sos);
+            assert_abs_diff_eq!(magnitude_db(&sos, 200.0), -3.0103, epsilon = 1e-2);
+            assert_abs_diff_eq!(magnitude_db(&sos, 20000.0), 0.0, epsilon = 1e-3);
+            assert!(magnitude_db(&sos, 50.0) < -55.0);
+        }
+
+        #[test]
+        fn bandpass() {
+            let sos = butter(3, Band::Bandpass(500.0, 2000.0), SAMPLE_RATE);
+            assert_eq!(sos.sections().len(), 3);
+            assert_stable(&sos);
+            assert_abs_diff_eq!(magnitude_db(&sos, 1000.0), 0.0, epsilon = 1e-2);
+            assert_abs_diff_eq!(magnitude_db(&sos, 500.0), -3.0103, epsilon = 1e-2);
+            assert_abs_diff_eq!(magnitude_db(&sos, 2000.0), -3.0103, epsilon = 1e-2);
+            assert!(magnitude_db(&sos, 50.0) < -50.0);
+            assert!(magnitude_db(&sos, 20000.0) < -50.0);
+        }
+
+        #[test]
+        fn bandstop() {
+            let sos = butter(2, Band::Bandstop(900.0, 1100.0), SAMPLE_RATE);
+            assert_stable(&sos);
+            assert!(magnitude_db(&sos, 995.0) < -40.0);
+            assert_abs_diff_eq!(magnitude_db(&sos, 10.0), 0.0, epsilon = 1e-3);
+            assert_abs_diff_eq!(magnitude_db(&sos, 20000.0), 0.0, epsilon = 1e-3);
+        }
+
+        #[test]
+        fn high_order() {
+            let sos = butter(16, Band::Lowpass(100.0), SAMPLE_RATE);
+            assert_stable(&sos);
+            assert_abs_diff_eq!(magnitude_db(&sos, 1.0), 0.0, epsilon = 1e-2);
+            assert_abs_diff_eq!(magnitude_db(&sos, 100.0), -3.0103, epsilon = 5e-2);
+        }
+    }
+
+    mod chebyshev {
+        use super::*;
+
+        #[test]
+        fn type1_lowpass() {
+            let ripple = 1.0;
+            for order in [4, 5] {
+                let sos = cheby1(order, ripple, Band::Lowpass(2000.0), SAMPLE_RATE);
+                assert_stable(&sos);
+                // The passband stays within the ripple and ends at -ripple
+                for freq in (1..100).map(|i| i as f32 * 20.0) {
+                    let mag = magnitude_db(&sos, freq);
+                    assert!((-ripple - 1e-3..=1e-3).contains(&mag), "{} dB at {} Hz", mag, freq);
+                }
+                assert_abs_diff_eq!(magnitude_db(&sos, 2000.0), -ripple, epsilon = 1e-2);
+            }
+        }
+
+        #[test]
+        fn type2_lowpass() {
+            let attenuation = 40.0;
+            for order in [4, 5] {
+                let sos = cheby2(order, attenuation, Band::Lowpass(2000.0), SAMPLE_RATE);
+                assert_stable(&sos);
+                assert_abs_diff_eq!(magnitude_db(&sos, 1.0), 0.0, epsilon = 1e-3);
+                // The stopband starts at the critical frequency
+                for freq in (0..100).map(|i| 2000.0 + i as f32 * 200.0) {
+                    let mag = magnitude_db(&sos, freq);
+                    assert!(mag < -attenuation + 1e-2, "{} dB at {} Hz", mag, freq);
+                }
+            }
+        }
+    }
+
+    mod elliptic {
+        use super::*;
+
+        #[test]
+        fn degree_equation() {
+            // For the first order, the selectivity is the same as the discrimination
+            assert_abs_diff_eq!(elliptic_degree(1, 0.1), 0.1, epsilon = 1e-12);
+            // Higher orders give sharper transitions (larger k) for the same specification
+            let k4 = elliptic_degree(4, 0.01);
+            let k6 = elliptic_degree(6, 0.01);
+            assert!(0.0 < k4 && k4 < k6 && k6 < 1.0);
+        }
+
+        #[test]
+        fn lowpass() {
+            let (ripple, attenuation) = (0.5, 60.0);
+            for order in [3, 4, 5, 6] {
+                let sos = ellip(order, ripple, attenuation, Band::Lowpass(3000.0), SAMPLE_RATE);
+                assert_stable(&sos);
+                for freq in (1..100).map(|i| i as f32 * 30.0) {
+                    let mag = magnitude_db(&sos, freq);
+                    assert!((-ripple - 1e-2..=1e-2).contains(&mag), "{} dB at {} Hz", mag, freq);
+                }
+                assert_abs_diff_eq!(magnitude_db(&sos, 3000.0), -ripple, epsilon = 2e-2);
+
+                // The analog stopband edge is 1 / k times the passband edge
+                let ep = (10.0f64.powf(0.1 * ripple as f64) - 1.0).sqrt();
+                let es = (10.0f64.powf(0.1 * attenuation as f64) - 1.0).sqrt();
+                let k = elliptic_degree(order, ep / es);
+                let fs = SAMPLE_RATE as f64;
+                let stop = fs / PI * ((PI * 3000.0 / fs).tan() / k).atan();
+                for freq in (0..200).map(|i| stop as f32 + i as f32 * (23990.0 - stop as f32) / 200.0) {
+                    let mag = magnitude_db(&sos, freq);
+                    assert!(mag < -attenuation + 0.1, "{} dB at {} Hz", mag, freq);
+                }
+            }
+        }
+
+        #[test]
+        fn first_order() {
+            let sos = ellip(1, 1.0, 40.0, Band::Lowpass(1000.0), SAMPLE_RATE);
+            assert_eq!(sos.sections().len(), 1);
+            assert_abs_diff_eq!(magnitude_db(&sos, 1.0), 0.0, epsilon = 1e-3);
+            assert_abs_diff_eq!(magnitude_db(&sos, 1000.0), -1.0, epsilon = 1e-2);
+        }
+
+        #[test]
+        fn highpass() {
+            let sos = ellip(5, 0.1, 80.0, Band::Highpass(1000.0), SAMPLE_RATE);
+            assert_stable(&sos);
+            assert_abs_diff_eq!(magnitude_db(&sos, 1000.0), -0.1, epsilon = 1e-2);
+            assert_abs_diff_eq!(magnitude_db(&sos, 20000.0), 0.0, epsilon = 0.11);
+            assert!(magnitude_db(&sos, 100.0) < -79.9);
+        }
+    }
+
+    mod bessel {
+        use super::*;
+
+        #[test]
+        fn roots_of_second_order() {
+            // s^2 + 3s + 3 normalized: s^2 + sqrt(3)s + 1
+            let poles = bessel_prototype(2).poles;
+            for p in poles {
+                assert_abs_diff_eq!(p.re, -3.0f64.sqrt() / 2.0, epsilon = 1e-12);
+                assert_abs_diff_eq!(p.im.abs(), 0.5, epsilon = 1e-12);
+            }
+        }
+
+        #[test]
+        fn lowpass() {
+            for order in [1, 2, 5, 8, 12] {
+                let sos = bessel(order, Band::Lowpass(1000.0), SAMPLE_RATE);
+                assert_stable(&sos);
+                assert_abs_diff_eq!(magnitude_db(&sos, 1.0), 0.0, epsilon = 1e-3);
+                assert!(magnitude_db(&sos, 1000.0) < 0.0);
+                assert!(magnitude_db(&sos, 20000.0) < magnitude_db(&sos, 4000.0));
+            }
+        }
+
+        #[test]
+        #[should_panic]
+        fn too_high_order() {
+            let _ = bessel(MAX_BESSEL_ORDER + 1, Band::Lowpass(1000.0), SAMPLE_RATE);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_order() {
+        let _ = butter(0, Band::Lowpass(1000.0), SAMPLE_RATE);
+    }
+
+    #[test]
+    #[should_panic]
+    fn inverted_band_edges() {
+        let _ = butter(2, Band::Bandpass(2000.0, 1000.0), SAMPLE_RATE);
+    }
+
+    #[test]
+    #[should_panic]
+    fn frequency_above_nyquist() {
+        let _ = butter(2, Band::Lowpass(25000.0), SAMPLE_RATE);
+    }
+}
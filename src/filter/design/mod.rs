@@ -1,20 +1,39 @@
 //! Filter design for FIR and IIR filters.
 
+pub mod biquad;
 pub mod delay;
+pub mod fir;
+pub mod iir;
+pub mod remez;
+pub mod response;
 pub mod window;
 
+/// The band type and the critical (cutoff) frequencies of a frequency-selective design, in Hz.
+/// How the critical frequencies are interpreted depends on the design method.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Band {
+    Lowpass(f32),
+    Highpass(f32),
+    /// The lower and the upper edges.
+    Bandpass(f32, f32),
+    /// The lower and the upper edges.
+    Bandstop(f32, f32),
+}
+
 /// FIR filter coefficients.
 #[derive(Debug, Clone)]
 pub struct FirCoeffs {
     pub(crate) b: Vec<f32>,
 }
 
+/// IIR filter coefficients in direct form. The denominator is normalized so that `a[0]` is 1.0.
 #[derive(Debug, Clone)]
 pub struct IirCoeffs {
     pub(crate) b: Vec<f32>,
     pub(crate) a: Vec<f32>,
 }
 
+/// Coefficients of a single second-order section (biquad).
 #[derive(Debug, Clone)]
 pub struct SecondOrderSection {
     pub(crate) b0: f32,
@@ -24,6 +43,7 @@ pub struct SecondOrderSection {
     pub(crate) a2: f32,
 }
 
+/// IIR filter coefficients as a cascade of second-order sections.
 #[derive(Debug, Clone)]
 pub struct SosCoeffs {
     pub(crate) sections: Vec<SecondOrderSection>,
@@ -33,4 +53,87 @@ impl FirCoeffs {
     pub fn new(coeffs: Vec<f32>) -> Self {
         Self { b: coeffs }
     }
+
+    /// The filter taps.
+    pub fn b(&self) -> &[f32] {
+        &self.b
+    }
+}
+
+impl IirCoeffs {
+    /// Create the coefficients from the numerator `b` and the denominator `a`. Both of them are
+    /// divided by `a[0]`.
+    ///
+    /// # Panics
+    ///
+    /// * If `b` or `a` is empty.
+    /// * If `a[0]` is zero.
+    pub fn new(b: Vec<f32>, a: Vec<f32>) -> Self {
+        assert!(!b.is_empty(), "The numerator must not be empty");
+        assert!(!a.is_empty(), "The denominator must not be empty");
+        assert!(a[0] != 0.0, "The leading denominator coefficient must not be zero");
+
+        let a0 = a[0];
+        Self {
+            b: b.iter().map(|&x| x / a0).collect(),
+            a: a.iter().map(|&x| x / a0).collect(),
+        }
+    }
+
+    /// The numerator coefficients.
+    pub fn b(&self) -> &[f32] {
+        &self.b
+    }
+
+    /// The (normalized) denominator coefficients.
+    pub fn a(&self) -> &[f32] {
+        &self.a
+    }
+}
+
+impl SecondOrderSection {
+    /// Create a section from the transfer function
+    /// `(b0 + b1 z^-1 + b2 z^-2) / (a0 + a1 z^-1 + a2 z^-2)`. All the coefficients are divided by
+    /// `a0`.
+    ///
+    /// # Panics
+    ///
+    /// * If `a0` is zero.
+    pub fn new(b0: f32, b1: f32, b2: f32, a0: f32, a1: f32, a2: f32) -> Self {
+        assert!(a0 != 0.0, "a0 must not be zero");
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    /// The numerator coefficients `[b0, b1, b2]`.
+    pub fn b(&self) -> [f32; 3] {
+        [self.b0, self.b1, self.b2]
+    }
+
+    /// The normalized denominator coefficients `[1.0, a1, a2]`.
+    pub fn a(&self) -> [f32; 3] {
+        [1.0, self.a1, self.a2]
+    }
+}
+
+impl SosCoeffs {
+    pub fn new(sections: Vec<SecondOrderSection>) -> Self {
+        Self { sections }
+    }
+
+    /// The second-order sections of the cascade.
+    pub fn sections(&self) -> &[SecondOrderSection] {
+        &self.sections
+    }
+}
+
+impl From<SecondOrderSection> for SosCoeffs {
+    fn from(section: SecondOrderSection) -> Self {
+        Self { sections: vec![section] }
+    }
 }
@@ -0,0 +1,498 @@
+//! Equiripple FIR filter design with the Parks-McClellan (Remez exchange) algorithm.
+//!
+//! Compared with the window method, the Parks-McClellan algorithm finds the linear-phase filter
+//! that minimizes the maximum weighted error over the given bands, so the passband ripple and the
+//! stopband attenuation can be traded against each other with the band weights, and the filter is
+//! usually much shorter for the same specification.
+//!
+//! Only the symmetric (type I and type II) multiband filters are designed, like
+//! `scipy.signal.remez` with `type='bandpass'`.
+
+use std::f64::consts::PI;
+
+use log::warn;
+
+use super::FirCoeffs;
+
+/// The number of grid points per extremal frequency.
+const GRID_DENSITY: usize = 16;
+/// The maximum number of exchange iterations.
+const MAX_ITERATIONS: usize = 40;
+/// The relative spread of the extremal errors at which the exchange is considered converged.
+const CONVERGENCE_TOLERANCE: f64 = 1e-6;
+
+/// The result of the Parks-McClellan design.
+#[derive(Debug, Clone)]
+pub struct RemezDesign {
+    coeffs: FirCoeffs,
+    deviation: f32,
+    band_errors: Vec<f32>,
+    converged: bool,
+}
+
+impl RemezDesign {
+    /// The designed filter.
+    pub fn coeffs(&self) -> &FirCoeffs {
+        &self.coeffs
+    }
+
+    /// Take the designed filter.
+    pub fn into_coeffs(self) -> FirCoeffs {
+        self.coeffs
+    }
+
+    /// The achieved maximum weighted error. The maximum error in a band is this value divided by
+    /// the weight of the band.
+    pub fn deviation(&self) -> f32 {
+        self.deviation
+    }
+
+    /// The achieved maximum (unweighted) error in each band, e.g. the passband ripple and the
+    /// stopband leakage as linear amplitudes.
+    pub fn band_errors(&self) -> &[f32] {
+        &self.band_errors
+    }
+
+    /// The maximum error of each band in dB relative to its desired gain, i.e. the peak ripple for
+    /// the bands with non-zero desired gains and the (negative) attenuation for the others.
+    pub fn band_errors_db(&self, desired: &[f32]) -> Vec<f32> {
+        self.band_errors
+            .iter()
+            .zip(desired.iter())
+            .map(|(&error, &gain)| {
+                if gain == 0.0 {
+                    20.0 * error.log10()
+                } else {
+                    20.0 * ((gain.abs() + error) / gain.abs()).log10()
+                }
+            })
+            .collect()
+    }
+
+    /// Whether the exchange converged within the maximum number of iterations. The filter is still
+    /// usable if not, but it may not be optimal.
+    pub fn converged(&self) -> bool {
+        self.converged
+    }
+}
+
+/// Equiripple FIR filter design with the Parks-McClellan algorithm.
+///
+/// # Arguments
+///
+/// * `num_taps` - The length of the filter.
+/// * `bands` - The bands as `(start, end)` pairs in Hz, which must be within `[0, sample_rate / 2]`
+///   and must be separated by gaps. The gaps between the bands are transition bands.
+/// * `desired` - The desired gain (linear) of each band.
+/// * `weights` - The relative weight of each band. All the bands have the same weight if not
+///   provided.
+/// * `sample_rate` - The sample rate in Hz.
+///
+/// # Returns
+///
+/// The designed filter and the achieved errors.
+///
+/// # Panics
+///
+/// * If `num_taps` is less than 3.
+/// * If `bands`, `desired` and `weights` have different lengths, or `bands` is empty.
+/// * If the bands are out of range, reversed, overlapping or touching.
+/// * If any weight is not positive.
+/// * If `num_taps` is even and the desired gain at the Nyquist frequency is not zero.
+pub fn remez(
+    num_taps: usize,
+    bands: &[(f32, f32)],
+    desired: &[f32],
+    weights: Option<&[f32]>,
+    sample_rate: f32,
+) -> RemezDesign {
+    assert!(num_taps >= 3, "The number of taps must be at least 3");
+    assert!(sample_rate > 0.0, "The sample rate must be positive");
+    assert!(!bands.is_empty(), "At least one band is required");
+    assert_eq!(bands.len(), desired.len(), "Each band must have its desired gain");
+    let default_weights = vec![1.0; bands.len()];
+    let weights = weights.unwrap_or(&default_weights);
+    assert_eq!(bands.len(), weights.len(), "Each band must have its weight");
+    assert!(weights.iter().all(|&w| w > 0.0), "The weights must be positive");
+
+    // Band edges in cycles per sample
+    let nyquist = sample_rate / 2.0;
+    let bands: Vec<(f64, f64)> = bands
+        .iter()
+        .map(|&(start, end)| {
+            assert!(
+                0.0 <= start && start < end && end <= nyquist,
+                "The bands must be within [0, sample_rate / 2] and have positive widths"
+            );
+            (start as f64 / sample_rate as f64, end as f64 / sample_rate as f64)
+        })
+        .collect();
+    assert!(
+        bands.windows(2).all(|w| w[0].1 < w[1].0),
+        "The bands must be increasing and separated by transition bands"
+    );
+
+    let odd = num_taps % 2 == 1;
+    if !odd {
+        let last = bands.len() - 1;
+        assert!(
+            bands[last].1 < 0.5 || desired[last] == 0.0,
+            "The desired gain at the Nyquist frequency must be 0 for an even number of taps"
+        );
+    }
+
+    // The number of cosine basis functions
+    let num_basis = if odd { num_taps.div_ceil(2) } else { num_taps / 2 };
+    let grid = Grid::new(&bands, desired, weights, num_basis, odd);
+    assert!(
+        grid.len() > num_basis,
+        "The bands are too narrow for the number of taps"
+    );
+
+    // Initial extremal frequencies, spread uniformly over the grid
+    let mut extremals: Vec<usize> = (0..=num_basis)
+        .map(|i| i * (grid.len() - 1) / num_basis)
+        .collect();
+
+    let mut converged = false;
+    let mut solution = Solution::new(&grid, &extremals);
+    for _ in 0..MAX_ITERATIONS {
+        let errors: Vec<f64> = (0..grid.len())
+            .map(|i| grid.weight[i] * (grid.desired[i] - solution.evaluate(grid.x[i])))
+            .collect();
+
+        let Some(new_extremals) = find_extremals(&errors, num_basis + 1) else {
+            break;
+        };
+        let extremal_errors = new_extremals.iter().map(|&i| errors[i].abs());
+        let max_error = extremal_errors.clone().fold(0.0, f64::max);
+        let min_error = extremal_errors.fold(f64::INFINITY, f64::min);
+
+        extremals = new_extremals;
+        if (max_error - min_error) / max_error < CONVERGENCE_TOLERANCE {
+            converged = true;
+            break;
+        }
+        solution = Solution::new(&grid, &extremals);
+    }
+    if !converged {
+        warn!("The Remez exchange did not converge in {} iterations", MAX_ITERATIONS);
+    }
+
+    // Sample the amplitude response at the DFT frequencies and transform it back to the taps
+    let delay = (num_taps - 1) as f64 / 2.0;
+    let amplitudes: Vec<f64> = (0..num_taps)
+        .map(|k| {
+            let w = 2.0 * PI * k as f64 / num_taps as f64;
+            let scale = if odd { 1.0 } else { (w / 2.0).cos() };
+            scale * solution.evaluate(w.cos())
+        })
+        .collect();
+    let taps: Vec<f32> = (0..num_taps)
+        .map(|n| {
+            let sum: f64 = amplitudes
+                .iter()
+                .enumerate()
+                .map(|(k, &a)| a * (2.0 * PI * k as f64 * (n as f64 - delay) / num_taps as f64).cos())
+                .sum();
+            (sum / num_taps as f64) as f32
+        })
+        .collect();
+
+    // Measure the achieved errors with the final taps
+    let mut band_errors = vec![0.0f64; bands.len()];
+    for i in 0..grid.len() {
+        let w = 2.0 * PI * grid.freq[i];
+        let response: f64 = taps
+            .iter()
+            .enumerate()
+            .map(|(n, &h)| h as f64 * (w * (n as f64 - delay)).cos())
+            .sum();
+        let band = grid.band[i];
+        band_errors[band] = band_errors[band].max((desired[band] as f64 - response).abs());
+    }
+
+    RemezDesign {
+        coeffs: FirCoeffs { b: taps },
+        deviation: solution.deviation.abs() as f32,
+        band_errors: band_errors.iter().map(|&e| e as f32).collect(),
+        converged,
+    }
+}
+
+/// The dense frequency grid over the bands, with the desired response and the weight already
+/// transformed for the type II filters.
+struct Grid {
+    /// The frequency in cycles per sample.
+    freq: Vec<f64>,
+    /// `cos(2 pi f)`, where the interpolation is done.
+    x: Vec<f64>,
+    desired: Vec<f64>,
+    weight: Vec<f64>,
+    /// The index of the band.
+    band: Vec<usize>,
+}
+
+impl Grid {
+    fn new(bands: &[(f64, f64)], desired: &[f32], weights: &[f32], num_basis: usize, odd: bool) -> Self {
+        let spacing = 0.5 / (GRID_DENSITY * num_basis) as f64;
+        let mut grid = Self { freq: vec![], x: vec![], desired: vec![], weight: vec![], band: vec![] };
+
+        for (i, &(start, end)) in bands.iter().enumerate() {
+            let num_points = (((end - start) / spacing).round() as usize).max(1);
+            for k in 0..=num_points {
+                let f = start + (end - start) * k as f64 / num_points as f64;
+                // For the type II filters, the response is always zero at the Nyquist frequency
+                let scale = if odd { 1.0 } else { (PI * f).cos() };
+                if scale < 1e-6 {
+                    continue;
+                }
+                grid.freq.push(f);
+                grid.x.push((2.0 * PI * f).cos());
+                grid.desired.push(desired[i] as f64 / scale);
+                grid.weight.push(weights[i] as f64 * scale);
+                grid.band.push(i);
+            }
+        }
+        grid
+    }
+
+    fn len(&self) -> usize {
+        self.freq.len()
+    }
+}
+
+/// The best approximation on the current extremal set, represented in the barycentric form.
+struct Solution {
+    /// The signed weighted deviation on the extremal set.
+    deviation: f64,
+    /// The interpolation nodes (all the extremals except the last one).
+    nodes: Vec<f64>,
+    /// The interpolated values at the nodes.
+    values: Vec<f64>,
+    /// The barycentric weights of the nodes.
+    weights: Vec<f64>,
+}
+
+impl Solution {
+    fn new(grid: &Grid, extremals: &[usize]) -> Self {
+        let x: Vec<f64> = extremals.iter().map(|&i| grid.x[i]).collect();
+
+        // Deviation from the alternation condition
+        let gamma = barycentric_weights(&x);
+        let (numerator, denominator) = extremals.iter().enumerate().fold(
+            (0.0, 0.0),
+            |(num, den), (k, &i)| {
+                let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                (num + gamma[k] * grid.desired[i], den + sign * gamma[k] / grid.weight[i])
+            },
+        );
+        let deviation = numerator / denominator;
+
+        // The amplitude is interpolated through all the extremals except the last one
+        let count = extremals.len() - 1;
+        let nodes = x[..count].to_vec();
+        let values = extremals[..count]
+            .iter()
+            .enumerate()
+            .map(|(k, &i)| {
+                let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                grid.desired[i] - sign * deviation / grid.weight[i]
+            })
+            .collect();
+        let weights = barycentric_weights(&nodes);
+        Self { deviation, nodes, values, weights }
+    }
+
+    /// Evaluate the (transformed) amplitude response at `x = cos(w)`.
+    fn evaluate(&self, x: f64) -> f64 {
+        let mut numerator = 0.0;
+        let mut denominator = 0.0;
+        for ((&node, &value), &weight) in self.nodes.iter().zip(&self.values).zip(&self.weights) {
+            let diff = x - node;
+            if diff.abs() < 1e-14 {
+                return value;
+            }
+            numerator += weight / diff * value;
+            denominator += weight / diff;
+        }
+        numerator / denominator
+    }
+}
+
+/// The barycentric weights `1 / prod(x_k - x_i)`, scaled to avoid overflow. The scale cancels in
+/// all the uses.
+fn barycentric_weights(x: &[f64]) -> Vec<f64> {
+    let logs: Vec<(f64, f64)> = x
+        .iter()
+        .enumerate()
+        .map(|(k, &xk)| {
+            x.iter().enumerate().filter(|&(i, _)| i != k).fold((0.0, 1.0), |(log, sign), (_, &xi)| {
+                let diff = xk - xi;
+                (log - diff.abs().ln(), sign * diff.signum())
+            })
+        })
+        .collect();
+    let max_log = logs.iter().map(|&(log, _)| log).fold(f64::NEG_INFINITY, f64::max);
+    logs.iter().map(|&(log, sign)| sign * (log - max_log).exp()).collect()
+}
+
+/// Find the new extremal set: the alternating local extrema of the error with the largest
+/// magnitudes. Returns `None` if there are not enough extrema.
+fn find_extremals(errors: &[f64], count: usize) -> Option<Vec<usize>> {
+    let n = errors.len();
+
+    // All the local extrema, including the ends of the grid
+    let mut candidates: Vec<usize> = (0..n)
+        .filter(|&i| {
+            let e = errors[i];
+            let left = if i > 0 { errors[i - 1] } else { f64::NAN };
+            let right = if i + 1 < n { errors[i + 1] } else { f64::NAN };
+            let not_below = |other: f64| other.is_nan() || e >= other;
+            let not_above = |other: f64| other.is_nan() || e <= other;
+            (e > 0.0 && not_below(left) && not_below(right))
+                || (e < 0.0 && not_above(left) && not_above(right))
+        })
+        .collect();
+
+    // Enforce the alternation by keeping the largest of the consecutive same-sign extrema
+    let mut alternating: Vec<usize> = Vec::with_capacity(candidates.len());
+    for i in candidates.drain(..) {
+        match alternating.last() {
+            Some(&last) if errors[last].signum() == errors[i].signum() => {
+                if errors[i].abs() > errors[last].abs() {
+                    *alternating.last_mut().unwrap() = i;
+                }
+            }
+            _ => alternating.push(i),
+        }
+    }
+
+    // Remove the smaller of the two ends until the count is right, which keeps the alternation
+    while alternating.len() > count {
+        if errors[alternating[0]].abs() > errors[alternating[alternating.len() - 1]].abs() {
+            alternating.pop();
+        } else {
+            alternating.remove(0);
+        }
+    }
+
+    (alternating.len() == count).then_some(alternating)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use crate::filter::design::response::FrequencyResponse;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    fn assert_symmetric(coeffs: &FirCoeffs) {
+        let b = coeffs.b();
+        for n in 0..b.len() / 2 {
+            assert_abs_diff_eq!(b[n], b[b.len() - 1 - n], epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn lowpass_odd() {
+        let design = remez(
+            51,
+            &[(0.0, 4000.0), (6000.0, 24000.0)],
+            &[1.0, 0.0],
+            None,
+            SAMPLE_RATE,
+        );
+        assert!(design.converged());
+        assert_symmetric(design.coeffs());
+
+        // Equiripple: both bands reach the same (weighted) deviation
+        let errors = design.band_errors();
+        assert_abs_diff_eq!(errors[0], design.deviation(), epsilon = 1e-4);
+        assert_abs_diff_eq!(errors[1], design.deviation(), epsilon = 1e-4);
+        assert!(design.deviation() < 0.01);
+    }
+
+    #[test]
+    fn lowpass_weighted() {
+        let design = remez(
+            63,
+            &[(0.0, 4000.0), (6000.0, 24000.0)],
+            &[1.0, 0.0],
+            Some(&[1.0, 100.0]),
+            SAMPLE_RATE,
+        );
+        assert!(design.converged());
+        let errors = design.band_errors();
+        // The error in each band is the deviation divided by the weight of the band
+        assert_abs_diff_eq!(errors[0], design.deviation(), epsilon = 1e-4);
+        assert_abs_diff_eq!(errors[1], design.deviation() / 100.0, epsilon = 1e-5);
+
+        let errors_db = design.band_errors_db(&[1.0, 0.0]);
+        assert!(errors_db[0] < 1.0);
+        assert!(errors_db[1] < -60.0);
+        for freq in (0..60).map(|i| 6000.0 + i as f32 * 300.0) {
+            let mag = design.coeffs().magnitude_db(&[freq], SAMPLE_RATE)[0];
+            assert!(mag < errors_db[1] + 0.1, "{} dB at {} Hz", mag, freq);
+        }
+    }
+
+    #[test]
+    fn lowpass_even() {
+        let design = remez(
+            50,
+            &[(0.0, 4000.0), (6000.0, 24000.0)],
+            &[1.0, 0.0],
+            None,
+            SAMPLE_RATE,
+        );
+        assert!(design.converged());
+        assert_eq!(design.coeffs().b().len(), 50);
+        assert_symmetric(design.coeffs());
+        let errors = design.band_errors();
+        assert_abs_diff_eq!(errors[0], design.deviation(), epsilon = 1e-4);
+        assert!(errors[1] <= design.deviation() + 1e-4);
+    }
+
+    #[test]
+    fn bandpass() {
+        let design = remez(
+            81,
+            &[(0.0, 2000.0), (3000.0, 8000.0), (9000.0, 24000.0)],
+            &[0.0, 1.0, 0.0],
+            Some(&[10.0, 1.0, 10.0]),
+            SAMPLE_RATE,
+        );
+        assert!(design.converged());
+        assert_symmetric(design.coeffs());
+        assert_abs_diff_eq!(design.coeffs().magnitude_db(&[5500.0], SAMPLE_RATE)[0], 0.0, epsilon = 0.5);
+        let errors = design.band_errors();
+        assert_abs_diff_eq!(errors[0], design.deviation() / 10.0, epsilon = 1e-4);
+        assert_abs_diff_eq!(errors[2], design.deviation() / 10.0, epsilon = 1e-4);
+    }
+
+    #[test]
+    #[should_panic]
+    fn even_highpass() {
+        let _ = remez(50, &[(0.0, 4000.0), (6000.0, 24000.0)], &[0.0, 1.0], None, SAMPLE_RATE);
+    }
+
+    #[test]
+    #[should_panic]
+    fn non_positive_weight() {
+        let _ = remez(
+            51,
+            &[(0.0, 4000.0), (6000.0, 24000.0)],
+            &[1.0, 0.0],
+            Some(&[1.0, 0.0]),
+            SAMPLE_RATE,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The bands must be increasing and separated by transition bands")]
+    fn touching_bands() {
+        let _ = remez(51, &[(0.0, 4800.0), (4800.0, 24000.0)], &[1.0, 0.0], None, SAMPLE_RATE);
+    }
+}
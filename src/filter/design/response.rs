@@ -0,0 +1,254 @@
+//! Frequency response evaluation of filter coefficients, similar to `scipy.signal.freqz` and
+//! `scipy.signal.group_delay`.
+//!
+//! The [`FrequencyResponse`] trait is implemented for all the coefficient types, so any designed
+//! filter can be verified in the same way, e.g.
+//!
+//! ```
+//! use ruadio::filter::design::{Band, iir::butter, response::FrequencyResponse};
+//!
+//! let sos = butter(4, Band::Lowpass(1000.0), 48000.0);
+//! let magnitude = sos.magnitude_db(&[100.0, 1000.0, 10000.0], 48000.0);
+//! assert!((magnitude[1] + 3.01).abs() < 0.01);
+//! ```
+
+use std::f64::consts::PI;
+
+use crate::complex::Complex;
+use super::{FirCoeffs, IirCoeffs, SecondOrderSection, SosCoeffs};
+
+/// Frequency response evaluation of a linear time-invariant filter.
+///
+/// All the methods take the frequencies in Hz and the sample rate, and return one value per
+/// frequency. The response is evaluated in double precision.
+pub trait FrequencyResponse {
+    /// The complex frequency response `H(e^{jw})` at the given frequencies.
+    fn response(&self, freqs: &[f32], sample_rate: f32) -> Vec<Complex<f64>>;
+
+    /// The group delay in samples at the given frequencies.
+    ///
+    /// The group delay is not defined at the zeros of the transfer function on the unit circle,
+    /// where the result is not finite.
+    fn group_delay(&self, freqs: &[f32], sample_rate: f32) -> Vec<f32>;
+
+    /// The magnitude response in dB at the given frequencies.
+    fn magnitude_db(&self, freqs: &[f32], sample_rate: f32) -> Vec<f32> {
+        self.response(freqs, sample_rate)
+            .iter()
+            .map(|h| (20.0 * h.norm().log10()) as f32)
+            .collect()
+    }
+
+    /// The phase response in radians at the given frequencies. The phase is unwrapped along
+    /// `freqs`, so the frequencies should be sorted and dense enough for the unwrapping to be
+    /// meaningful.
+    fn phase(&self, freqs: &[f32], sample_rate: f32) -> Vec<f32> {
+        let phases: Vec<f64> = self.response(freqs, sample_rate).iter().map(|h| h.arg()).collect();
+        unwrap(&phases).into_iter().map(|p| p as f32).collect()
+    }
+}
+
+/// `num_points` evenly spaced frequencies from DC (inclusive) to the Nyquist frequency
+/// (exclusive), the default frequency grid of `scipy.signal.freqz`.
+pub fn linear_frequencies(num_points: usize, sample_rate: f32) -> Vec<f32> {
+    (0..num_points)
+        .map(|k| (k as f64 / num_points as f64 * sample_rate as f64 / 2.0) as f32)
+        .collect()
+}
+
+impl FrequencyResponse for FirCoeffs {
+    fn response(&self, freqs: &[f32], sample_rate: f32) -> Vec<Complex<f64>> {
+        map_angular(freqs, sample_rate, |w| polynomial(&self.b, w).0)
+    }
+
+    fn group_delay(&self, freqs: &[f32], sample_rate: f32) -> Vec<f32> {
+        map_angular(freqs, sample_rate, |w| polynomial_group_delay(&self.b, w) as f32)
+    }
+}
+
+impl FrequencyResponse for IirCoeffs {
+    fn response(&self, freqs: &[f32], sample_rate: f32) -> Vec<Complex<f64>> {
+        map_angular(freqs, sample_rate, |w| polynomial(&self.b, w).0 / polynomial(&self.a, w).0)
+    }
+
+    fn group_delay(&self, freqs: &[f32], sample_rate: f32) -> Vec<f32> {
+        map_angular(freqs, sample_rate, |w| {
+            (polynomial_group_delay(&self.b, w) - polynomial_group_delay(&self.a, w)) as f32
+        })
+    }
+}
+
+impl FrequencyResponse for SecondOrderSection {
+    fn response(&self, freqs: &[f32], sample_rate: f32) -> Vec<Complex<f64>> {
+        map_angular(freqs, sample_rate, |w| section_response(self, w))
+    }
+
+    fn group_delay(&self, freqs: &[f32], sample_rate: f32) -> Vec<f32> {
+        map_angular(freqs, sample_rate, |w| section_group_delay(self, w) as f32)
+    }
+}
+
+impl FrequencyResponse for SosCoeffs {
+    fn response(&self, freqs: &[f32], sample_rate: f32) -> Vec<Complex<f64>> {
+        map_angular(freqs, sample_rate, |w| {
+            self.sections
+                .iter()
+                .fold(Complex::<f64>::ONE, |h, section| h * section_response(section, w))
+        })
+    }
+
+    fn group_delay(&self, freqs: &[f32], sample_rate: f32) -> Vec<f32> {
+        // The group delay of a cascade is the sum of the group delays of its sections
+        map_angular(freqs, sample_rate, |w| {
+            self.sections
+                .iter()
+                .map(|section| section_group_delay(section, w))
+                .sum::<f64>() as f32
+        })
+    }
+}
+
+/// Evaluate `f` at the normalized angular frequency of each of the frequencies.
+fn map_angular<T>(freqs: &[f32], sample_rate: f32, f: impl Fn(f64) -> T) -> Vec<T> {
+    assert!(sample_rate > 0.0, "The sample rate must be positive");
+    freqs
+        .iter()
+        .map(|&freq| f(2.0 * PI * freq as f64 / sample_rate as f64))
+        .collect()
+}
+
+/// Evaluate the polynomial `sum c[n] z^-n` and its "ramped" version `sum n c[n] z^-n` at
+/// `z = e^{jw}`.
+fn polynomial(coeffs: &[f32], w: f64) -> (Complex<f64>, Complex<f64>) {
+    coeffs.iter().enumerate().fold(
+        (Complex::<f64>::ZERO, Complex::<f64>::ZERO),
+        |(value, ramped), (n, &c)| {
+            let term = Complex::<f64>::cis(-w * n as f64) * c as f64;
+            (value + term, ramped + term * n as f64)
+        },
+    )
+}
+
+/// The group delay of the polynomial `sum c[n] z^-n`, `Re(sum n c[n] z^-n / sum c[n] z^-n)`.
+fn polynomial_group_delay(coeffs: &[f32], w: f64) -> f64 {
+    let (value, ramped) = polynomial(coeffs, w);
+    (ramped / value).re
+}
+
+fn section_response(section: &SecondOrderSection, w: f64) -> Complex<f64> {
+    polynomial(&section.b(), w).0 / polynomial(&section.a(), w).0
+}
+
+fn section_group_delay(section: &SecondOrderSection, w: f64) -> f64 {
+    polynomial_group_delay(&section.b(), w) - polynomial_group_delay(&section.a(), w)
+}
+
+/// Unwrap the phase by removing the jumps larger than pi between consecutive values.
+fn unwrap(phases: &[f64]) -> Vec<f64> {
+    let mut offset = 0.0;
+    let mut unwrapped = Vec::with_capacity(phases.len());
+    for (i, &phase) in phases.iter().enumerate() {
+        if i > 0 {
+            let jump = phase - phases[i - 1];
+            if jump > PI {
+                offset -= 2.0 * PI * ((jump + PI) / (2.0 * PI)).floor();
+            } else if jump < -PI {
+                offset += 2.0 * PI * ((-jump + PI) / (2.0 * PI)).floor();
+            }
+        }
+        unwrapped.push(phase + offset);
+    }
+    unwrapped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use crate::assert_all_close;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    #[test]
+    fn fir_moving_average() {
+        // The two-point moving average has a zero at the Nyquist frequency
+        let coeffs = FirCoeffs::new(vec![0.5, 0.5]);
+        let h = coeffs.response(&[0.0, 12000.0], SAMPLE_RATE);
+        assert_abs_diff_eq!(h[0].re, 1.0, epsilon = 1e-12);
+        assert_abs_diff_eq!(h[1].norm(), std::f64::consts::FRAC_1_SQRT_2, epsilon = 1e-12);
+
+        let magnitude = coeffs.magnitude_db(&[12000.0], SAMPLE_RATE);
+        assert_abs_diff_eq!(magnitude[0], -3.0103, epsilon = 1e-4);
+        assert!(coeffs.magnitude_db(&[24000.0], SAMPLE_RATE)[0] < -100.0);
+
+        // Linear phase with a constant group delay of half a sample
+        let freqs = linear_frequencies(16, SAMPLE_RATE);
+        let delay = coeffs.group_delay(&freqs, SAMPLE_RATE);
+        assert_all_close!(delay, [0.5; 16], 1e-6);
+        let phase = coeffs.phase(&freqs, SAMPLE_RATE);
+        let expected: Vec<f32> = freqs
+            .iter()
+            .map(|&f| -std::f32::consts::PI * f / SAMPLE_RATE)
+            .collect();
+        assert_all_close!(phase, expected, 1e-6);
+    }
+
+    #[test]
+    fn iir_one_pole() {
+        // y[n] = x[n] + 0.5 y[n - 1]
+        let coeffs = IirCoeffs::new(vec![1.0], vec![1.0, -0.5]);
+        let h = coeffs.response(&[0.0, 24000.0], SAMPLE_RATE);
+        assert_abs_diff_eq!(h[0].re, 2.0, epsilon = 1e-12);
+        assert_abs_diff_eq!(h[1].re, 2.0 / 3.0, epsilon = 1e-12);
+
+        // The group delay at DC is a / (1 - a) and at the Nyquist frequency -a / (1 + a)
+        let delay = coeffs.group_delay(&[0.0, 24000.0], SAMPLE_RATE);
+        assert_all_close!(delay, [1.0, -1.0 / 3.0], 1e-6);
+    }
+
+    #[test]
+    fn sos_matches_direct_form() {
+        let sections = vec![
+            SecondOrderSection::new(1.0, 2.0, 1.0, 1.0, -0.5, 0.25),
+            SecondOrderSection::new(0.5, -0.3, 0.1, 1.0, 0.2, 0.6),
+        ];
+        let sos = SosCoeffs::new(sections);
+        // Multiply the numerators and the denominators of the sections
+        let convolve = |x: [f32; 3], y: [f32; 3]| {
+            let mut z = vec![0.0; 5];
+            for (i, &xi) in x.iter().enumerate() {
+                for (j, &yj) in y.iter().enumerate() {
+                    z[i + j] += xi * yj;
+                }
+            }
+            z
+        };
+        let [s0, s1] = [&sos.sections()[0], &sos.sections()[1]];
+        let direct = IirCoeffs::new(convolve(s0.b(), s1.b()), convolve(s0.a(), s1.a()));
+
+        let freqs = linear_frequencies(64, SAMPLE_RATE);
+        let (sos_magnitude, direct_magnitude) =
+            (sos.magnitude_db(&freqs, SAMPLE_RATE), direct.magnitude_db(&freqs, SAMPLE_RATE));
+        assert_all_close!(sos_magnitude, direct_magnitude, 1e-4);
+        let (sos_phase, direct_phase) = (sos.phase(&freqs, SAMPLE_RATE), direct.phase(&freqs, SAMPLE_RATE));
+        assert_all_close!(sos_phase, direct_phase, 1e-4);
+        let (sos_delay, direct_delay) =
+            (sos.group_delay(&freqs, SAMPLE_RATE), direct.group_delay(&freqs, SAMPLE_RATE));
+        assert_all_close!(sos_delay, direct_delay, 1e-3);
+    }
+
+    #[test]
+    fn phase_unwrapping() {
+        // A pure delay of 10 samples has the phase -10 w without any jump
+        let mut b = vec![0.0; 11];
+        b[10] = 1.0;
+        let coeffs = FirCoeffs::new(b);
+        let freqs = linear_frequencies(256, SAMPLE_RATE);
+        let expected: Vec<f32> = freqs
+            .iter()
+            .map(|&f| -10.0 * 2.0 * std::f32::consts::PI * f / SAMPLE_RATE)
+            .collect();
+        let phase = coeffs.phase(&freqs, SAMPLE_RATE);
+        assert_all_close!(phase, expected, 1e-4);
+    }
+}
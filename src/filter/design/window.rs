@@ -1,4 +1,92 @@
+//! Window functions for FIR filter design.
+//!
+//! The window functions are available both as plain functions returning the coefficients, and as
+//! the [`Window`] enum accepted by the designers, e.g.
+//! [`sinc_interpolation`](super::delay::sinc_interpolation) and [`firwin`](super::fir::firwin).
+
 use std::f32::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+/// A window function with its parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Window {
+    /// Rectangular (boxcar) window, i.e. no windowing.
+    Rectangular,
+    #[default]
+    Hamming,
+    Hann,
+    Blackman,
+    /// 4-term Blackman-Harris window.
+    BlackmanHarris,
+    /// Kaiser window with the shape parameter `beta`. See [`kaiser_beta`] for choosing `beta` from
+    /// the desired stopband attenuation.
+    Kaiser { beta: f32 },
+    /// Tukey (tapered cosine) window. `alpha` is the fraction of the window inside the cosine
+    /// tapers: 0.0 gives the rectangular window and 1.0 gives the Hann window.
+    Tukey { alpha: f32 },
+    /// Flat-top window, mainly for amplitude measurement.
+    FlatTop,
+    /// Gaussian window with the standard deviation `sigma` in samples.
+    Gaussian { sigma: f32 },
+}
+
+/// The error returned when parsing a window name fails.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseWindowError(String);
+
+impl Window {
+    /// Return the coefficients of the window.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of points in the window.
+    /// * `sym` - Whether the window is symmetric. If not symmetric, the window will be periodic.
+    pub fn coefficients(&self, n: usize, sym: bool) -> Vec<f32> {
+        match *self {
+            Window::Rectangular => rectangular(n),
+            Window::Hamming => hamming(n, sym),
+            Window::Hann => hann(n, sym),
+            Window::Blackman => blackman(n, sym),
+            Window::BlackmanHarris => blackman_harris(n, sym),
+            Window::Kaiser { beta } => kaiser(n, beta, sym),
+            Window::Tukey { alpha } => tukey(n, alpha, sym),
+            Window::FlatTop => flat_top(n, sym),
+            Window::Gaussian { sigma } => gaussian(n, sigma, sym),
+        }
+    }
+}
+
+impl FromStr for Window {
+    type Err = ParseWindowError;
+
+    /// Parse the name of a window without parameters, e.g. "hamming" or "blackmanharris". The
+    /// parameterized windows (Kaiser, Tukey and Gaussian) cannot be parsed.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "rectangular" | "boxcar" => Ok(Window::Rectangular),
+            "hamming" => Ok(Window::Hamming),
+            "hann" => Ok(Window::Hann),
+            "blackman" => Ok(Window::Blackman),
+            "blackmanharris" => Ok(Window::BlackmanHarris),
+            "flattop" => Ok(Window::FlatTop),
+            _ => Err(ParseWindowError(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for ParseWindowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid window function: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseWindowError {}
+
+/// Return the coefficients of a rectangular window.
+pub fn rectangular(n: usize) -> Vec<f32> {
+    vec![1.0; n]
+}
 
 /// Return the coefficients of a Hamming window.
 ///
@@ -7,30 +95,212 @@ use std::f32::consts::PI;
 /// * `n` - The number of points in the window.
 /// * `sym` - Whether the window is symmetric. If not symmetric, the window will be periodic.
 pub fn hamming(n: usize, sym: bool) -> Vec<f32> {
-    let a0 = 0.54;
-    let a1 = 0.46;
-    let two_pi = 2.0 * PI;
-    let denom: f32 = if sym {
-        n as f32 - 1.0
-    } else {
-        n as f32
-    };
-    (0..n)
-        .map(|i| a0 - a1 * (two_pi * i as f32 / denom).cos())
-        .collect()
+    general_cosine(n, &[0.54, 0.46], sym)
 }
 
 /// Return the coefficients of a Hann window.
 pub fn hann(n: usize, sym: bool) -> Vec<f32> {
-    let a0 = 0.5;
-    let a1 = 0.5;
-    let two_pi = 2.0 * PI;
-    let denom: f32 = if sym {
-        n as f32 - 1.0
+    general_cosine(n, &[0.5, 0.5], sym)
+}
+
+/// Return the coefficients of a Blackman window.
+pub fn blackman(n: usize, sym: bool) -> Vec<f32> {
+    general_cosine(n, &[0.42, 0.5, 0.08], sym)
+}
+
+/// Return the coefficients of a 4-term Blackman-Harris window.
+pub fn blackman_harris(n: usize, sym: bool) -> Vec<f32> {
+    general_cosine(n, &[0.35875, 0.48829, 0.14128, 0.01168], sym)
+}
+
+/// Return the coefficients of a flat-top window.
+pub fn flat_top(n: usize, sym: bool) -> Vec<f32> {
+    general_cosine(
+        n,
+        &[0.21557895, 0.41663158, 0.27726316, 0.08357895, 0.006947368],
+        sym,
+    )
+}
+
+/// Return the coefficients of a Kaiser window with the shape parameter `beta`.
+pub fn kaiser(n: usize, beta: f32, sym: bool) -> Vec<f32> {
+    let beta = beta as f64;
+    let denom = bessel_i0(beta);
+    symmetric_or_periodic(n, sym, |i, len| {
+        let x = 2.0 * i as f64 / (len - 1) as f64 - 1.0;
+        (bessel_i0(beta * (1.0 - x * x).max(0.0).sqrt()) / denom) as f32
+    })
+}
+
+/// Return the coefficients of a Tukey (tapered cosine) window. `alpha` is the fraction of the
+/// window inside the cosine tapers, and is clamped to `[0, 1]`.
+pub fn tukey(n: usize, alpha: f32, sym: bool) -> Vec<f32> {
+    let alpha = alpha.clamp(0.0, 1.0);
+    if alpha == 0.0 {
+        return rectangular(n);
+    }
+    symmetric_or_periodic(n, sym, |i, len| {
+        // Position in [0, 1], folded so that both tapers are handled the same way
+        let x = i as f32 / (len - 1) as f32;
+        let x = x.min(1.0 - x);
+        if x < alpha / 2.0 {
+            0.5 * (1.0 + (PI * (2.0 * x / alpha - 1.0)).cos())
+        } else {
+            1.0
+        }
+    })
+}
+
+/// Return the coefficients of a Gaussian window with the standard deviation `sigma` in samples.
+pub fn gaussian(n: usize, sigma: f32, sym: bool) -> Vec<f32> {
+    assert!(sigma > 0.0, "The standard deviation must be positive");
+    symmetric_or_periodic(n, sym, |i, len| {
+        let x = i as f32 - (len - 1) as f32 / 2.0;
+        (-0.5 * (x / sigma).powi(2)).exp()
+    })
+}
+
+/// The Kaiser window shape parameter `beta` that gives the desired stopband attenuation for a
+/// windowed-sinc filter, using Kaiser's empirical formula.
+///
+/// # Arguments
+///
+/// * `attenuation_db` - The desired stopband attenuation in dB (positive).
+pub fn kaiser_beta(attenuation_db: f32) -> f32 {
+    if attenuation_db > 50.0 {
+        0.1102 * (attenuation_db - 8.7)
+    } else if attenuation_db > 21.0 {
+        let a = attenuation_db - 21.0;
+        0.5842 * a.powf(0.4) + 0.07886 * a
     } else {
-        n as f32
-    };
-    (0..n)
-        .map(|i| a0 - a1 * (two_pi * i as f32 / denom).cos())
-        .collect()
+        0.0
+    }
+}
+
+/// Sum-of-cosines window, `w[i] = a0 - a1 cos(2 pi i / N) + a2 cos(4 pi i / N) - ...`.
+fn general_cosine(n: usize, a: &[f32], sym: bool) -> Vec<f32> {
+    symmetric_or_periodic(n, sym, |i, len| {
+        let phase = 2.0 * PI * i as f32 / (len - 1) as f32;
+        a.iter()
+            .enumerate()
+            .map(|(k, &ak)| {
+                let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                sign * ak * (phase * k as f32).cos()
+            })
+            .sum()
+    })
+}
+
+/// Evaluate a symmetric window of length `len` with `f(i, len)`. A periodic window of length `n`
+/// is the first `n` points of the symmetric window of length `n + 1`.
+fn symmetric_or_periodic(n: usize, sym: bool, f: impl Fn(usize, usize) -> f32) -> Vec<f32> {
+    if n <= 1 {
+        return vec![1.0; n];
+    }
+    let len = if sym { n } else { n + 1 };
+    (0..n).map(|i| f(i, len)).collect()
+}
+
+/// The modified Bessel function of the first kind of order zero, by its power series.
+fn bessel_i0(x: f64) -> f64 {
+    let half_x_sqr = (x / 2.0).powi(2);
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    for k in 1..500 {
+        term *= half_x_sqr / (k * k) as f64;
+        sum += term;
+        if term < sum * 1e-17 {
+            break;
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use crate::assert_all_close;
+
+    #[test]
+    fn hamming_periodic() {
+        // The periodic window is the symmetric window with one more point, truncated
+        let periodic = hamming(8, false);
+        let symmetric = hamming(9, true);
+        assert_all_close!(periodic, symmetric[..8]);
+    }
+
+    #[test]
+    fn blackman_values() {
+        assert_all_close!(blackman(5, true), [0.0, 0.34, 1.0, 0.34, 0.0]);
+    }
+
+    #[test]
+    fn kaiser_values() {
+        let expected = [0.03671089, 0.5528518, 1.0, 0.5528518, 0.03671089];
+        assert_all_close!(kaiser(5, 5.0, true), expected);
+        // Zero beta gives the rectangular window
+        assert_all_close!(kaiser(5, 0.0, true), [1.0; 5]);
+    }
+
+    #[test]
+    fn tukey_limits() {
+        assert_all_close!(tukey(9, 0.0, true), rectangular(9));
+        assert_all_close!(tukey(9, 1.0, true), hann(9, true));
+
+        let window = tukey(11, 0.5, true);
+        assert_all_close!(window[3..8], [1.0; 5]);
+        assert_abs_diff_eq!(window[0], 0.0);
+    }
+
+    #[test]
+    fn gaussian_values() {
+        let window = gaussian(5, 1.0, true);
+        let edge = (-2.0f32).exp();
+        let inner = (-0.5f32).exp();
+        assert_all_close!(window, [edge, inner, 1.0, inner, edge]);
+    }
+
+    #[test]
+    fn symmetry() {
+        let windows = [
+            Window::Rectangular,
+            Window::Hamming,
+            Window::Hann,
+            Window::Blackman,
+            Window::BlackmanHarris,
+            Window::Kaiser { beta: 8.6 },
+            Window::Tukey { alpha: 0.3 },
+            Window::FlatTop,
+            Window::Gaussian { sigma: 3.0 },
+        ];
+        for window in windows {
+            let coeffs = window.coefficients(16, true);
+            for i in 0..8 {
+                assert_abs_diff_eq!(coeffs[i], coeffs[15 - i], epsilon = 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn short_windows() {
+        assert!(Window::Hann.coefficients(0, true).is_empty());
+        assert_all_close!(Window::Hann.coefficients(1, true), [1.0]);
+    }
+
+    #[test]
+    fn beta_from_attenuation() {
+        assert_abs_diff_eq!(kaiser_beta(60.0), 5.65326, epsilon = 1e-4);
+        assert_abs_diff_eq!(kaiser_beta(30.0), 2.11662, epsilon = 1e-4);
+        assert_eq!(kaiser_beta(20.0), 0.0);
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("hamming".parse(), Ok(Window::Hamming));
+        assert_eq!("Hann".parse(), Ok(Window::Hann));
+        assert_eq!("blackmanharris".parse(), Ok(Window::BlackmanHarris));
+        assert!("kaiser".parse::<Window>().is_err());
+        assert!("triangle".parse::<Window>().is_err());
+    }
 }
@@ -0,0 +1,298 @@
+//! General-purpose IIR (Infinite Impulse Response) filters.
+//!
+//! [`IirFilter`] runs a single high-order difference equation in direct form I. It is convenient
+//! for low orders, but high-order designs are sensitive to coefficient quantization and should be
+//! run as a cascade of second-order sections with [`SosFilter`] instead.
+
+use crate::filter::Filter;
+use crate::filter::design::{IirCoeffs, SecondOrderSection, SosCoeffs};
+
+/// General-purpose IIR filter in direct form I.
+pub struct IirFilter {
+    coeffs: IirCoeffs,
+    /// FIFO buffer for storing the input samples. The length will be restricted to powers of 2.
+    input_buffer: Vec<f32>,
+    /// FIFO buffer for storing the output samples. The length will be restricted to powers of 2.
+    output_buffer: Vec<f32>,
+    /// Index of the next sample to be written to the buffers.
+    buffer_index: usize,
+}
+
+/// IIR filter implemented as a cascade of second-order sections (biquads), each of which is in
+/// transposed direct form II.
+pub struct SosFilter {
+    coeffs: SosCoeffs,
+    /// The two state variables of each section.
+    states: Vec<[f32; 2]>,
+}
+
+impl Filter for IirFilter {
+    fn process_inplace(&mut self, buffer: &mut [f32]) {
+        let b = &self.coeffs.b;
+        let a = &self.coeffs.a;
+        let buffer_len = self.input_buffer.len();
+        let buffer_mask = buffer_len - 1; // For wrapping around the buffer index
+
+        buffer.iter_mut().for_each(|sample| {
+            // Push the new sample into the input buffer
+            self.input_buffer[self.buffer_index] = *sample;
+
+            // Feedforward part: b[i] * x[n - i]
+            let mut y: f32 = b.iter()
+                .enumerate()
+                .map(|(i, &coeff)| {
+                    let idx = (self.buffer_index + (buffer_len - i)) & buffer_mask;
+                    coeff * self.input_buffer[idx]
+                })
+                .sum();
+
+            // Feedback part: a[i] * y[n - i], a[0] is always 1.0
+            y -= a.iter()
+                .enumerate()
+                .skip(1)
+                .map(|(i, &coeff)| {
+                    let idx = (self.buffer_index + (buffer_len - i)) & buffer_mask;
+                    coeff * self.output_buffer[idx]
+                })
+                .sum::<f32>();
+
+            self.output_buffer[self.buffer_index] = y;
+            *sample = y;
+            self.buffer_index = (self.buffer_index + 1) & buffer_mask;
+        });
+    }
+
+    fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        let mut output = input.to_vec();
+        self.process_inplace(&mut output);
+        output
+    }
+
+    fn reset(&mut self) {
+        self.input_buffer.fill(0.0);
+        self.output_buffer.fill(0.0);
+        self.buffer_index = 0;
+    }
+}
+
+impl IirFilter {
+    pub fn new(coeffs: IirCoeffs) -> Self {
+        let size = coeffs.b.len().max(coeffs.a.len()).next_power_of_two();
+        Self {
+            coeffs,
+            input_buffer: vec![0.0; size],
+            output_buffer: vec![0.0; size],
+            buffer_index: 0,
+        }
+    }
+}
+
+impl Filter for SosFilter {
+    fn process_inplace(&mut self, buffer: &mut [f32]) {
+        for (section, state) in self.coeffs.sections.iter().zip(self.states.iter_mut()) {
+            let [mut s1, mut s2] = *state;
+            for sample in buffer.iter_mut() {
+                let x = *sample;
+                let y = section.b0 * x + s1;
+                s1 = section.b1 * x - section.a1 * y + s2;
+                s2 = section.b2 * x - section.a2 * y;
+                *sample = y;
+            }
+            *state = [s1, s2];
+        }
+    }
+
+    fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        let mut output = input.to_vec();
+        self.process_inplace(&mut output);
+        output
+    }
+
+    fn reset(&mut self) {
+        self.states.fill([0.0; 2]);
+    }
+}
+
+impl SosFilter {
+    pub fn new(coeffs: SosCoeffs) -> Self {
+        let num_sections = coeffs.sections.len();
+        Self {
+            coeffs,
+            states: vec![[0.0; 2]; num_sections],
+        }
+    }
+
+    /// Process a single sample.
+    pub fn process_sample(&mut self, x: f32) -> f32 {
+        let mut y = x;
+        for (section, state) in self.coeffs.sections.iter().zip(self.states.iter_mut()) {
+            let x = y;
+            y = section.b0 * x + state[0];
+            state[0] = section.b1 * x - section.a1 * y + state[1];
+            state[1] = section.b2 * x - section.a2 * y;
+        }
+        y
+    }
+
+    /// Replace the coefficients while keeping the internal states, which allows the filter to be
+    /// tuned while running. The number of sections must stay the same.
+    ///
+    /// # Panics
+    ///
+    /// * If the number of sections differs from the current one.
+    pub fn set_coeffs(&mut self, coeffs: SosCoeffs) {
+        assert_eq!(
+            coeffs.sections.len(),
+            self.states.len(),
+            "The number of sections must not change"
+        );
+        self.coeffs = coeffs;
+    }
+
+    /// Replace the coefficients of a single section while keeping the internal states. Unlike
+    /// [`set_coeffs`](Self::set_coeffs), this does not allocate, so it can be called on the audio
+    /// thread.
+    ///
+    /// # Panics
+    ///
+    /// * If `index` is not less than the number of sections.
+    pub fn set_section(&mut self, index: usize, section: SecondOrderSection) {
+        self.coeffs.sections[index] = section;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert_all_close;
+    use crate::utilities::testing::impulse;
+
+    mod iir_filter {
+        use super::*;
+
+        #[test]
+        fn buffer_length() {
+            let coeffs = IirCoeffs::new(vec![1.0; 3], vec![1.0; 5]);
+            let filter = IirFilter::new(coeffs);
+            assert_eq!(filter.input_buffer.len(), 8);
+            assert_eq!(filter.output_buffer.len(), 8);
+        }
+
+        #[test]
+        fn normalization() {
+            let coeffs = IirCoeffs::new(vec![2.0, 1.0], vec![2.0, -1.0]);
+            assert_all_close!(coeffs.b, [1.0, 0.5]);
+            assert_all_close!(coeffs.a, [1.0, -0.5]);
+        }
+
+        #[test]
+        fn fir_only() {
+            // Without feedback, the filter should behave like an FIR filter
+            let coeffs = IirCoeffs::new(vec![1.0, 2.0, 3.0], vec![1.0]);
+            let mut filter = IirFilter::new(coeffs);
+            let output = filter.process(&impulse(5));
+            assert_all_close!(output, [1.0, 2.0, 3.0, 0.0, 0.0]);
+        }
+
+        #[test]
+        fn one_pole() {
+            // y[n] = x[n] + 0.5 * y[n - 1]
+            let coeffs = IirCoeffs::new(vec![1.0], vec![1.0, -0.5]);
+            let mut filter = IirFilter::new(coeffs);
+            let output = filter.process(&impulse(5));
+            assert_all_close!(output, [1.0, 0.5, 0.25, 0.125, 0.0625]);
+        }
+
+        #[test]
+        fn process_inplace() {
+            let coeffs = IirCoeffs::new(vec![1.0, 1.0], vec![1.0, -0.5, 0.25]);
+            let mut filter = IirFilter::new(coeffs);
+            let mut buffer = impulse(5);
+            filter.process_inplace(&mut buffer);
+            // y[n] = x[n] + x[n - 1] + 0.5 y[n - 1] - 0.25 y[n - 2]
+            assert_all_close!(buffer, [1.0, 1.5, 0.5, -0.125, -0.1875]);
+        }
+
+        #[test]
+        fn reset() {
+            let coeffs = IirCoeffs::new(vec![1.0], vec![1.0, -0.5]);
+            let mut filter = IirFilter::new(coeffs);
+            let _ = filter.process(&[1.0, 0.0]);
+
+            filter.reset();
+            let output = filter.process(&[0.0, 0.0, 0.0]);
+            assert_all_close!(output, [0.0, 0.0, 0.0]);
+        }
+    }
+
+    mod sos_filter {
+        use super::*;
+
+        #[test]
+        fn matches_direct_form() {
+            // Cascade of two sections and the equivalent direct form (the product of the two)
+            let s1 = SecondOrderSection::new(1.0, 0.5, 0.0, 1.0, -0.3, 0.0);
+            let s2 = SecondOrderSection::new(1.0, -0.2, 0.1, 1.0, 0.4, 0.2);
+            let mut sos = SosFilter::new(SosCoeffs::new(vec![s1, s2]));
+            let mut iir = IirFilter::new(IirCoeffs::new(
+                vec![1.0, 0.3, 0.0, 0.05],
+                vec![1.0, 0.1, 0.08, -0.06],
+            ));
+
+            let input: Vec<f32> = (0..32).map(|n| ((n * 7) % 5) as f32 - 2.0).collect();
+            let sos_output = sos.process(&input);
+            let iir_output = iir.process(&input);
+            assert_all_close!(sos_output, iir_output, 1e-5);
+        }
+
+        #[test]
+        fn process_sample() {
+            let section = SecondOrderSection::new(0.5, 0.2, 0.1, 1.0, -0.5, 0.25);
+            let mut block_filter = SosFilter::new(section.clone().into());
+            let mut sample_filter = SosFilter::new(section.into());
+
+            let input: Vec<f32> = (0..16).map(|n| (n as f32 * 0.3).sin()).collect();
+            let expected = block_filter.process(&input);
+            let output: Vec<f32> = input.iter().map(|&x| sample_filter.process_sample(x)).collect();
+            assert_all_close!(output, expected);
+        }
+
+        #[test]
+        fn normalization() {
+            let section = SecondOrderSection::new(2.0, 4.0, 2.0, 2.0, -1.0, 0.5);
+            assert_all_close!(section.b(), [1.0, 2.0, 1.0]);
+            assert_all_close!(section.a(), [1.0, -0.5, 0.25]);
+        }
+
+        #[test]
+        fn reset() {
+            let section = SecondOrderSection::new(1.0, 0.0, 0.0, 1.0, -0.5, 0.0);
+            let mut filter = SosFilter::new(section.into());
+            let _ = filter.process(&[1.0, 0.0]);
+
+            filter.reset();
+            let output = filter.process(&[0.0, 0.0, 0.0]);
+            assert_all_close!(output, [0.0, 0.0, 0.0]);
+        }
+
+        #[test]
+        #[should_panic]
+        fn set_coeffs_section_count() {
+            let section = SecondOrderSection::new(1.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+            let mut filter = SosFilter::new(section.clone().into());
+            filter.set_coeffs(SosCoeffs::new(vec![section.clone(), section]));
+        }
+
+        #[test]
+        fn set_section() {
+            let first = SecondOrderSection::new(1.0, 0.0, 0.0, 1.0, -0.5, 0.0);
+            let second = SecondOrderSection::new(1.0, 1.0, 0.0, 1.0, 0.0, 0.0);
+            let mut filter = SosFilter::new(SosCoeffs::new(vec![first.clone(), first.clone()]));
+            filter.set_section(1, second.clone());
+
+            let output = filter.process(&impulse(4));
+            let expected = SosFilter::new(SosCoeffs::new(vec![first, second])).process(&impulse(4));
+            assert_all_close!(output, expected);
+        }
+    }
+}
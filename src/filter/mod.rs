@@ -2,6 +2,11 @@
 //!
 //! General filters:
 //! - FIR (Finite Impulse Response) filters through [`FirFilter`]
+//! - IIR (Infinite Impulse Response) filters in direct form through [`IirFilter`]
+//! - IIR filters as cascaded second-order sections through [`SosFilter`]
+//! - Long FIR filters with partitioned FFT convolution through [`PartitionedConvolver`]
+//! - Comb and Schroeder allpass filters through [`CombFilter`] and [`SchroederAllpass`]
+//! - State variable filters with modulatable cutoff through [`StateVariableFilter`]
 //!
 //! Delay filters are filters of which the only purpose is to introduce a delay to the signal.
 //! They implement the [`DelayFilter`] trait:
@@ -12,10 +17,18 @@
 //! for processing audio samples.
 
 pub mod fir;
+pub mod convolution;
+pub mod comb;
+pub mod iir;
+pub mod svf;
 pub mod delay;
-mod design;
+pub mod design;
 
 pub use fir::FirFilter;
+pub use convolution::PartitionedConvolver;
+pub use comb::{AllpassType, CombFilter, CombType, SchroederAllpass};
+pub use iir::{IirFilter, SosFilter};
+pub use svf::{StateVariableFilter, SvfMode};
 pub use delay::{
     DelayFilter,
     LinearInterpDelay,
@@ -0,0 +1,204 @@
+//! State variable filter, whose cutoff can be modulated at audio rate.
+
+use std::f32::consts::PI;
+
+use crate::filter::Filter;
+
+/// The output of a [`StateVariableFilter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SvfMode {
+    #[default]
+    Lowpass,
+    /// Bandpass with 0 dB peak gain.
+    Bandpass,
+    Highpass,
+    Notch,
+}
+
+/// Second-order state variable filter in the topology-preserving form of Zavalishin and Simper.
+///
+/// The responses are the same as the ones of the biquads designed by
+/// [`biquad`](crate::filter::design::biquad) with [`Width::Q`](crate::filter::design::biquad::Width::Q),
+/// but the cutoff and the Q can be changed every sample without zipper noise or instability, since
+/// the states are the voltages of the two integrators instead of past samples.
+pub struct StateVariableFilter {
+    mode: SvfMode,
+    sample_rate: f32,
+    cutoff: f32,
+    q: f32,
+
+    // Coefficients
+    /// The prewarped gain of the integrators.
+    g: f32,
+    /// The damping `1 / Q`.
+    k: f32,
+    a1: f32,
+    a2: f32,
+    a3: f32,
+
+    // States of the integrators
+    ic1eq: f32,
+    ic2eq: f32,
+}
+
+impl Filter for StateVariableFilter {
+    fn process_inplace(&mut self, buffer: &mut [f32]) {
+        for sample in buffer.iter_mut() {
+            *sample = self.process_sample(*sample);
+        }
+    }
+
+    fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        let mut output = input.to_vec();
+        self.process_inplace(&mut output);
+        output
+    }
+
+    fn reset(&mut self) {
+        self.ic1eq = 0.0;
+        self.ic2eq = 0.0;
+    }
+}
+
+impl StateVariableFilter {
+    /// Create a state variable filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - The filter output.
+    /// * `sample_rate` - The sample rate in Hz.
+    /// * `cutoff` - The cutoff (or center) frequency in Hz.
+    /// * `q` - The quality factor. `1 / sqrt(2)` gives the Butterworth lowpass and highpass.
+    ///
+    /// # Panics
+    ///
+    /// * If `sample_rate` is not positive.
+    /// * If `cutoff` is not in the range `(0, sample_rate / 2)`.
+    /// * If `q` is not positive.
+    pub fn new(mode: SvfMode, sample_rate: f32, cutoff: f32, q: f32) -> Self {
+        assert!(sample_rate > 0.0, "The sample rate must be positive");
+        let mut filter = Self {
+            mode,
+            sample_rate,
+            cutoff,
+            q,
+            g: 0.0,
+            k: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+            a3: 0.0,
+            ic1eq: 0.0,
+            ic2eq: 0.0,
+        };
+        filter.set_cutoff(cutoff);
+        filter.set_q(q);
+        filter
+    }
+
+    pub fn mode(&self) -> SvfMode {
+        self.mode
+    }
+
+    pub fn cutoff(&self) -> f32 {
+        self.cutoff
+    }
+
+    /// Set the output. The states are kept, so the filter can be switched while running.
+    pub fn set_mode(&mut self, mode: SvfMode) {
+        self.mode = mode;
+    }
+
+    /// Set the cutoff frequency in Hz. The states are kept.
+    ///
+    /// # Panics
+    ///
+    /// * If `cutoff` is not in the range `(0, sample_rate / 2)`.
+    pub fn set_cutoff(&mut self, cutoff: f32) {
+        assert!(
+            cutoff > 0.0 && cutoff < self.sample_rate / 2.0,
+            "The cutoff must be in the range (0, sample_rate / 2)"
+        );
+        self.cutoff = cutoff;
+        self.g = (PI * cutoff / self.sample_rate).tan();
+        self.update_coeffs();
+    }
+
+    /// Set the quality factor. The states are kept.
+    ///
+    /// # Panics
+    ///
+    /// * If `q` is not positive.
+    pub fn set_q(&mut self, q: f32) {
+        assert!(q > 0.0, "The Q must be positive");
+        self.q = q;
+        self.k = 1.0 / q;
+        self.update_coeffs();
+    }
+
+    pub fn process_sample(&mut self, x: f32) -> f32 {
+        let v3 = x - self.ic2eq;
+        let v1 = self.a1 * self.ic1eq + self.a2 * v3;
+        let v2 = self.ic2eq + self.a2 * self.ic1eq + self.a3 * v3;
+        self.ic1eq = 2.0 * v1 - self.ic1eq;
+        self.ic2eq = 2.0 * v2 - self.ic2eq;
+
+        match self.mode {
+            SvfMode::Lowpass => v2,
+            SvfMode::Bandpass => self.k * v1,
+            SvfMode::Highpass => x - self.k * v1 - v2,
+            SvfMode::Notch => x - self.k * v1,
+        }
+    }
+
+    fn update_coeffs(&mut self) {
+        self.a1 = 1.0 / (1.0 + self.g * (self.g + self.k));
+        self.a2 = self.g * self.a1;
+        self.a3 = self.g * self.a2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert_all_close;
+    use crate::filter::SosFilter;
+    use crate::filter::design::biquad::{self, Width};
+    use crate::utilities::testing::impulse;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    #[test]
+    fn same_as_biquads() {
+        let (cutoff, q) = (1000.0, 2.0);
+        let width = Width::Q(q);
+        let biquads = [
+            (SvfMode::Lowpass, biquad::lowpass(SAMPLE_RATE, cutoff, width)),
+            (SvfMode::Bandpass, biquad::bandpass(SAMPLE_RATE, cutoff, width)),
+            (SvfMode::Highpass, biquad::highpass(SAMPLE_RATE, cutoff, width)),
+            (SvfMode::Notch, biquad::notch(SAMPLE_RATE, cutoff, width)),
+        ];
+        for (mode, section) in biquads {
+            let mut svf = StateVariableFilter::new(mode, SAMPLE_RATE, cutoff, q);
+            let output = svf.process(&impulse(256));
+            let expected = SosFilter::new(section.into()).process(&impulse(256));
+            assert_all_close!(output, expected, 1e-4);
+        }
+    }
+
+    #[test]
+    fn modulation() {
+        // Sweeping the cutoff every sample keeps the filter stable
+        let mut svf = StateVariableFilter::new(SvfMode::Bandpass, SAMPLE_RATE, 100.0, 10.0);
+        let mut peak = 0.0f32;
+        for n in 0..48000 {
+            let phase = n as f32 / 48000.0;
+            svf.set_cutoff(100.0 * 100.0f32.powf((PI * 20.0 * phase).sin().abs()));
+            let y = svf.process_sample((2.0 * PI * 440.0 * phase).sin());
+            peak = peak.max(y.abs());
+        }
+        assert!(peak.is_finite() && peak < 2.0);
+
+        svf.reset();
+        assert_eq!(svf.process_sample(0.0), 0.0);
+    }
+}
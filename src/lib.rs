@@ -6,4 +6,6 @@
 pub mod filter;
 pub mod effects;
 pub mod buffer_view;
+pub mod complex;
+pub mod fft;
 mod utilities;
@@ -52,3 +52,84 @@ pub fn sinc(x: f32) -> f32 {
     }
     (PI * x).sin() / (PI * x)
 }
+
+/// The number of zero crossings on each side of the interpolation kernel of [`resample`].
+const RESAMPLE_HALF_WIDTH: f64 = 16.0;
+
+/// Band-limited (offline) resampling with a Hann-windowed sinc interpolation kernel.
+///
+/// When downsampling, the cutoff of the kernel is lowered to the new Nyquist frequency to avoid
+/// aliasing. The amplitude of the signal is preserved.
+///
+/// # Arguments
+///
+/// * `input` - The input signal.
+/// * `ratio` - The ratio of the output sample rate to the input sample rate.
+pub(crate) fn resample(input: &[f32], ratio: f64) -> Vec<f32> {
+    assert!(ratio > 0.0, "The resampling ratio must be positive");
+    let output_len = (input.len() as f64 * ratio).ceil() as usize;
+    let cutoff = ratio.min(1.0);
+    let half_width = RESAMPLE_HALF_WIDTH / cutoff; // in input samples
+
+    (0..output_len)
+        .map(|m| {
+            let t = m as f64 / ratio;
+            let first = (t - half_width).ceil().max(0.0) as usize;
+            let last = ((t + half_width).floor() as usize).min(input.len().saturating_sub(1));
+            (first..=last)
+                .map(|k| {
+                    let x = t - k as f64;
+                    let window = 0.5 * (1.0 + (std::f64::consts::PI * x / half_width).cos());
+                    input[k] as f64 * cutoff * sinc64(cutoff * x) * window
+                })
+                .sum::<f64>() as f32
+        })
+        .collect()
+}
+
+/// The normalized sinc function in double precision.
+fn sinc64(x: f64) -> f64 {
+    if x.abs() < EPSILON {
+        return 1.0;
+    }
+    (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x)
+}
+
+/// Test signals and measurements shared by the tests of the effects.
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::f32::consts::PI;
+
+    /// A sine of `len` samples.
+    pub(crate) fn sine(freq: f32, amplitude: f32, sample_rate: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|n| amplitude * (2.0 * PI * freq * n as f32 / sample_rate).sin())
+            .collect()
+    }
+
+    /// A unit impulse of `len` samples.
+    pub(crate) fn impulse(len: usize) -> Vec<f32> {
+        let mut x = vec![0.0; len];
+        x[0] = 1.0;
+        x
+    }
+
+    /// The peak absolute value of a signal.
+    pub(crate) fn peak(x: &[f32]) -> f32 {
+        x.iter().fold(0.0f32, |peak, y| peak.max(y.abs()))
+    }
+
+    /// The energy of a signal, the sum of its squared samples.
+    pub(crate) fn energy(x: &[f32]) -> f32 {
+        x.iter().map(|y| y * y).sum()
+    }
+
+    /// The magnitude of the DFT of the signal at the frequency, normalized to 1.0 for a unit sine.
+    pub(crate) fn magnitude(x: &[f32], freq: f32, sample_rate: f32) -> f32 {
+        let (re, im) = x.iter().enumerate().fold((0.0, 0.0), |(re, im), (n, &x)| {
+            let w = 2.0 * PI * freq * n as f32 / sample_rate;
+            (re + x * w.cos(), im - x * w.sin())
+        });
+        2.0 * (re * re + im * im).sqrt() / x.len() as f32
+    }
+}